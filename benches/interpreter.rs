@@ -0,0 +1,96 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use lycoris::Interpreter;
+
+// ============================================================================
+// インタプリタのベンチマーク
+// ============================================================================
+//
+// cargo bench で実行する。--save-baseline NAME で保存した結果と --baseline NAME で比べると、
+// 遅くなったワークロードに "Performance has regressed" と表示される。
+
+fn loaded(source: &str) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval(source).unwrap();
+    interpreter
+}
+
+// 定義、メタデータ、スコープ、ローカル束縛、集合、コメントを混ぜたソース
+fn script(lines: usize) -> String {
+    let mut source = String::new();
+    for i in 0..lines {
+        source.push_str(&format!(
+            "[dup mul] 'sq{i}' ['Square a number' '( n -- n2 )'] def # line {i}\n\
+             {i} sq{i} [1 2/3 'text' {{1 2 3}}] 2 @mul *add [a b] -> [a b sub] swap over rot\n"
+        ));
+    }
+    source
+}
+
+// クオーテーションに包んで実行しないので、ほぼ字句解析だけの時間になる
+fn tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    group.sample_size(20);
+    for lines in [1_000, 10_000] {
+        let source = format!("[{}] drop", script(lines));
+        let mut interpreter = Interpreter::new();
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(lines), &source, |b, source| {
+            b.iter(|| interpreter.eval(black_box(source)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn reduce(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reduce");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(1_000_000));
+    group.bench_function("*add over 10^6 elements", |b| {
+        b.iter_batched(
+            || loaded("0 1000000 range"),
+            |mut interpreter| {
+                interpreter.eval("*add").unwrap();
+                interpreter
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+// 1000 行 x 100 列のベクタの各行を @mul で2倍にする
+fn map_nested(c: &mut Criterion) {
+    let mut group = c.benchmark_group("map");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(100_000));
+    group.bench_function("@mul over 1000x100 nested vectors", |b| {
+        b.iter_batched(
+            || loaded("[drop 0 100 range] 'row' def [2 @mul] 'double' def 0 1000 range @row"),
+            |mut interpreter| {
+                interpreter.eval("@double").unwrap();
+                interpreter
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+// 末尾呼び出しでない再帰。fib 20 で約2万回呼び出す
+fn recursion(c: &mut Criterion) {
+    let mut interpreter = loaded("[dup 2 lt [] [dup 1 sub fib swap 2 sub fib add] ifelse] 'fib' def");
+    assert_eq!(interpreter.eval("20 fib print").unwrap(), "6765");
+    interpreter.clear_output();
+    c.bench_function("recursive fib 20", |b| b.iter(|| interpreter.eval("20 fib drop").unwrap()));
+}
+
+// 多倍長整数の掛け算とローカル束縛
+fn factorial(c: &mut Criterion) {
+    let mut interpreter = loaded("[[n] -> [n 1 le [1] [n n 1 sub fact mul] ifelse]] 'fact' def");
+    c.bench_function("big integer factorial 1000", |b| b.iter(|| interpreter.eval("1000 fact drop").unwrap()));
+}
+
+criterion_group!(benches, tokenize, reduce, map_nested, recursion, factorial);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lycoris::{Interpreter, Limits};
+
+// ============================================================================
+// 制限つきの実行
+// ============================================================================
+//
+// どんな入力を実行してもパニックしないこと。無限ループや巨大な値は制限のエラーで止まる。
+// atomic モードで失敗した実行は実行前の状態に戻ること。同じインタプリタでもう一度実行して、
+// 1回目の定義や失敗が残っていても壊れないことも確かめる。
+//
+// 厳しい制限のほかに、ステップ数だけを決めた既定の制限でも実行する。既定の制限で
+// 大きな値を作ってもメモリを使い切らないことは libFuzzer の -rss_limit_mb で確かめる。
+
+fn limits() -> [Limits; 2] {
+    let tight = Limits {
+        max_steps: Some(10_000),
+        max_call_depth: Some(64),
+        max_stack: Some(1_000),
+        max_number_bits: Some(4_096),
+        max_length: Some(10_000),
+    };
+    [tight, Limits { max_steps: Some(10_000), ..Limits::default() }]
+}
+
+fuzz_target!(|source: &str| {
+    for (limits, atomic) in limits().into_iter().flat_map(|limits| [(limits.clone(), false), (limits, true)]) {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(limits);
+        interpreter.set_atomic(atomic);
+
+        if interpreter.eval(source).is_err() && atomic {
+            assert!(interpreter.stack().is_empty());
+            assert!(interpreter.words() == Interpreter::new().words());
+        }
+        let _ = interpreter.eval(source);
+        let _ = interpreter.run_tests();
+        for name in interpreter.words() {
+            let _ = interpreter.describe(&name);
+        }
+    }
+});
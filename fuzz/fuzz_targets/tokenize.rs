@@ -0,0 +1,38 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lycoris::{FormatOptions, Interpreter, Limits, Node, Value};
+
+// ============================================================================
+// 字句解析と表示の往復
+// ============================================================================
+//
+// どんな入力でもパニックせず、読めた値を表示して読み直すと同じ値に戻ること。
+// 構文木は空白とコメントを含めて元のソースに戻り、整形の結果も読み直せること。
+// 1e100000 のような巨大な数を読んで表示するだけで時間切れにならないよう、数の大きさは制限する。
+
+fn read(interpreter: &Interpreter, source: &str) -> Option<Vec<Value>> {
+    let nodes = interpreter.read(source).ok()?;
+    Some(nodes.into_iter().map(Node::into_value).collect())
+}
+
+fuzz_target!(|source: &str| {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_limits(Limits { max_number_bits: Some(4_096), ..Limits::default() });
+    let Some(values) = read(&interpreter, source) else {
+        return;
+    };
+
+    // プログラム全体を1つのベクタとして表示する
+    let program = Value::Vector(values);
+    let printed = program.to_display_string();
+    let reread = read(&interpreter, &printed).unwrap_or_else(|| panic!("printed program does not parse: {}", printed));
+    assert_eq!(reread, [program], "printed program reads differently: {}", printed);
+
+    let tree = interpreter.syntax_tree(source).expect("a readable source has a syntax tree");
+    assert_eq!(tree.to_string(), source);
+
+    if let Ok(formatted) = interpreter.format(source, &FormatOptions::default()) {
+        assert!(interpreter.read(&formatted).is_ok(), "formatted source does not parse: {}", formatted);
+    }
+});
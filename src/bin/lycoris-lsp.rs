@@ -0,0 +1,20 @@
+use std::io;
+use std::process::ExitCode;
+
+// ============================================================================
+// 言語サーバー
+// ============================================================================
+//
+//   lycoris-lsp        標準入出力で Language Server Protocol を話す
+
+fn main() -> ExitCode {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match lycoris::lsp::run(stdin.lock(), stdout.lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("lycoris-lsp: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
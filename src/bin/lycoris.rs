@@ -0,0 +1,580 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+use lycoris::{
+    DebugAction, DebugHandler, DebugReply, DebugSession, FormatOptions, Interpreter, Limits, ProfileClock,
+    Severity, SourceResolver, SystemClock, TestResult,
+};
+
+// ============================================================================
+// コマンドラインインターフェース
+// ============================================================================
+//
+//   lycoris            対話モード
+//   lycoris FILE       ファイルを実行して出力を表示
+//   lycoris check FILE 実行せずにスタック効果を検査する
+//   lycoris test FILE...  ファイルで登録したテストを実行する
+//   lycoris fmt [--check] [--width N] FILE...  ファイルの空白を整える
+//   lycoris lint [--json] FILE...  紛らわしい字句の区切りを指摘する
+//   lycoris trace [--json] FILE  実行したトークンごとのスタックの変化を表示する
+//   lycoris profile [--folded | --json] FILE  ワードごとの時間と呼び出し回数を表示する
+//   lycoris debug FILE  最初のトークンで止めて対話的にステップ実行する
+//   lycoris doc [--html | --test] [FILE...]  ファイルを読み込んでワードのリファレンスを表示するか、例を実行する
+//
+// コマンドの前に --max-steps N などで資源制限を指定できる（none で無制限）。
+
+const USAGE: &str = "usage: lycoris [LIMITS] [FILE] | lycoris check FILE | lycoris [LIMITS] test FILE... \
+                     | lycoris fmt [--check] [--width N] FILE... | lycoris lint [--json] FILE... \
+                     | lycoris [LIMITS] trace [--json] FILE | lycoris [LIMITS] profile [--folded | --json] FILE \
+                     | lycoris [LIMITS] debug FILE | lycoris [LIMITS] doc [--html | --test] [FILE...]\n\
+                     LIMITS: --max-steps N --max-call-depth N --max-stack N --max-number-bits N --max-length N \
+                     (N may be none)";
+
+// コードを実行するすべてのインタプリタに適用する資源制限
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (limits, args) = match parse_limits(&args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    LIMITS.get_or_init(|| limits);
+    match args {
+        [] => repl(),
+        [flag] if flag == "-h" || flag == "--help" => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        [command, file] if command == "check" => check_file(Path::new(file)),
+        [command, files @ ..] if command == "test" && !files.is_empty() => test_files(files),
+        [command, args @ ..] if command == "fmt" => format_files(args),
+        [command, args @ ..] if command == "lint" => lint_files(args),
+        [command, file] if command == "trace" => trace_file(Path::new(file), false),
+        [command, flag, file] if command == "trace" && flag == "--json" => trace_file(Path::new(file), true),
+        [command, file] if command == "profile" => profile_file(Path::new(file), ""),
+        [command, flag, file] if command == "profile" && matches!(flag.as_str(), "--folded" | "--json") => {
+            profile_file(Path::new(file), flag)
+        }
+        [command, file] if command == "debug" => debug_file(Path::new(file)),
+        [command, args @ ..] if command == "doc" => doc_files(args),
+        [file] => run_file(Path::new(file)),
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// 先頭の --max-* N を読んで、残りの引数と分ける
+fn parse_limits(args: &[String]) -> Result<(Limits, &[String]), String> {
+    let mut limits = Limits::default();
+    let mut rest = args;
+    while let [flag, value, tail @ ..] = rest {
+        if !flag.starts_with("--max-") {
+            break;
+        }
+        let limit = match value.as_str() {
+            "none" => None,
+            n => Some(n.parse::<u64>().map_err(|_| format!("{} needs a number or none", flag))?),
+        };
+        let size = limit.map(|n| n as usize);
+        match flag.as_str() {
+            "--max-steps" => limits.max_steps = limit,
+            "--max-call-depth" => limits.max_call_depth = size,
+            "--max-stack" => limits.max_stack = size,
+            "--max-number-bits" => limits.max_number_bits = limit,
+            "--max-length" => limits.max_length = size,
+            _ => return Err(format!("unknown limit {}", flag)),
+        }
+        rest = tail;
+    }
+    Ok((limits, rest))
+}
+
+// プロファイルで確保したバイト数を数えるためのアロケータ
+struct CountingAllocator;
+
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()) as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+struct CountingClock(SystemClock);
+
+impl ProfileClock for CountingClock {
+    fn now(&mut self) -> u64 {
+        self.0.now()
+    }
+
+    fn allocated(&mut self) -> u64 {
+        ALLOCATED.load(Ordering::Relaxed)
+    }
+}
+
+// include のパスは読み込み元ファイルのディレクトリからの相対パス
+// パスは include を書いたファイルのディレクトリから（トップレベルでは base から）読む。
+// キーは正規化した絶対パスなので、./lib.lyc と lib.lyc は同じファイルになる
+struct FsResolver {
+    base: PathBuf,
+}
+
+impl SourceResolver for FsResolver {
+    fn locate(&mut self, path: &str, from: Option<&str>) -> Result<String, String> {
+        let dir = from.and_then(|file| Path::new(file).parent()).unwrap_or(&self.base);
+        let file = dir.join(path).canonicalize().map_err(|err| err.to_string())?;
+        Ok(file.to_string_lossy().into_owned())
+    }
+
+    fn resolve(&mut self, key: &str) -> Result<String, String> {
+        std::fs::read_to_string(key).map_err(|err| err.to_string())
+    }
+}
+
+fn interpreter_for(base: &Path) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_resolver(FsResolver { base: base.to_path_buf() });
+    interpreter.set_limits(LIMITS.get().cloned().unwrap_or_default());
+    interpreter
+}
+
+fn read_source(path: &Path) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(source) => Some(source),
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+fn run_file(path: &Path) -> ExitCode {
+    let Some(source) = read_source(path) else {
+        return ExitCode::FAILURE;
+    };
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut interpreter = interpreter_for(base);
+    match interpreter.eval(&source) {
+        Ok(output) => {
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// 診断を FILE:LINE:COLUMN: severity: message の形で表示し、エラーがあれば失敗にする
+fn check_file(path: &Path) -> ExitCode {
+    let Some(source) = read_source(path) else {
+        return ExitCode::FAILURE;
+    };
+
+    let interpreter = Interpreter::new();
+    let diagnostics = match interpreter.check(&source) {
+        Ok(diagnostics) => diagnostics,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+    for diagnostic in &diagnostics {
+        let severity = match diagnostic.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        println!("{}:{}:{}: {}: {}", path.display(), diagnostic.line, diagnostic.column, severity, diagnostic.message);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// ファイルごとに新しいインタプリタで実行し、テストの結果をまとめて表示する
+fn test_files(files: &[String]) -> ExitCode {
+    let mut passed = 0;
+    let mut failed = 0;
+    for file in files {
+        let path = Path::new(file);
+        let Some(source) = read_source(path) else {
+            failed += 1;
+            continue;
+        };
+        let mut interpreter = interpreter_for(path.parent().unwrap_or(Path::new(".")));
+        let results = match interpreter.test_source(&source) {
+            Ok(results) => results,
+            Err(err) => {
+                println!("FAIL  {}: {}", path.display(), err);
+                failed += 1;
+                continue;
+            }
+        };
+        for result in results {
+            let location = match (result.line, result.column) {
+                (Some(line), Some(column)) => format!(" ({}:{}:{})", path.display(), line, column),
+                _ => format!(" ({})", path.display()),
+            };
+            if print_result(&result, &location) {
+                passed += 1;
+            } else {
+                failed += 1;
+            }
+        }
+    }
+    print_summary(passed, failed)
+}
+
+// 成功したら true
+fn print_result(result: &TestResult, location: &str) -> bool {
+    if result.passed {
+        println!("ok    {}", result.name);
+        return true;
+    }
+    println!("FAIL  {}{}", result.name, location);
+    if let Some(message) = &result.message {
+        println!("      {}", message);
+    }
+    for difference in &result.diff {
+        println!("      at {}: expected {}, got {}", difference.path, difference.expected, difference.actual);
+    }
+    false
+}
+
+fn print_summary(passed: usize, failed: usize) -> ExitCode {
+    println!("{} passed; {} failed", passed, failed);
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+// ファイルを順に同じインタプリタで実行してから、組み込みワードと公開の定義のリファレンスを表示する。
+// --test ではリファレンスの代わりにすべての例を実行する
+fn doc_files(args: &[String]) -> ExitCode {
+    let mut mode = "";
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--html" | "--test" if mode.is_empty() => mode = arg,
+            flag if flag.starts_with("--") => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+            file => files.push(Path::new(file)),
+        }
+    }
+
+    let mut interpreter = interpreter_for(Path::new("."));
+    for path in files {
+        let Some(source) = read_source(path) else {
+            return ExitCode::FAILURE;
+        };
+        interpreter.set_resolver(FsResolver { base: path.parent().unwrap_or(Path::new(".")).to_path_buf() });
+        if let Err(err) = interpreter.eval(&source) {
+            eprintln!("{}: {}", path.display(), err);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match mode {
+        "--test" => {
+            let results = interpreter.run_doctests();
+            let passed = results.iter().filter(|result| print_result(result, "")).count();
+            print_summary(passed, results.len() - passed)
+        }
+        "--html" => {
+            print!("{}", interpreter.reference().to_html());
+            ExitCode::SUCCESS
+        }
+        _ => {
+            print!("{}", interpreter.reference().to_markdown());
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+// ファイルを書き換える。--check では書き換えずに、整っていないファイルを表示して失敗にする
+fn format_files(args: &[String]) -> ExitCode {
+    let mut check = false;
+    let mut options = FormatOptions::default();
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--width" => match args.next().and_then(|width| width.parse().ok()) {
+                Some(width) => options.width = width,
+                None => {
+                    eprintln!("--width needs a number");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => files.push(Path::new(arg)),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+
+    let interpreter = Interpreter::new();
+    let mut failed = false;
+    for path in files {
+        let Some(source) = read_source(path) else {
+            failed = true;
+            continue;
+        };
+        let formatted = match interpreter.format(&source, &options) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", path.display());
+            failed = true;
+        } else if let Err(err) = std::fs::write(path, formatted) {
+            eprintln!("{}: {}", path.display(), err);
+            failed = true;
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// FILE:LINE:COLUMN: severity[code]: message の形か、--json ではファイルごとの指摘の配列で表示する。
+// エラーの指摘があれば失敗にする
+fn lint_files(args: &[String]) -> ExitCode {
+    let json = args.iter().any(|arg| arg == "--json");
+    let files: Vec<&Path> = args.iter().filter(|arg| *arg != "--json").map(Path::new).collect();
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+
+    let interpreter = Interpreter::new();
+    let mut failed = false;
+    let mut reports = Vec::new();
+    for path in files {
+        let Some(source) = read_source(path) else {
+            failed = true;
+            continue;
+        };
+        let lints = match interpreter.lint(&source) {
+            Ok(lints) => lints,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                failed = true;
+                continue;
+            }
+        };
+        failed |= lints.iter().any(|lint| lint.severity == Severity::Error);
+        if json {
+            reports.push(serde_json::json!({ "file": path.display().to_string(), "lints": lints }));
+            continue;
+        }
+        for lint in &lints {
+            let severity = match lint.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            println!("{}:{}:{}: {}[{}]: {}", path.display(), lint.line, lint.column, severity, lint.code, lint.message);
+        }
+    }
+    if json {
+        println!("{}", serde_json::Value::Array(reports));
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// エラーで止まってもそこまでのトレースを表示する
+fn trace_file(path: &Path, json: bool) -> ExitCode {
+    let Some(source) = read_source(path) else {
+        return ExitCode::FAILURE;
+    };
+
+    let mut interpreter = interpreter_for(path.parent().unwrap_or(Path::new(".")));
+    interpreter.set_tracing(true);
+    let result = interpreter.eval(&source);
+    if json {
+        println!("{}", interpreter.get_trace_json());
+    } else {
+        print!("{}", interpreter.get_trace_table());
+    }
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// format は "" なら表、--folded なら flamegraph 用、--json なら JSON
+fn profile_file(path: &Path, format: &str) -> ExitCode {
+    let Some(source) = read_source(path) else {
+        return ExitCode::FAILURE;
+    };
+
+    let mut interpreter = interpreter_for(path.parent().unwrap_or(Path::new(".")));
+    interpreter.start_profiling(CountingClock(SystemClock::new()));
+    let result = interpreter.eval(&source);
+    match format {
+        "--folded" => print!("{}", interpreter.get_profile_folded()),
+        "--json" => println!("{}", interpreter.get_profile_json()),
+        _ => print!("{}", interpreter.get_profile_report()),
+    }
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// 止まった位置を表示して標準入力からコマンドを読む
+struct StdinDebugger {
+    path: PathBuf,
+    source: String,
+    // 入力が終わったら以降は止まらずに実行する
+    finished: bool,
+}
+
+impl DebugHandler for StdinDebugger {
+    fn paused(&mut self, session: &mut DebugSession) -> DebugAction {
+        if self.finished {
+            return DebugAction::Continue;
+        }
+        let pause = session.pause();
+        let mut header = match (pause.line, pause.column) {
+            (Some(line), Some(column)) => format!("{}:{}:{}: {}", self.path.display(), line, column, pause.token()),
+            _ => format!("{}: {}", self.path.display(), pause.token()),
+        };
+        if let Some(id) = pause.breakpoint {
+            header.push_str(&format!("  (breakpoint {})", id));
+        }
+        println!("{}", header);
+        if let Some(text) = pause.line.and_then(|line| self.source.lines().nth(line - 1)) {
+            println!("    {}", text);
+        }
+        println!("stack: {}", pause.stack.join(" "));
+
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            if !matches!(stdin.lock().read_line(&mut line), Ok(n) if n > 0) {
+                println!();
+                self.finished = true;
+                return DebugAction::Continue;
+            }
+            match session.command(&line) {
+                DebugReply::Resume(action) => return action,
+                DebugReply::Output(text) if text.is_empty() => {}
+                DebugReply::Output(text) => println!("{}", text),
+            }
+        }
+    }
+}
+
+fn debug_file(path: &Path) -> ExitCode {
+    let Some(source) = read_source(path) else {
+        return ExitCode::FAILURE;
+    };
+
+    let mut interpreter = interpreter_for(path.parent().unwrap_or(Path::new(".")));
+    interpreter.set_debug_handler(StdinDebugger { path: path.to_path_buf(), source: source.clone(), finished: false });
+    interpreter.request_pause();
+    match interpreter.eval(&source) {
+        Ok(output) => {
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+            println!("{}", interpreter.get_stack_json());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn repl() -> ExitCode {
+    let mut interpreter = interpreter_for(Path::new("."));
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    loop {
+        print!("> ");
+        let _ = stdout.flush();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => return ExitCode::SUCCESS,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+
+        match interpreter.eval(&line) {
+            Ok(output) => {
+                if !output.is_empty() {
+                    println!("{}", output);
+                }
+                interpreter.clear_output();
+            }
+            Err(err) => println!("Error: {}", err),
+        }
+        println!("{}", interpreter.get_stack_json());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::module::StackEffect;
+
+// ============================================================================
+// 組み込みワードの一覧
+// ============================================================================
+//
+// 辞書への登録、help、UI向けのワード情報、リファレンスはすべてこの表から作る。
+// スタック効果の `..` は個数が実行時に決まることを表す。
+// 例は「ソース → 実行後のスタック」で、リファレンスに載せ、doctest として実行する。
+
+pub(crate) struct BuiltinInfo {
+    pub(crate) name: &'static str,
+    pub(crate) effect: &'static str,
+    pub(crate) doc: &'static str,
+    pub(crate) examples: &'static [&'static str],
+}
+
+impl BuiltinInfo {
+    const fn examples(self, examples: &'static [&'static str]) -> BuiltinInfo {
+        BuiltinInfo { examples, ..self }
+    }
+}
+
+const fn builtin(name: &'static str, effect: &'static str, doc: &'static str) -> BuiltinInfo {
+    BuiltinInfo { name, effect, doc, examples: &[] }
+}
+
+// リファレンスの節
+pub(crate) struct Section {
+    pub(crate) title: &'static str,
+    pub(crate) words: &'static [BuiltinInfo],
+}
+
+const fn section(title: &'static str, words: &'static [BuiltinInfo]) -> Section {
+    Section { title, words }
+}
+
+pub(crate) const SECTIONS: &[Section] = &[
+    // 算術
+    section("Arithmetic", &[
+        builtin("add", "( a b -- c )", "Add two numbers")
+            .examples(&["1 2 add → 3", "[1 2 3] 10 @add → [11 12 13]", "[1 2 3] *add → 6"]),
+        builtin("sub", "( a b -- c )", "Subtract b from a").examples(&["5 3 sub → 2"]),
+        builtin("mul", "( a b -- c )", "Multiply two numbers").examples(&["1/2 4 mul → 2", "[1 2 3] *mul → 6"]),
+        builtin("div", "( a b -- c )", "Divide a by b exactly")
+            .examples(&["1 3 div → 1/3", "[1 0 div] [error-kind] try → 'division-by-zero'"]),
+        builtin("pow", "( a n -- c )", "Raise a to an integer power").examples(&["2 10 pow → 1024"]),
+        builtin("mod", "( a b -- c )", "Reserved; not implemented yet"),
+    ]),
+    // スタック操作
+    section("Stack Operations", &[
+        builtin("dup", "( a -- a a )", "Duplicate the top value").examples(&["3 dup → 3 3"]),
+        builtin("drop", "( a -- )", "Remove the top value").examples(&["1 2 drop → 1"]),
+        builtin("swap", "( a b -- b a )", "Exchange the top two values").examples(&["1 2 swap → 2 1"]),
+        builtin("over", "( a b -- a b a )", "Copy the second value to the top").examples(&["1 2 over → 1 2 1"]),
+        builtin("rot", "( a b c -- b c a )", "Rotate the top three values").examples(&["1 2 3 rot → 2 3 1"]),
+    ]),
+    // Vector操作
+    section("Vector Operations", &[
+        builtin("vec", "( x1 .. xn n -- v )", "Collect the top n values into a vector")
+            .examples(&["1 'x' 2 vec → [1 'x']"]),
+        builtin("unpack", "( v -- x1 .. xn )", "Push every element of a vector").examples(&["[1 2 3] unpack → 1 2 3"]),
+        builtin("nth", "( v i -- x )", "Element at an index; negative indexes count from the end")
+            .examples(&["[10 20 30] 1 nth → 20", "[10 20 30] -1 nth → 30"]),
+        builtin("slice", "( v from to -- v' )", "Elements from one index up to another")
+            .examples(&["[1 2 3 4] 1 3 slice → [2 3]", "'abc' 1 3 slice → 'bc'"]),
+        builtin("concat", "( a b -- c )", "Join two vectors or two strings")
+            .examples(&["[1 2] [3] concat → [1 2 3]", "'ab' 'c' concat → 'abc'"]),
+        builtin("length", "( v -- n )", "Number of elements or characters")
+            .examples(&["[1 2 3] length → 3", "'añb' length → 3"]),
+    ]),
+    // 実行制御
+    section("Execution Control", &[
+        builtin("run", "( quot -- .. )", "Execute a vector as a program").examples(&["[1 2 add] run → 3"]),
+        builtin("step", "( -- )", "Reserved; not implemented yet"),
+        builtin("quote", "( x -- v )", "Wrap a value in a vector").examples(&["3 quote → [3]"]),
+        builtin("->", "( x1 .. xn names body -- .. )", "Bind values to local names: [a b] -> [body]")
+            .examples(&["2 3 4 [a b c] -> [a b mul c add] → 10"]),
+        builtin("if", "( cond then -- .. )", "Run then when cond is truthy").examples(&["1 [2] if → 2", "0 [2] if →"]),
+        builtin("ifelse", "( cond then else -- .. )", "Run then or else depending on cond")
+            .examples(&["0 ['yes'] ['no'] ifelse → 'no'"]),
+        builtin("match", "( x clauses -- .. )", "Run the action of the first matching [pattern action] clause").examples(&[
+            "[5 6 7] [[[] ['empty']] [[h & t] [t]]] match → [6 7]",
+            "5 [[0 ['zero']] [_ ['other']]] match → 'other'",
+        ]),
+    ]),
+    // 辞書
+    section("Dictionary", &[
+        builtin("def", "( body name -- )", "Define a word; [body] 'name' [meta] def also takes a docstring, a stack effect and examples")
+            .examples(&["[dup mul] 'square' def 3 square → 9"]),
+        builtin("defp", "( body name -- )", "Define a word private to the open module")
+            .examples(&["'m' module [1] 'one' defp [one 1 add] 'two' def end m.two → 2"]),
+        builtin("undef", "( name -- )", "Remove a word definition")
+            .examples(&["[1] 'one' def 'one' undef 'one' words-prefix → []"]),
+        builtin("words", "( -- names )", "Names of all builtin and user words").examples(&["words length 0 gt → true"]),
+        builtin("words-prefix", "( prefix -- names )", "Names of all words starting with a prefix")
+            .examples(&["'add' words-prefix → ['add']"]),
+        builtin("see", "( name -- )", "Print the definition of a word as source"),
+        builtin("help", "( name -- )", "Print the stack effect and docstring of a word"),
+    ]),
+    // モジュール
+    section("Modules", &[
+        builtin("module", "( name -- )", "Start a module; definitions until end go into its namespace")
+            .examples(&["'math' module [dup mul] 'sq' def end 3 math.sq → 9"]),
+        builtin("end", "( -- )", "Close the innermost module"),
+        builtin("use", "( name -- )", "Make the words of a namespace callable by their short names")
+            .examples(&["'math' module [dup mul] 'sq' def end 'math' use 3 sq → 9"]),
+        builtin("include", "( path -- .. )", "Run a source file once through the host resolver"),
+    ]),
+    // I/O
+    section("I/O", &[
+        builtin("print", "( x -- )", "Append a value to the output").examples(&["'hi' print →"]),
+        builtin("clear", "( -- )", "Clear the output"),
+    ]),
+    // 比較
+    section("Comparison", &[
+        builtin("eq", "( a b -- ? )", "Whether two values are equal").examples(&["1/2 2/4 eq → true"]),
+        builtin("ne", "( a b -- ? )", "Whether two values differ").examples(&["1 2 ne → true"]),
+        builtin("lt", "( a b -- ? )", "Whether a sorts before b").examples(&["'a' 'b' lt → true"]),
+        builtin("gt", "( a b -- ? )", "Whether a sorts after b").examples(&["1 2 gt → false"]),
+        builtin("le", "( a b -- ? )", "Whether a sorts before or equal to b").examples(&["2 2 le → true"]),
+        builtin("ge", "( a b -- ? )", "Whether a sorts after or equal to b").examples(&["nil 0 ge → false"]),
+        builtin("cmp", "( a b -- n )", "-1, 0 or 1 by the total order of values")
+            .examples(&["1 2 cmp → -1", "[3 1 2] [cmp] sort → [1 2 3]"]),
+    ]),
+    // 論理演算
+    section("Logic", &[
+        builtin("and", "( a b -- ? )", "Both operands are truthy")
+            .examples(&["true 0 and → false", "[true false] *and → false"]),
+        builtin("or", "( a b -- ? )", "Either operand is truthy").examples(&["0 'x' or → true", "[] *or → false"]),
+        builtin("not", "( a -- ? )", "The operand is falsy").examples(&["[] not → true"]),
+        builtin("xor", "( a b -- ? )", "Exactly one operand is truthy").examples(&["true false xor → true"]),
+        builtin("and-then", "( a quot -- ? )", "Run quot only when a is truthy")
+            .examples(&["0 [1 0 div] and-then → false"]),
+        builtin("or-else", "( a quot -- ? )", "Run quot only when a is falsy")
+            .examples(&["1 [1 0 div] or-else → true"]),
+        builtin("truthy", "( a -- ? )", "Convert a value to a bool")
+            .examples(&["nil truthy → false", "'0' truthy → true"]),
+    ]),
+    // 集合
+    section("Set Operations", &[
+        builtin("toset", "( v -- s )", "Convert a vector to a set").examples(&["[3 1 3] toset → {1 3}"]),
+        builtin("tovec", "( s -- v )", "Convert a set to a sorted vector").examples(&["{3 1 2} tovec → [1 2 3]"]),
+        builtin("union", "( a b -- s )", "Elements in either set").examples(&["{1 2} {2 3} union → {1 2 3}"]),
+        builtin("intersect", "( a b -- s )", "Elements in both sets").examples(&["{1 2} {2 3} intersect → {2}"]),
+        builtin("diff", "( a b -- s )", "Elements of a that are not in b").examples(&["{1 2} {2 3} diff → {1}"]),
+        builtin("subset?", "( a b -- ? )", "Every element of a is in b").examples(&["{1} {1 2} subset? → true"]),
+        builtin("member?", "( s x -- ? )", "Whether x is in the set").examples(&["{1 2} 2 member? → true"]),
+    ]),
+    // 文字列
+    section("String Operations", &[
+        builtin("split", "( text sep -- v )", "Split by a separator; '' splits into characters")
+            .examples(&["'a,b' ',' split → ['a' 'b']", "'ab' '' split → ['a' 'b']"]),
+        builtin("join", "( v sep -- text )", "Join elements with a separator")
+            .examples(&["['a' 'b'] '-' join → 'a-b'"]),
+        builtin("upper", "( text -- text' )", "Convert to upper case").examples(&["'abc' upper → 'ABC'"]),
+        builtin("lower", "( text -- text' )", "Convert to lower case").examples(&["'ABC' lower → 'abc'"]),
+        builtin("trim", "( text -- text' )", "Remove surrounding whitespace").examples(&["'  hi ' trim → 'hi'"]),
+        builtin("replace", "( text from to -- text' )", "Replace every occurrence")
+            .examples(&["'aXbX' 'X' '-' replace → 'a-b-'"]),
+        builtin("find", "( text needle -- i )", "Character index of the first occurrence, or nil")
+            .examples(&["'abc' 'c' find → 2", "'abc' 'x' find → nil"]),
+        builtin("startswith", "( text prefix -- ? )", "Whether text starts with prefix")
+            .examples(&["'lycoris' 'ly' startswith → true"]),
+        builtin("endswith", "( text suffix -- ? )", "Whether text ends with suffix")
+            .examples(&["'lycoris' 'is' endswith → true"]),
+        builtin("chars", "( text -- v )", "Split into characters").examples(&["'abc' chars → ['a' 'b' 'c']"]),
+        builtin("ord", "( c -- n )", "Code point of a single character").examples(&["'a' ord → 97"]),
+        builtin("chr", "( n -- c )", "Character for a code point").examples(&["97 chr → 'a'"]),
+        builtin("tostring", "( x -- text )", "Convert a value to a string").examples(&["1/2 tostring → '1/2'"]),
+        builtin("parse-number", "( text -- n )", "Parse a number literal")
+            .examples(&["'1.5' parse-number → 3/2", "'1e3' parse-number → 1000"]),
+    ]),
+    // Vectorアルゴリズム
+    section("Vector Algorithms", &[
+        builtin("range", "( from to -- v )", "Integers from from up to, not including, to")
+            .examples(&["1 4 range → [1 2 3]"]),
+        builtin("iota", "( n -- v )", "Integers from 0 up to n").examples(&["3 iota → [0 1 2]"]),
+        builtin("reverse", "( v -- v' )", "Reverse the order").examples(&["[1 2 3] reverse → [3 2 1]"]),
+        builtin("sort", "( v quot -- v' )", "Stable sort with a comparator quotation")
+            .examples(&[
+                "[3 1 2] [sub] sort → [1 2 3]",
+                "['bb' 'a'] [swap length swap length sub] sort → ['a' 'bb']",
+                "[[3 1] [2 0]] [sub] @sort → [[1 3] [0 2]]",
+            ]),
+        builtin("filter", "( v quot -- v' )", "Keep elements for which quot is truthy")
+            .examples(&["[1 2 3 4] [2 gt] filter → [3 4]", "[[1 2] [3 4]] [1 gt] @filter → [[2] [3 4]]"]),
+        builtin("zip", "( a b -- v )", "Pair up the elements of two vectors")
+            .examples(&["[1 2] ['a' 'b'] zip → [[1 'a'] [2 'b']]"]),
+        builtin("flatten", "( v -- v' )", "Splice nested vectors one level")
+            .examples(&["[1 [2 [3]]] flatten → [1 2 [3]]"]),
+        builtin("transpose", "( v -- v' )", "Swap rows and columns")
+            .examples(&["[[1 2] [3 4]] transpose → [[1 3] [2 4]]"]),
+        builtin("take", "( v n -- v' )", "The first n elements").examples(&["[1 2 3] 2 take → [1 2]"]),
+        builtin("drop-n", "( v n -- v' )", "All but the first n elements").examples(&["[1 2 3] 2 drop-n → [3]"]),
+        builtin("uniq", "( v -- v' )", "Remove duplicates, keeping first occurrences")
+            .examples(&["[1 2 1 2/2] uniq → [1 2]"]),
+        builtin("index-of", "( v x -- i )", "Index of the first equal element, or nil")
+            .examples(&["[1 2 3] 2 index-of → 1", "[1 2 3] 4 index-of → nil"]),
+        builtin("contains", "( v x -- ? )", "Whether the vector contains x").examples(&["[1 2 3] 4 contains → false"]),
+        builtin("first", "( v -- x )", "The first element").examples(&["[1 2 3] first → 1"]),
+        builtin("rest", "( v -- v' )", "All but the first element").examples(&["[1 2 3] rest → [2 3]"]),
+        builtin("scan", "( v quot -- v' )", "Running fold")
+            .examples(&["[1 2 3] [add] scan → [1 3 6]", "[[1 2] [3 4]] [add] @scan → [[1 3] [3 7]]"]),
+        builtin("group-by", "( v quot -- v' )", "Group by key into [key [elements]] pairs")
+            .examples(&["[1 2 3 4] [2 gt] group-by → [[false [1 2]] [true [3 4]]]"]),
+    ]),
+    // 例外処理
+    section("Error Handling", &[
+        builtin("throw", "( x -- )", "Raise a string or an error value")
+            .examples(&["['oops' throw] [error-kind] try → 'user'"]),
+        builtin("try", "( body handler -- .. )", "Run body; on error restore the stack and run handler with the error")
+            .examples(&["[1 0 div] [error-message] try → 'Division by zero'", "1 [2 'x' add] [drop] try → 1"]),
+        builtin("finally", "( body cleanup -- .. )", "Run body, then cleanup even if body fails")
+            .examples(&["[1] [2] finally → 1 2"]),
+        builtin("error", "( kind message -- e )", "Build an error value")
+            .examples(&["'parse' 'bad input' error error? → true"]),
+        builtin("error-kind", "( e -- kind )", "Kind of an error value")
+            .examples(&["'parse' 'bad input' error error-kind → 'parse'"]),
+        builtin("error-message", "( e -- message )", "Message of an error value")
+            .examples(&["'parse' 'bad input' error error-message → 'bad input'"]),
+    ]),
+    // 型判定
+    section("Type Tests", &[
+        builtin("rational?", "( x -- ? )", "Whether x is a number").examples(&["1/2 rational? → true"]),
+        builtin("string?", "( x -- ? )", "Whether x is a string").examples(&["'a' string? → true"]),
+        builtin("bool?", "( x -- ? )", "Whether x is a bool").examples(&["true bool? → true"]),
+        builtin("nil?", "( x -- ? )", "Whether x is nil").examples(&["nil nil? → true"]),
+        builtin("vector?", "( x -- ? )", "Whether x is a vector")
+            .examples(&["[1] vector? → true", "{1} vector? → false"]),
+        builtin("set?", "( x -- ? )", "Whether x is a set").examples(&["{1} set? → true"]),
+        builtin("word?", "( x -- ? )", "Whether x is a word").examples(&["[add] first word? → true"]),
+        builtin("error?", "( x -- ? )", "Whether x is an error value").examples(&["[1 0 div] [error?] try → true"]),
+    ]),
+    // テスト
+    section("Testing", &[
+        builtin("assert", "( x -- )", "Fail unless x is truthy").examples(&["1 1 eq assert →"]),
+        builtin("assert-eq", "( actual expected -- )", "Fail unless the two values are equal, reporting where they differ")
+            .examples(&["[1 2] [1 2] assert-eq →"]),
+        builtin("assert-stack", "( v -- )", "Fail unless the rest of the stack equals the vector, bottom first")
+            .examples(&["1 2 [1 2] assert-stack → 1 2"]),
+        builtin("test", "( body name -- )", "Register a named test; run_tests runs each one on a fresh stack")
+            .examples(&["[2 2 add 4 assert-eq] 'addition' test →"]),
+    ]),
+];
+
+pub(crate) fn builtins() -> impl Iterator<Item = &'static BuiltinInfo> {
+    SECTIONS.iter().flat_map(|section| section.words)
+}
+
+pub(crate) fn builtin_info(name: &str) -> Option<&'static BuiltinInfo> {
+    builtins().find(|info| info.name == name)
+}
+
+// 最後の入力がクオーテーションの組み込みワード（filter、sort、scan など）。
+// `@` ではスタックの一番上のベクタを、写像する対象ではなくこのクオーテーションとして渡す
+pub(crate) fn takes_quotation(name: &str) -> bool {
+    builtin_info(name)
+        .and_then(|info| StackEffect::parse(info.effect))
+        .is_some_and(|effect| effect.inputs.len() == 2 && effect.inputs[1] == "quot")
+}
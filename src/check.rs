@@ -0,0 +1,982 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::builtins::takes_quotation;
+use crate::module::StackEffect;
+use crate::syntax::{forms, line_col, Node, NodeKind, Span};
+use crate::{Interpreter, Scope, Value};
+
+// ============================================================================
+// スタック効果の静的検査
+// ============================================================================
+//
+// ソースを実行せずにスタックの形と値の種類を追い、実行すると必ず失敗する箇所
+// （型の不一致、スタック不足）と、分岐ごとにスタックの深さが変わる箇所を報告する。
+// クオーテーションの中身は run / if / ifelse などでリテラルのまま実行される場合だけ追う。
+// 実行時まで効果が決まらないワード（個数がリテラルでない vec など）に出会ったら、
+// 誤検出を避けるためにその列の残りは調べない。
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    // span の開始位置（1始まり）
+    pub line: usize,
+    pub column: usize,
+}
+
+// 値の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ty {
+    Any,
+    Number,
+    String,
+    Bool,
+    Nil,
+    Vector,
+    Set,
+    Word,
+    Error,
+}
+
+impl Ty {
+    fn of(value: &Value) -> Ty {
+        match value {
+            Value::Rational(_) => Ty::Number,
+            Value::String(_) => Ty::String,
+            Value::Bool(_) => Ty::Bool,
+            Value::Nil => Ty::Nil,
+            Value::Vector(_) => Ty::Vector,
+            Value::Word(_, _) => Ty::Word,
+            Value::Set(_) => Ty::Set,
+            Value::Error { .. } => Ty::Error,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Ty::Any => "x",
+            Ty::Number => "number",
+            Ty::String => "string",
+            Ty::Bool => "bool",
+            Ty::Nil => "nil",
+            Ty::Vector => "vector",
+            Ty::Set => "set",
+            Ty::Word => "word",
+            Ty::Error => "error",
+        }
+    }
+
+    // actual をこの種類として受け取れるか（不明な値は常に受け取る）
+    fn accepts(self, actual: Ty) -> bool {
+        self == Ty::Any || actual == Ty::Any || self == actual
+    }
+
+    fn join(self, other: Ty) -> Ty {
+        if self == other {
+            self
+        } else {
+            Ty::Any
+        }
+    }
+}
+
+// スタック上の値。リテラルなら構文も持ち、クオーテーションとして実行するときに使う
+#[derive(Debug, Clone)]
+struct Slot {
+    ty: Ty,
+    node: Option<Node>,
+    // 呼び出し元から取った入力なら (Sim の番号, 取った順番)。後で種類が分かったら入力に反映する
+    origin: Option<(usize, usize)>,
+}
+
+impl Slot {
+    fn of(ty: Ty) -> Slot {
+        Slot { ty, node: None, origin: None }
+    }
+
+    fn literal(node: &Node) -> Slot {
+        let ty = match &node.kind {
+            NodeKind::Literal(value) => Ty::of(value),
+            NodeKind::Word(_, _) => Ty::Word,
+            NodeKind::Vector(_) => Ty::Vector,
+            NodeKind::Set(_) => Ty::Set,
+        };
+        Slot { ty, node: Some(node.clone()), origin: None }
+    }
+
+    fn quotation(&self) -> Option<&[Node]> {
+        match self.node.as_ref().map(|node| &node.kind) {
+            Some(NodeKind::Vector(items)) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+// 効果の出力。Input は入力をそのまま返すこと（分岐をそろえたときの通過分）
+#[derive(Debug, Clone)]
+enum Output {
+    Value(Slot),
+    Input(usize),
+}
+
+// ( inputs -- outputs )。どちらも下から順
+#[derive(Debug, Clone)]
+struct Effect {
+    inputs: Vec<Ty>,
+    outputs: Vec<Output>,
+    // 戻らない（throw で終わる）
+    diverges: bool,
+}
+
+impl Effect {
+    fn new(inputs: &[Ty], outputs: &[Ty]) -> Effect {
+        Effect {
+            inputs: inputs.to_vec(),
+            outputs: outputs.iter().map(|&ty| Output::Value(Slot::of(ty))).collect(),
+            diverges: false,
+        }
+    }
+
+    // 宣言された効果。個数が実行時に決まるものは使えない
+    fn declared(effect: &StackEffect) -> Option<Effect> {
+        let variadic = effect.inputs.iter().chain(&effect.outputs).any(|name| name == "..");
+        (!variadic).then(|| Effect::new(&vec![Ty::Any; effect.inputs.len()], &vec![Ty::Any; effect.outputs.len()]))
+    }
+
+    fn net(&self) -> isize {
+        self.outputs.len() as isize - self.inputs.len() as isize
+    }
+
+    fn output_ty(&self, output: &Output) -> Ty {
+        match output {
+            Output::Value(slot) => slot.ty,
+            Output::Input(i) => self.inputs[*i],
+        }
+    }
+
+    // 入力を n 個に増やす（増やした分はそのまま出力に戻す）
+    fn widen(&self, n: usize) -> Effect {
+        let extra = n - self.inputs.len();
+        let mut inputs = vec![Ty::Any; extra];
+        inputs.extend(&self.inputs);
+        let mut outputs: Vec<Output> = (0..extra).map(Output::Input).collect();
+        outputs.extend(self.outputs.iter().map(|output| match output {
+            Output::Input(i) => Output::Input(i + extra),
+            value => value.clone(),
+        }));
+        Effect { inputs, outputs, diverges: self.diverges }
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec!["("];
+        parts.extend(self.inputs.iter().map(|ty| ty.name()));
+        parts.push("--");
+        parts.extend(self.outputs.iter().map(|output| self.output_ty(output).name()));
+        parts.push(")");
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+// 分岐のどれかが実行されるときの効果。深さの変化がそろわなければ None
+fn combine(effects: &[Effect]) -> Option<Effect> {
+    let width = effects.iter().map(|e| e.inputs.len()).max().unwrap_or(0);
+    let live: Vec<Effect> = effects.iter().filter(|e| !e.diverges).map(|e| e.widen(width)).collect();
+    let Some(first) = live.first() else {
+        return Some(Effect { inputs: vec![Ty::Any; width], outputs: Vec::new(), diverges: true });
+    };
+    if live.iter().any(|e| e.net() != first.net()) {
+        return None;
+    }
+
+    // どれかの分岐が要求する種類は、入力全体に要求する
+    let inputs = (0..width)
+        .map(|i| {
+            let mut required = live.iter().map(|e| e.inputs[i]).filter(|&ty| ty != Ty::Any);
+            let ty = required.next().unwrap_or(Ty::Any);
+            if required.all(|other| other == ty) { ty } else { Ty::Any }
+        })
+        .collect();
+    let outputs = (0..first.outputs.len())
+        .map(|i| match &first.outputs[i] {
+            Output::Input(j) if live.iter().all(|e| matches!(e.outputs[i], Output::Input(k) if k == *j)) => {
+                Output::Input(*j)
+            }
+            _ => {
+                let ty = live.iter().map(|e| e.output_ty(&e.outputs[i])).reduce(Ty::join).unwrap_or(Ty::Any);
+                Output::Value(Slot::of(ty))
+            }
+        })
+        .collect();
+    Some(Effect { inputs, outputs, diverges: false })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Known,
+    // 効果が実行時まで決まらない
+    Unknown,
+    // throw で抜けた
+    Diverged,
+}
+
+// 抽象的なスタック
+struct Sim {
+    id: usize,
+    stack: Vec<Slot>,
+    // 定義の本体では、足りない値は呼び出し元のスタックから取る（取った順に上から）
+    open: bool,
+    inputs: Vec<Ty>,
+    flow: Flow,
+}
+
+impl Sim {
+    fn new(id: usize, stack: Vec<Slot>, open: bool) -> Sim {
+        Sim { id, stack, open, inputs: Vec::new(), flow: Flow::Known }
+    }
+
+    fn push(&mut self, slot: Slot) {
+        self.stack.push(slot);
+    }
+
+    // 入力をそのまま取った値として使う。種類が分かっていればそれに絞る
+    fn refine(&mut self, slot: Slot, expected: Ty) -> Slot {
+        match slot.origin {
+            Some((id, i)) if id == self.id && slot.ty == Ty::Any && expected != Ty::Any => {
+                self.inputs[i] = expected;
+                for other in &mut self.stack {
+                    if other.origin == slot.origin {
+                        other.ty = expected;
+                    }
+                }
+                Slot { ty: expected, ..slot }
+            }
+            _ => slot,
+        }
+    }
+
+    fn finish(self) -> Option<Effect> {
+        if self.flow == Flow::Unknown {
+            return None;
+        }
+        let count = self.inputs.len();
+        let outputs = self.stack.into_iter()
+            .map(|slot| match slot.origin {
+                Some((id, i)) if id == self.id => Output::Input(count - 1 - i),
+                _ => Output::Value(slot),
+            })
+            .collect();
+        Some(Effect {
+            inputs: self.inputs.into_iter().rev().collect(),
+            outputs,
+            diverges: self.flow == Flow::Diverged,
+        })
+    }
+}
+
+type Env = HashMap<String, Slot>;
+
+const MAX_DEPTH: usize = 64;
+
+// このソースの中の 'name' def
+struct SourceDef {
+    body: Vec<Node>,
+    effect: Option<StackEffect>,
+    span: Span,
+}
+
+struct Checker<'a> {
+    interpreter: &'a Interpreter,
+    source: &'a str,
+    defs: HashMap<String, SourceDef>,
+    // 推論した効果（None は静的に決まらない）
+    effects: HashMap<String, Option<Effect>>,
+    in_progress: HashSet<String>,
+    // Sim の通し番号
+    sims: usize,
+    // run などでリテラルのクオーテーションをその場で展開している深さ
+    depth: usize,
+    // 0 より大きい間は報告しない（辞書にある定義の本体を調べるとき）
+    quiet: usize,
+    // include するファイルのワードは分からないので、未定義のワードを報告しない
+    includes: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+pub(crate) fn check_program(interpreter: &Interpreter, source: &str, program: &[Node]) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        interpreter,
+        source,
+        defs: HashMap::new(),
+        effects: HashMap::new(),
+        in_progress: HashSet::new(),
+        sims: 0,
+        depth: 0,
+        quiet: 0,
+        includes: false,
+        diagnostics: Vec::new(),
+    };
+    checker.collect_definitions(program);
+
+    // トップレベルは実際のスタックから始める
+    let stack = interpreter.stack.iter()
+        .map(|value| Slot::literal(&Node::from_value(value, Span::default())))
+        .collect();
+    let mut sim = checker.sim(stack, false);
+    checker.run(&mut sim, program, &Env::new());
+
+    // 呼ばれない定義も調べる
+    let mut names: Vec<String> = checker.defs.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        checker.user_effect(&name);
+    }
+
+    let mut diagnostics = checker.diagnostics;
+    diagnostics.sort_by_key(|d| (d.span.start, d.span.end));
+    diagnostics.dedup_by(|a, b| a.span == b.span && a.message == b.message);
+    diagnostics
+}
+
+impl Checker<'_> {
+    fn report(&mut self, severity: Severity, span: Span, message: String) {
+        if self.quiet > 0 {
+            return;
+        }
+        let (line, column) = line_col(self.source, span.start);
+        self.diagnostics.push(Diagnostic { severity, message, span, line, column });
+    }
+
+    // トップレベルの [body] 'name' [meta] def を集める
+    fn collect_definitions(&mut self, program: &[Node]) {
+        self.includes = program.iter().any(|node| node.word() == Some("include"));
+        for form in forms(program, &["def", "defp"]) {
+            let (_, effect) = form.metadata();
+            let def = |effect| SourceDef { body: form.body.to_vec(), effect, span: form.name_span };
+            // モジュールの中の定義は短縮名でも引けるようにする
+            if form.name != form.short && !self.defs.contains_key(form.short) {
+                self.defs.insert(form.short.to_string(), def(effect.clone()));
+            }
+            self.defs.insert(form.name.clone(), def(effect));
+        }
+    }
+
+    fn sim(&mut self, stack: Vec<Slot>, open: bool) -> Sim {
+        self.sims += 1;
+        Sim::new(self.sims, stack, open)
+    }
+
+    // nodes を呼び出し元から切り離して実行したときの効果
+    fn infer(&mut self, nodes: &[Node], env: &Env) -> Option<Effect> {
+        let mut sim = self.sim(Vec::new(), true);
+        self.run(&mut sim, nodes, env);
+        sim.finish()
+    }
+
+    fn run(&mut self, sim: &mut Sim, nodes: &[Node], env: &Env) {
+        // 自分自身を run し続けるクオーテーションは展開しきれない
+        if self.depth >= MAX_DEPTH {
+            sim.flow = Flow::Unknown;
+            return;
+        }
+        self.depth += 1;
+        self.run_nodes(sim, nodes, env);
+        self.depth -= 1;
+    }
+
+    fn run_nodes(&mut self, sim: &mut Sim, nodes: &[Node], env: &Env) {
+        for node in nodes {
+            if sim.flow != Flow::Known {
+                return;
+            }
+            match &node.kind {
+                NodeKind::Word(name, scope) => self.word(sim, name, *scope, node.span, env),
+                _ => sim.push(Slot::literal(node)),
+            }
+        }
+    }
+
+    fn word(&mut self, sim: &mut Sim, name: &str, scope: Scope, span: Span, env: &Env) {
+        match scope {
+            Scope::Local => {
+                // -> や match で束縛した名前は値に置き換わる
+                if let Some(slot) = env.get(name) {
+                    sim.push(slot.clone());
+                } else if self.interpreter.builtin_dict.contains(name) {
+                    self.builtin(sim, name, span, env);
+                } else {
+                    match self.lookup(name, span) {
+                        Some(effect) => self.apply(sim, &effect, name, span),
+                        None => sim.flow = Flow::Unknown,
+                    }
+                }
+            }
+            Scope::Map => self.map(sim, name, span),
+            Scope::Reduce => self.reduce(sim, name, span),
+            Scope::Global => sim.flow = Flow::Unknown,
+        }
+    }
+
+    // スタックから types の値を取る（下から順に返す）。足りなければ報告して以降を調べない
+    fn take(&mut self, sim: &mut Sim, types: &[Ty], word: &str, span: Span) -> Option<Vec<Slot>> {
+        if sim.flow != Flow::Known {
+            return None;
+        }
+        if !sim.open && sim.stack.len() < types.len() {
+            let noun = if types.len() == 1 { "value" } else { "values" };
+            let message = format!("{} needs {} {} but the stack has {}", word, types.len(), noun, sim.stack.len());
+            self.report(Severity::Error, span, message);
+            sim.flow = Flow::Unknown;
+            return None;
+        }
+        let mut taken = Vec::with_capacity(types.len());
+        for &expected in types.iter().rev() {
+            let slot = match sim.stack.pop() {
+                Some(slot) => {
+                    if !expected.accepts(slot.ty) {
+                        let message = format!("{} expects {} but got {}", word, expected.name(), slot.ty.name());
+                        self.report(Severity::Error, span, message);
+                    }
+                    sim.refine(slot, expected)
+                }
+                None => {
+                    sim.inputs.push(expected);
+                    Slot { origin: Some((sim.id, sim.inputs.len() - 1)), ..Slot::of(expected) }
+                }
+            };
+            taken.push(slot);
+        }
+        taken.reverse();
+        Some(taken)
+    }
+
+    // 種類が choices のどれかであることを確かめる
+    fn expect_one_of(&mut self, slot: &Slot, choices: &[Ty], word: &str, span: Span) {
+        if slot.ty != Ty::Any && !choices.contains(&slot.ty) {
+            let names: Vec<&str> = choices.iter().map(|ty| ty.name()).collect();
+            let message = format!("{} expects {} but got {}", word, names.join(" or "), slot.ty.name());
+            self.report(Severity::Error, span, message);
+        }
+    }
+
+    fn apply(&mut self, sim: &mut Sim, effect: &Effect, word: &str, span: Span) {
+        let Some(taken) = self.take(sim, &effect.inputs, word, span) else {
+            return;
+        };
+        if effect.diverges {
+            sim.flow = Flow::Diverged;
+            return;
+        }
+        for output in &effect.outputs {
+            sim.push(match output {
+                Output::Value(slot) => slot.clone(),
+                Output::Input(i) => taken[*i].clone(),
+            });
+        }
+    }
+
+    // クオーテーションとして実行する値の効果（リテラルでなければ None）
+    fn quotation_effect(&mut self, slot: &Slot, env: &Env) -> Option<Effect> {
+        let body = slot.quotation()?.to_vec();
+        self.infer(&body, env)
+    }
+
+    // 高階ワードに渡すクオーテーションが ( x1 .. xk -- y ) として使えるか
+    fn expect_quotation(&mut self, slot: &Slot, arity: usize, word: &str, span: Span, env: &Env) {
+        let Some(effect) = self.quotation_effect(slot, env) else {
+            return;
+        };
+        if effect.diverges || (effect.inputs.len() <= arity && effect.net() == 1 - arity as isize) {
+            return;
+        }
+        let expected = Effect::new(&vec![Ty::Any; arity], &[Ty::Any]);
+        let message = format!("{} expects a quotation {} but got {}", word, expected, effect);
+        self.report(Severity::Error, span, message);
+    }
+
+    // 分岐をまとめた効果を適用する。深さがそろわなければ報告して以降を調べない
+    fn branches(&mut self, sim: &mut Sim, effects: Vec<Option<Effect>>, word: &str, span: Span) {
+        let Some(effects) = effects.into_iter().collect::<Option<Vec<Effect>>>() else {
+            sim.flow = Flow::Unknown;
+            return;
+        };
+        if let Some(effect) = combine(&effects) {
+            self.apply(sim, &effect, word, span);
+            return;
+        }
+
+        let shown: Vec<String> = effects.iter().map(Effect::to_string).collect();
+        let message = match word {
+            "if" => format!("if branch changes the stack depth: {}", shown[0]),
+            "ifelse" => format!("ifelse branches have different stack effects: {}", shown.join(" and ")),
+            "try" => format!("try body and handler have different stack effects: {}", shown.join(" and ")),
+            _ => format!("{} clauses have different stack effects: {}", word, shown.join(" and ")),
+        };
+        self.report(Severity::Warning, span, message);
+        sim.flow = Flow::Unknown;
+    }
+
+    fn builtin(&mut self, sim: &mut Sim, name: &str, span: Span, env: &Env) {
+        if let Some((inputs, outputs)) = signature(name) {
+            self.apply(sim, &Effect::new(inputs, outputs), name, span);
+            return;
+        }
+
+        let any = Ty::Any;
+        match name {
+            "dup" | "drop" | "swap" | "over" | "rot" => {
+                let arity = match name {
+                    "dup" | "drop" => 1,
+                    "rot" => 3,
+                    _ => 2,
+                };
+                let Some(slots) = self.take(sim, &vec![any; arity], name, span) else {
+                    return;
+                };
+                let order: &[usize] = match name {
+                    "dup" => &[0, 0],
+                    "drop" => &[],
+                    "swap" => &[1, 0],
+                    "over" => &[0, 1, 0],
+                    _ => &[1, 2, 0],
+                };
+                for &i in order {
+                    sim.push(slots[i].clone());
+                }
+            }
+
+            "vec" => {
+                let count = match sim.stack.last().and_then(|slot| slot.node.as_ref()).map(|node| &node.kind) {
+                    Some(NodeKind::Literal(Value::Rational(n))) if n.is_integer() => n.numer().try_into().ok(),
+                    _ => None,
+                };
+                let Some(count) = count.filter(|&count: &usize| count <= sim.stack.len()) else {
+                    if self.take(sim, &[Ty::Number], name, span).is_some() {
+                        sim.flow = Flow::Unknown;
+                    }
+                    return;
+                };
+                let mut types = vec![any; count];
+                types.push(Ty::Number);
+                if self.take(sim, &types, name, span).is_some() {
+                    sim.push(Slot::of(Ty::Vector));
+                }
+            }
+            "unpack" => {
+                let Some(slots) = self.take(sim, &[Ty::Vector], name, span) else {
+                    return;
+                };
+                match slots[0].quotation() {
+                    Some(items) => {
+                        for item in items {
+                            sim.push(Slot::literal(item));
+                        }
+                    }
+                    None => sim.flow = Flow::Unknown,
+                }
+            }
+            "nth" | "slice" => {
+                let types: &[Ty] = if name == "nth" { &[any, Ty::Number] } else { &[any, Ty::Number, Ty::Number] };
+                let Some(slots) = self.take(sim, types, name, span) else {
+                    return;
+                };
+                self.expect_one_of(&slots[0], &[Ty::Vector, Ty::String], name, span);
+                let ty = if name == "nth" && slots[0].ty != Ty::String { any } else { slots[0].ty };
+                sim.push(Slot::of(ty));
+            }
+            "length" => {
+                let Some(slots) = self.take(sim, &[any], name, span) else {
+                    return;
+                };
+                self.expect_one_of(&slots[0], &[Ty::Vector, Ty::String, Ty::Set], name, span);
+                sim.push(Slot::of(Ty::Number));
+            }
+            "concat" => {
+                let Some(slots) = self.take(sim, &[any, any], name, span) else {
+                    return;
+                };
+                // 1つの間違いに1つの診断: 型の合わない値があればそれだけを報告する
+                let choices = [Ty::Vector, Ty::String];
+                let (a, b) = (slots[0].ty, slots[1].ty);
+                if let Some(slot) = slots.iter().find(|slot| slot.ty != Ty::Any && !choices.contains(&slot.ty)) {
+                    self.expect_one_of(slot, &choices, name, span);
+                } else if a != Ty::Any && b != Ty::Any && a != b {
+                    let message = format!("concat expects two vectors or two strings but got {} and {}", a.name(), b.name());
+                    self.report(Severity::Error, span, message);
+                }
+                sim.push(Slot::of(a.join(b)));
+            }
+
+            // 実行制御
+            "run" => {
+                let Some(slots) = self.take(sim, &[Ty::Vector], name, span) else {
+                    return;
+                };
+                match slots[0].quotation() {
+                    Some(body) => self.run(sim, body, env),
+                    None => sim.flow = Flow::Unknown,
+                }
+            }
+            "->" => {
+                let Some(slots) = self.take(sim, &[Ty::Vector, Ty::Vector], name, span) else {
+                    return;
+                };
+                let names: Option<Vec<String>> = slots[0].quotation().and_then(|items| {
+                    items.iter().map(|item| item.word().map(String::from)).collect()
+                });
+                let (Some(names), Some(body)) = (names, slots[1].quotation()) else {
+                    sim.flow = Flow::Unknown;
+                    return;
+                };
+                let body = body.to_vec();
+                let Some(values) = self.take(sim, &vec![any; names.len()], name, span) else {
+                    return;
+                };
+                let mut inner = env.clone();
+                inner.extend(names.into_iter().zip(values));
+                self.run(sim, &body, &inner);
+            }
+            "if" => {
+                let Some(slots) = self.take(sim, &[any, Ty::Vector], name, span) else {
+                    return;
+                };
+                let then_branch = self.quotation_effect(&slots[1], env);
+                let skip = Some(Effect::new(&[], &[]));
+                self.branches(sim, vec![then_branch, skip], name, span);
+            }
+            "ifelse" => {
+                let Some(slots) = self.take(sim, &[any, Ty::Vector, Ty::Vector], name, span) else {
+                    return;
+                };
+                let effects = vec![self.quotation_effect(&slots[1], env), self.quotation_effect(&slots[2], env)];
+                self.branches(sim, effects, name, span);
+            }
+            "match" => {
+                let Some(slots) = self.take(sim, &[any, Ty::Vector], name, span) else {
+                    return;
+                };
+                let Some(clauses) = slots[1].quotation().map(<[Node]>::to_vec) else {
+                    sim.flow = Flow::Unknown;
+                    return;
+                };
+                let mut effects = Vec::new();
+                for clause in &clauses {
+                    let NodeKind::Vector(parts) = &clause.kind else {
+                        sim.flow = Flow::Unknown;
+                        return;
+                    };
+                    let (pattern, action) = match parts.as_slice() {
+                        [pattern, action] | [pattern, _, action] => (pattern, action),
+                        _ => {
+                            sim.flow = Flow::Unknown;
+                            return;
+                        }
+                    };
+                    let mut inner = env.clone();
+                    bind_pattern(pattern, &slots[0], &mut inner);
+                    effects.push(self.quotation_effect(&Slot::literal(action), &inner));
+                }
+                self.branches(sim, effects, name, span);
+            }
+            "try" => {
+                let Some(slots) = self.take(sim, &[Ty::Vector, Ty::Vector], name, span) else {
+                    return;
+                };
+                let body = self.quotation_effect(&slots[0], env);
+                // ハンドラーは本体の前のスタックにエラーを積んで実行する
+                let handler = slots[1].quotation().map(<[Node]>::to_vec).and_then(|handler| {
+                    let mut caught = self.sim(vec![Slot::of(Ty::Error)], true);
+                    self.run(&mut caught, &handler, env);
+                    caught.finish()
+                });
+                self.branches(sim, vec![body, handler], name, span);
+            }
+            "finally" => {
+                let Some(slots) = self.take(sim, &[Ty::Vector, Ty::Vector], name, span) else {
+                    return;
+                };
+                for slot in &slots {
+                    match self.quotation_effect(slot, env) {
+                        Some(effect) => self.apply(sim, &effect, name, span),
+                        None => sim.flow = Flow::Unknown,
+                    }
+                }
+            }
+            "and-then" | "or-else" => {
+                let Some(slots) = self.take(sim, &[any, Ty::Vector], name, span) else {
+                    return;
+                };
+                self.expect_quotation(&slots[1], 0, name, span, env);
+                sim.push(Slot::of(Ty::Bool));
+            }
+            "sort" | "filter" | "scan" | "group-by" => {
+                let Some(slots) = self.take(sim, &[Ty::Vector, Ty::Vector], name, span) else {
+                    return;
+                };
+                let arity = if matches!(name, "sort" | "scan") { 2 } else { 1 };
+                self.expect_quotation(&slots[1], arity, name, span, env);
+                sim.push(Slot::of(Ty::Vector));
+            }
+            "throw" => {
+                let Some(slots) = self.take(sim, &[any], name, span) else {
+                    return;
+                };
+                self.expect_one_of(&slots[0], &[Ty::String, Ty::Error], name, span);
+                sim.flow = Flow::Diverged;
+            }
+
+            // 辞書
+            "def" | "defp" => {
+                let with_meta = sim.stack.last().is_some_and(|slot| slot.ty == Ty::Vector);
+                let types: &[Ty] = if with_meta {
+                    &[Ty::Vector, Ty::String, Ty::Vector]
+                } else {
+                    &[Ty::Vector, Ty::String]
+                };
+                self.take(sim, types, name, span);
+            }
+            "include" => {
+                self.take(sim, &[Ty::String], name, span);
+            }
+
+            // mod と step は未実装
+            _ => sim.flow = Flow::Unknown,
+        }
+    }
+
+    // v @w と v x @w
+    fn map(&mut self, sim: &mut Sim, name: &str, span: Span) {
+        let word = format!("@{}", name);
+        let Some(effect) = self.word_effect(name, span) else {
+            sim.flow = Flow::Unknown;
+            return;
+        };
+        let top = sim.stack.last().map_or(Ty::Any, |slot| slot.ty);
+        let with_arg = match top {
+            Ty::Vector => takes_quotation(name),
+            Ty::Set => false,
+            Ty::Any => effect.inputs.len() == 2,
+            _ => true,
+        };
+        let types: &[Ty] = if with_arg { &[Ty::Any, Ty::Any] } else { &[Ty::Any] };
+        let Some(slots) = self.take(sim, types, &word, span) else {
+            return;
+        };
+        self.expect_one_of(&slots[0], &[Ty::Vector, Ty::Set], &word, span);
+        let arity = types.len();
+        if !effect.diverges && (effect.inputs.len() > arity || effect.net() != 1 - arity as isize) {
+            let expected = Effect::new(&vec![Ty::Any; arity], &[Ty::Any]);
+            let message = format!("{} needs a word with effect {} but {} has {}", word, expected, name, effect);
+            self.report(Severity::Error, span, message);
+        }
+        let ty = if slots[0].ty == Ty::Set { Ty::Set } else { Ty::Vector };
+        sim.push(Slot::of(ty));
+    }
+
+    // v *w
+    fn reduce(&mut self, sim: &mut Sim, name: &str, span: Span) {
+        let word = format!("*{}", name);
+        let Some(effect) = self.word_effect(name, span) else {
+            sim.flow = Flow::Unknown;
+            return;
+        };
+        let Some(slots) = self.take(sim, &[Ty::Any], &word, span) else {
+            return;
+        };
+        self.expect_one_of(&slots[0], &[Ty::Vector, Ty::Set], &word, span);
+        let ty = match effect.outputs.as_slice() {
+            [output] if effect.inputs.len() <= 2 && effect.net() == -1 => effect.output_ty(output),
+            _ if effect.diverges => Ty::Any,
+            _ => {
+                let expected = Effect::new(&[Ty::Any, Ty::Any], &[Ty::Any]);
+                let message = format!("{} needs a word with effect {} but {} has {}", word, expected, name, effect);
+                self.report(Severity::Error, span, message);
+                Ty::Any
+            }
+        };
+        sim.push(Slot::of(ty));
+    }
+
+    // @ や * に渡すワードの効果
+    fn word_effect(&mut self, name: &str, span: Span) -> Option<Effect> {
+        if let Some((inputs, outputs)) = signature(name) {
+            return Some(Effect::new(inputs, outputs));
+        }
+        if self.interpreter.builtin_dict.contains(name) {
+            return match name {
+                "dup" => Some(Effect::new(&[Ty::Any], &[Ty::Any, Ty::Any])),
+                "drop" => Some(Effect::new(&[Ty::Any], &[])),
+                "swap" => Some(Effect::new(&[Ty::Any, Ty::Any], &[Ty::Any, Ty::Any])),
+                "concat" => Some(Effect::new(&[Ty::Any, Ty::Any], &[Ty::Any])),
+                "length" => Some(Effect::new(&[Ty::Any], &[Ty::Number])),
+                _ => None,
+            };
+        }
+        self.lookup(name, span)
+    }
+
+    // ユーザー定義ワードの効果。見つからなければ報告する
+    fn lookup(&mut self, name: &str, span: Span) -> Option<Effect> {
+        if self.defs.contains_key(name) || self.interpreter.resolve_word(name).is_some() {
+            return self.user_effect(name);
+        }
+        if !self.includes {
+            self.report(Severity::Warning, span, format!("Unknown word: {}", name));
+        }
+        None
+    }
+
+    fn user_effect(&mut self, name: &str) -> Option<Effect> {
+        if let Some(effect) = self.effects.get(name) {
+            return effect.clone();
+        }
+
+        let (body, declared, span, quiet) = match self.defs.get(name) {
+            Some(def) => (def.body.clone(), def.effect.clone(), def.span, false),
+            None => {
+                let def = self.interpreter.resolve_word(name)?;
+                let body = def.code.iter().map(|value| Node::from_value(value, Span::default())).collect();
+                (body, def.effect.clone(), Span::default(), true)
+            }
+        };
+        let declared_effect = declared.as_ref().and_then(Effect::declared);
+
+        // 再帰呼び出しは宣言された効果で調べる
+        if !self.in_progress.insert(name.to_string()) {
+            return declared_effect;
+        }
+        if quiet {
+            self.quiet += 1;
+        }
+        let inferred = self.infer(&body, &Env::new());
+        if quiet {
+            self.quiet -= 1;
+        }
+        self.in_progress.remove(name);
+
+        let effect = match (inferred, declared_effect) {
+            (Some(inferred), Some(expected)) => {
+                let fits = inferred.diverges
+                    || (inferred.inputs.len() <= expected.inputs.len() && inferred.net() == expected.net());
+                if fits {
+                    // 個数が同じなら推論した種類の方が詳しい
+                    Some(if inferred.inputs.len() == expected.inputs.len() { inferred } else { expected })
+                } else {
+                    if let (false, Some(declared)) = (quiet, &declared) {
+                        let message = format!("{} declares {} but its body has effect {}", name, declared, inferred);
+                        self.report(Severity::Error, span, message);
+                    }
+                    Some(expected)
+                }
+            }
+            (inferred, expected) => expected.or(inferred),
+        };
+        self.effects.insert(name.to_string(), effect.clone());
+        effect
+    }
+}
+
+// match のパターンが束縛する名前
+fn bind_pattern(pattern: &Node, value: &Slot, env: &mut Env) {
+    match &pattern.kind {
+        NodeKind::Word(name, Scope::Local) if name != "_" && name != "&" && test_type(name).is_none() => {
+            env.insert(name.clone(), value.clone());
+        }
+        NodeKind::Vector(items) => {
+            let mut rest = false;
+            for item in items {
+                if item.word() == Some("&") {
+                    rest = true;
+                    continue;
+                }
+                let ty = if rest { Ty::Vector } else { Ty::Any };
+                bind_pattern(item, &Slot::of(ty), env);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn test_type(name: &str) -> Option<Ty> {
+    let ty = match name {
+        "rational?" => Ty::Number,
+        "string?" => Ty::String,
+        "bool?" => Ty::Bool,
+        "nil?" => Ty::Nil,
+        "vector?" => Ty::Vector,
+        "set?" => Ty::Set,
+        "word?" => Ty::Word,
+        "error?" => Ty::Error,
+        _ => return None,
+    };
+    Some(ty)
+}
+
+// 入力と出力の個数と種類が決まっている組み込みワード（下から順）
+fn signature(name: &str) -> Option<(&'static [Ty], &'static [Ty])> {
+    const X: Ty = Ty::Any;
+    const N: Ty = Ty::Number;
+    const S: Ty = Ty::String;
+    const B: Ty = Ty::Bool;
+    const V: Ty = Ty::Vector;
+    const T: Ty = Ty::Set;
+    const E: Ty = Ty::Error;
+
+    let signature: (&[Ty], &[Ty]) = match name {
+        "add" | "sub" | "mul" | "div" | "pow" => (&[N, N], &[N]),
+        "eq" | "ne" | "lt" | "gt" | "le" | "ge" => (&[X, X], &[B]),
+        "cmp" => (&[X, X], &[N]),
+        "and" | "or" | "xor" => (&[X, X], &[B]),
+        "not" | "truthy" => (&[X], &[B]),
+        "quote" => (&[X], &[V]),
+        "print" => (&[X], &[]),
+        "clear" | "end" => (&[], &[]),
+        "undef" | "see" | "help" | "module" | "use" => (&[S], &[]),
+        "words" => (&[], &[V]),
+        "words-prefix" => (&[S], &[V]),
+        "toset" => (&[V], &[T]),
+        "tovec" => (&[T], &[V]),
+        "union" | "intersect" | "diff" => (&[T, T], &[T]),
+        "subset?" => (&[T, T], &[B]),
+        "member?" => (&[T, X], &[B]),
+        "split" => (&[S, S], &[V]),
+        "join" => (&[V, S], &[S]),
+        "upper" | "lower" | "trim" => (&[S], &[S]),
+        "replace" => (&[S, S, S], &[S]),
+        "find" => (&[S, S], &[X]),
+        "startswith" | "endswith" => (&[S, S], &[B]),
+        "chars" => (&[S], &[V]),
+        "ord" => (&[S], &[N]),
+        "chr" => (&[N], &[S]),
+        "tostring" => (&[X], &[S]),
+        "parse-number" => (&[S], &[N]),
+        "range" => (&[N, N], &[V]),
+        "iota" => (&[N], &[V]),
+        "reverse" | "flatten" | "transpose" | "uniq" | "rest" => (&[V], &[V]),
+        "zip" => (&[V, V], &[V]),
+        "take" | "drop-n" => (&[V, N], &[V]),
+        "index-of" => (&[V, X], &[X]),
+        "contains" => (&[V, X], &[B]),
+        "first" => (&[V], &[X]),
+        "error" => (&[S, S], &[E]),
+        "error-kind" | "error-message" => (&[E], &[S]),
+        "assert" => (&[X], &[]),
+        "assert-eq" => (&[X, X], &[]),
+        "assert-stack" => (&[V], &[]),
+        "test" => (&[V, S], &[]),
+        _ if test_type(name).is_some() => (&[X], &[B]),
+        _ => return None,
+    };
+    Some(signature)
+}
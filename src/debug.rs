@@ -0,0 +1,377 @@
+use serde::Serialize;
+
+use crate::syntax::{NodeKind, Span};
+use crate::{Interpreter, LycorisError, Scope, Value};
+
+// ============================================================================
+// デバッガ
+// ============================================================================
+//
+// ブレークポイントに当たるか、ステップ実行で止まる位置に来ると、トークンを実行する前に
+// ハンドラを呼ぶ。高階ワードの中のコードは Rust の再帰で実行されるので、実行を抜けて
+// 後から再開するのではなく、ハンドラが戻るまでその場で止まっている。
+// ハンドラは DebugSession でスタックや呼び出しの並びを調べて書き換え、再開の仕方を返す。
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Breakpoint {
+    // ソースでの書き方（@sq など）が一致するワードの実行
+    Word(String),
+    // 先頭がこの範囲にあるトークンの実行
+    Span(Span),
+}
+
+impl Breakpoint {
+    fn matches(&self, token: &Value, span: Option<Span>) -> bool {
+        match (self, token) {
+            (Breakpoint::Word(word), Value::Word(name, scope)) => word.strip_prefix(scope.prefix()) == Some(name),
+            (Breakpoint::Word(_), _) => false,
+            (Breakpoint::Span(range), _) => span.is_some_and(|span| range.start <= span.start && span.start < range.end),
+        }
+    }
+}
+
+// 止まった後の再開の仕方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    // 次のブレークポイントまで実行する
+    Continue,
+    // 次のトークンで止まる（呼び出したワードの中にも入る）
+    StepIn,
+    // 同じフレームの次のトークンで止まる。フレームが終われば呼び出し元で止まる
+    StepOver,
+    // 呼び出し元のフレームに戻ったところで止まる
+    StepOut,
+    // 実行をやめる。try では捕捉できない
+    Abort,
+}
+
+// ハンドラへの返事
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugReply {
+    Resume(DebugAction),
+    // 調べた結果やエラーの表示
+    Output(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CallFrame {
+    // フレームが本体を実行しているユーザー定義ワード。トップレベルや run で実行中のコードは None
+    pub word: Option<String>,
+    // フレームで実行中のトークンの位置
+    pub span: Option<Span>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pause {
+    // 次に実行するトークン（TraceStep と同じ書き方）
+    pub word: String,
+    pub scope: Option<Scope>,
+    pub depth: usize,
+    pub span: Option<Span>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    // 当たったブレークポイントの番号。ステップ実行で止まったなら None
+    pub breakpoint: Option<usize>,
+    pub stack: Vec<String>,
+    // 呼び出しの並び（トップレベルから順）
+    pub calls: Vec<CallFrame>,
+}
+
+impl Pause {
+    // ソースでの書き方（@square など）
+    pub fn token(&self) -> String {
+        match self.scope {
+            Some(scope) => format!("{}{}", scope.prefix(), self.word),
+            None => self.word.clone(),
+        }
+    }
+}
+
+pub trait DebugHandler {
+    // 止まるたびに呼ばれる。戻るまで実行は止まっている
+    fn paused(&mut self, session: &mut DebugSession) -> DebugAction;
+}
+
+#[derive(Default)]
+pub(crate) struct Debugger {
+    // 止まっている間は取り出しておく
+    pub(crate) handler: Option<Box<dyn DebugHandler>>,
+    pub(crate) breakpoints: Vec<(usize, Breakpoint)>,
+    next_id: usize,
+    // フレーム数がこれ以下のトークンで止まる。None ならブレークポイントでだけ止まる
+    step_depth: Option<usize>,
+}
+
+impl Debugger {
+    pub(crate) fn attached(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    pub(crate) fn add(&mut self, breakpoint: Breakpoint) -> usize {
+        self.next_id += 1;
+        self.breakpoints.push((self.next_id, breakpoint));
+        self.next_id
+    }
+
+    pub(crate) fn remove(&mut self, id: usize) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|(other, _)| *other != id);
+        self.breakpoints.len() < before
+    }
+
+    // 次に実行するトークンで止まる
+    pub(crate) fn request_pause(&mut self) {
+        self.step_depth = Some(usize::MAX);
+    }
+
+    pub(crate) fn stop_stepping(&mut self) {
+        self.step_depth = None;
+    }
+
+    // 止まるなら Some(当たったブレークポイント)
+    pub(crate) fn check(&self, token: &Value, span: Option<Span>, depth: usize) -> Option<Option<usize>> {
+        let breakpoint = self.breakpoints.iter().find(|(_, breakpoint)| breakpoint.matches(token, span));
+        match breakpoint {
+            Some((id, _)) => Some(Some(*id)),
+            None if self.step_depth.is_some_and(|max| depth <= max) => Some(None),
+            None => None,
+        }
+    }
+
+    // depth: 止まったトークンのフレーム数。last: それがフレームの最後のトークンか
+    pub(crate) fn resume(&mut self, action: DebugAction, depth: usize, last: bool) -> Result<(), LycorisError> {
+        // 最後のトークンからの呼び出しはフレームを置き換えるので、同じ深さでもその中になる
+        let over = if last { depth - 1 } else { depth };
+        self.step_depth = match action {
+            DebugAction::Continue => None,
+            DebugAction::StepIn => Some(usize::MAX),
+            DebugAction::StepOver => Some(over),
+            DebugAction::StepOut => Some(depth - 1),
+            DebugAction::Abort => {
+                self.step_depth = None;
+                return Err(LycorisError::Aborted);
+            }
+        };
+        Ok(())
+    }
+}
+
+const HELP: &str = "\
+continue, c         run to the next breakpoint
+step, s             stop at the next token, entering calls
+next, n             stop at the next token in this frame
+out, o              stop after returning to the calling frame
+stack               show the stack (index 0 is the bottom)
+set INDEX VALUE     replace a stack value
+push VALUE          push a value
+pop                 drop the top value
+where, bt           show the call chain
+break WORD | LINE   add a breakpoint on a word or a source line
+delete ID           remove a breakpoint
+breakpoints         list breakpoints
+quit, q             abort the program";
+
+// 止まっている間にハンドラが使う操作
+pub struct DebugSession<'a> {
+    interpreter: &'a mut Interpreter,
+    pause: Pause,
+}
+
+impl<'a> DebugSession<'a> {
+    pub(crate) fn new(interpreter: &'a mut Interpreter, token: &Value, breakpoint: Option<usize>) -> Self {
+        let (word, scope) = match token {
+            Value::Word(name, scope) => (name.clone(), Some(*scope)),
+            value => (value.to_display_string(), None),
+        };
+        let span = interpreter.current_span();
+        let (line, column) = interpreter.location(span).unzip();
+        let calls = interpreter.frames.iter()
+            .map(|frame| {
+                let span = frame.span();
+                let (line, column) = interpreter.location(span).unzip();
+                CallFrame { word: frame.word.as_deref().map(str::to_string), span, line, column }
+            })
+            .collect();
+        let pause = Pause {
+            word,
+            scope,
+            depth: interpreter.frames.len().saturating_sub(1),
+            span,
+            line,
+            column,
+            breakpoint,
+            stack: Vec::new(),
+            calls,
+        };
+        let mut session = DebugSession { interpreter, pause };
+        session.refresh();
+        session
+    }
+
+    pub fn pause(&self) -> &Pause {
+        &self.pause
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        &self.interpreter.stack
+    }
+
+    fn refresh(&mut self) {
+        self.pause.stack = self.interpreter.stack.iter().map(Value::to_display_string).collect();
+    }
+
+    // index は下から数える。取り消しログに残るように pop と push で書き換える
+    pub fn set_stack_value(&mut self, index: usize, value: Value) -> Result<(), LycorisError> {
+        if index >= self.interpreter.stack.len() {
+            return Err(LycorisError::IndexOutOfBounds);
+        }
+        let mut above = Vec::new();
+        while self.interpreter.stack.len() > index + 1 {
+            above.push(self.interpreter.pop()?);
+        }
+        self.interpreter.pop()?;
+        self.interpreter.push(value)?;
+        for value in above.into_iter().rev() {
+            self.interpreter.push(value)?;
+        }
+        self.refresh();
+        Ok(())
+    }
+
+    pub fn push_value(&mut self, value: Value) -> Result<(), LycorisError> {
+        self.interpreter.push(value)?;
+        self.refresh();
+        Ok(())
+    }
+
+    pub fn pop_value(&mut self) -> Result<Value, LycorisError> {
+        let value = self.interpreter.pop()?;
+        self.refresh();
+        Ok(value)
+    }
+
+    // ソースに書いた値1つ（'text' や [1 2] など）を読む。ワードは受け付けない
+    pub fn parse_value(&self, text: &str) -> Result<Value, LycorisError> {
+        let mut nodes = self.interpreter.parse(text)?;
+        match nodes.pop() {
+            Some(node) if nodes.is_empty() && !matches!(node.kind, NodeKind::Word(..)) => Ok(node.into_value()),
+            _ => Err(LycorisError::Syntax(format!("Expected a single value: {}", text.trim()))),
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.interpreter.debugger.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.interpreter.debugger.add(breakpoint)
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.interpreter.debugger.remove(id)
+    }
+
+    // 実行中のソースの1行全体（1 始まり）
+    pub fn line_span(&self, line: usize) -> Option<Span> {
+        self.interpreter.source_map.as_ref()?.line_span(line)
+    }
+
+    // コマンドラインと Web UI で共通のコマンドを1行実行する
+    pub fn command(&mut self, line: &str) -> DebugReply {
+        let line = line.trim();
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+        let resume = |action| Ok(DebugReply::Resume(action));
+        let result = match command {
+            "continue" | "c" => resume(DebugAction::Continue),
+            "step" | "s" => resume(DebugAction::StepIn),
+            "next" | "n" => resume(DebugAction::StepOver),
+            "out" | "o" => resume(DebugAction::StepOut),
+            "quit" | "q" => resume(DebugAction::Abort),
+            "" => Ok(DebugReply::Output(String::new())),
+            "help" | "h" => Ok(DebugReply::Output(HELP.to_string())),
+            "stack" => Ok(DebugReply::Output(self.show_stack())),
+            "where" | "bt" => Ok(DebugReply::Output(self.show_calls())),
+            "breakpoints" => Ok(DebugReply::Output(self.show_breakpoints())),
+            "set" => self.command_set(argument),
+            "push" => self.parse_value(argument).and_then(|value| self.push_value(value)).map(|()| self.stack_reply()),
+            "pop" => self.pop_value().map(|_| self.stack_reply()),
+            "break" | "b" => self.command_break(argument),
+            "delete" | "d" => match argument.parse() {
+                Ok(id) if self.remove_breakpoint(id) => Ok(DebugReply::Output(format!("deleted breakpoint {}", id))),
+                _ => Err(LycorisError::InvalidArgument(format!("No breakpoint {}", argument))),
+            },
+            _ => Err(LycorisError::InvalidArgument(format!("Unknown command: {} (try help)", command))),
+        };
+        result.unwrap_or_else(|err| DebugReply::Output(format!("error: {}", err)))
+    }
+
+    fn command_set(&mut self, argument: &str) -> Result<DebugReply, LycorisError> {
+        let (index, value) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+        let index = index.parse().map_err(|_| LycorisError::InvalidArgument("Usage: set INDEX VALUE".to_string()))?;
+        let value = self.parse_value(value)?;
+        self.set_stack_value(index, value)?;
+        Ok(self.stack_reply())
+    }
+
+    fn command_break(&mut self, argument: &str) -> Result<DebugReply, LycorisError> {
+        if argument.is_empty() {
+            return Err(LycorisError::InvalidArgument("Usage: break WORD | LINE".to_string()));
+        }
+        let breakpoint = match argument.parse::<usize>() {
+            Ok(line) => Breakpoint::Span(
+                self.line_span(line).ok_or_else(|| LycorisError::InvalidArgument(format!("No line {}", line)))?,
+            ),
+            Err(_) => Breakpoint::Word(argument.to_string()),
+        };
+        let id = self.add_breakpoint(breakpoint);
+        Ok(DebugReply::Output(format!("breakpoint {} at {}", id, argument)))
+    }
+
+    fn stack_reply(&self) -> DebugReply {
+        DebugReply::Output(self.show_stack())
+    }
+
+    fn show_stack(&self) -> String {
+        if self.pause.stack.is_empty() {
+            return "(empty)".to_string();
+        }
+        let lines: Vec<String> = self.pause.stack.iter().enumerate()
+            .map(|(index, value)| format!("{}: {}", index, value))
+            .collect();
+        lines.join("\n")
+    }
+
+    // 内側のフレームから順に表示する
+    fn show_calls(&self) -> String {
+        let lines: Vec<String> = self.pause.calls.iter().enumerate().rev()
+            .map(|(depth, frame)| {
+                let word = frame.word.as_deref().unwrap_or("(code)");
+                match (frame.line, frame.column) {
+                    (Some(line), Some(column)) => format!("{}  {} at {}:{}", depth, word, line, column),
+                    _ => format!("{}  {}", depth, word),
+                }
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    fn show_breakpoints(&self) -> String {
+        if self.breakpoints().is_empty() {
+            return "(none)".to_string();
+        }
+        let lines: Vec<String> = self.breakpoints().iter()
+            .map(|(id, breakpoint)| match breakpoint {
+                Breakpoint::Word(word) => format!("{}  word {}", id, word),
+                Breakpoint::Span(span) => match self.interpreter.location(Some(*span)) {
+                    Some((line, column)) => format!("{}  {}:{}", id, line, column),
+                    None => format!("{}  bytes {}..{}", id, span.start, span.end),
+                },
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
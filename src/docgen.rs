@@ -0,0 +1,358 @@
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::builtins::SECTIONS;
+use crate::module::StackEffect;
+use crate::syntax::Node;
+use crate::testing::{TestCase, TestResult};
+use crate::{Interpreter, Value};
+
+// ============================================================================
+// リファレンスの生成
+// ============================================================================
+//
+// 組み込みワードの表と辞書にある公開の定義から、ワードごとの項目を作って Markdown と HTML に
+// 書き出す。例は「ソース → 実行後のスタック」で、ソースの後に [期待するスタック] assert-stack を
+// 付けたテストとして実行する（doctest）。
+
+const TITLE: &str = "Lycoris Word Reference";
+
+const INTRO: &str = "\
+Each entry gives the stack effect, the prefixed forms that make sense for the word, \
+examples and related words. `@word` maps the word over a vector (a second input is passed \
+to every element, so it cannot be a vector itself), `*word` folds a vector with it and \
+`#word` folds the whole stack. Because `#` at the start of a token begins a comment, \
+`#word` can only be built by a host. Each example shows source and the stack it leaves, \
+bottom first, and runs as a test.";
+
+// 意味のあるスコーププレフィックス
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Scopes {
+    pub map: bool,
+    pub reduce: bool,
+    pub global: bool,
+}
+
+impl Scopes {
+    // @ は入力1つか2つで出力1つ、* と # は入力2つで出力1つのワードで使える
+    fn from_effect(effect: &StackEffect) -> Scopes {
+        let fixed = !effect.inputs.iter().chain(&effect.outputs).any(|name| name == "..");
+        let single = fixed && effect.outputs.len() == 1;
+        let binary = single && effect.inputs.len() == 2;
+        Scopes {
+            map: single && matches!(effect.inputs.len(), 1 | 2),
+            reduce: binary,
+            global: binary,
+        }
+    }
+
+    fn forms(&self, name: &str) -> Vec<String> {
+        [(self.map, "@"), (self.reduce, "*"), (self.global, "#")].iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, prefix)| format!("{}{}", prefix, name))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Example {
+    pub source: String,
+    // 実行後のスタック（下から順、空白区切りの表示形式）
+    pub expected: String,
+}
+
+impl Example {
+    // 「ソース → 期待するスタック」
+    pub fn parse(text: &str) -> Option<Example> {
+        let (source, expected) = text.split_once('→')?;
+        Some(Example { source: source.trim().to_string(), expected: expected.trim().to_string() })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WordDoc {
+    // ユーザー定義ワードは完全名
+    pub name: String,
+    pub builtin: bool,
+    // 組み込みワードは表の節、ユーザー定義ワードはモジュール
+    pub section: String,
+    pub effect: Option<String>,
+    pub doc: Option<String>,
+    // スタック効果を宣言していないワードは None
+    pub scopes: Option<Scopes>,
+    pub examples: Vec<Example>,
+    // 例や本体で使っている、リファレンスにある他のワード
+    pub see_also: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reference {
+    // 組み込みワードは表の順、ユーザー定義ワードはモジュールと名前の順
+    pub words: Vec<WordDoc>,
+}
+
+pub(crate) fn reference(interpreter: &Interpreter) -> Reference {
+    let mut words = Vec::new();
+    let mut uses = Vec::new();
+    for section in SECTIONS {
+        for info in section.words {
+            let examples: Vec<Example> = info.examples.iter().filter_map(|text| Example::parse(text)).collect();
+            uses.push((String::new(), example_words(interpreter, &examples)));
+            words.push(WordDoc {
+                name: info.name.to_string(),
+                builtin: true,
+                section: section.title.to_string(),
+                effect: Some(info.effect.to_string()),
+                doc: Some(info.doc.to_string()),
+                scopes: StackEffect::parse(info.effect).map(|effect| Scopes::from_effect(&effect)),
+                examples,
+                see_also: Vec::new(),
+            });
+        }
+    }
+
+    let mut defs: Vec<_> = interpreter.dictionary.iter().filter(|(_, def)| !def.private).collect();
+    defs.sort_by(|(a, a_def), (b, b_def)| a_def.module.cmp(&b_def.module).then(a.cmp(b)));
+    for (name, def) in defs {
+        let examples: Vec<Example> = def.examples.iter().filter_map(|text| Example::parse(text)).collect();
+        let mut used = example_words(interpreter, &examples);
+        collect_words(&def.code, &mut used);
+        uses.push((def.module.to_string(), used));
+        words.push(WordDoc {
+            name: name.clone(),
+            builtin: false,
+            section: if def.module.is_empty() { "User Words".to_string() } else { format!("Module {}", def.module) },
+            effect: def.effect.as_ref().map(ToString::to_string),
+            doc: def.doc.clone(),
+            scopes: def.effect.as_ref().map(Scopes::from_effect),
+            examples,
+            see_also: Vec::new(),
+        });
+    }
+
+    // 使っている名前を、そのワードのモジュールから見たリファレンスの項目に結びつける
+    let documented: HashSet<String> = words.iter().map(|word| word.name.clone()).collect();
+    for (word, (module, used)) in words.iter_mut().zip(uses) {
+        for name in used {
+            let target = interpreter.namespaces.candidates(&name, &module).into_iter()
+                .find(|candidate| documented.contains(candidate));
+            if let Some(target) = target {
+                if target != word.name && !word.see_also.contains(&target) {
+                    word.see_also.push(target);
+                }
+            }
+        }
+    }
+    Reference { words }
+}
+
+// 例のソースに出てくるワード名（スコープを除く）
+fn example_words(interpreter: &Interpreter, examples: &[Example]) -> Vec<String> {
+    let mut words = Vec::new();
+    for example in examples {
+        if let Ok(nodes) = interpreter.parse(&example.source) {
+            let code: Vec<Value> = nodes.into_iter().map(Node::into_value).collect();
+            collect_words(&code, &mut words);
+        }
+    }
+    words
+}
+
+fn collect_words(code: &[Value], words: &mut Vec<String>) {
+    for value in code {
+        match value {
+            Value::Word(name, _) => words.push(name.clone()),
+            Value::Vector(items) => collect_words(items, words),
+            Value::Set(items) => items.iter().for_each(|item| collect_words(std::slice::from_ref(item), words)),
+            _ => {}
+        }
+    }
+}
+
+// 例ごとのテスト。組み込みワードの例は builtins のときだけ。読めない例は失敗した結果になる
+pub(crate) fn doctests(interpreter: &Interpreter, builtins: bool) -> Vec<Result<TestCase, TestResult>> {
+    let mut cases = Vec::new();
+    let mut add = |name: &str, module: &str, examples: &[String]| {
+        for (i, text) in examples.iter().enumerate() {
+            let name = format!("{} example {}", name, i + 1);
+            let case = Example::parse(text)
+                .ok_or_else(|| format!("Example has no →: {}", text))
+                .and_then(|example| {
+                    let source = format!("{}\n[{}] assert-stack", example.source, example.expected);
+                    interpreter.parse(&source).map_err(|err| err.to_string())
+                })
+                .map(|nodes| TestCase {
+                    name: name.clone(),
+                    code: nodes.into_iter().map(Node::into_value).collect(),
+                    module: module.into(),
+                })
+                .map_err(|message| TestResult {
+                    name: name.clone(),
+                    passed: false,
+                    message: Some(message),
+                    diff: Vec::new(),
+                    output: Vec::new(),
+                    span: None,
+                    line: None,
+                    column: None,
+                });
+            cases.push(case);
+        }
+    };
+    if builtins {
+        for section in SECTIONS {
+            for info in section.words {
+                let examples: Vec<String> = info.examples.iter().map(|text| text.to_string()).collect();
+                add(info.name, "", &examples);
+            }
+        }
+    }
+    let mut defs: Vec<_> = interpreter.dictionary.iter().filter(|(_, def)| !def.examples.is_empty()).collect();
+    defs.sort_by_key(|(name, _)| *name);
+    for (name, def) in defs {
+        add(name, &def.module, &def.examples);
+    }
+    cases
+}
+
+// 節の名前と、その節のワード（出てくる順）
+fn sections(words: &[WordDoc]) -> Vec<(&str, Vec<&WordDoc>)> {
+    let mut sections: Vec<(&str, Vec<&WordDoc>)> = Vec::new();
+    for word in words {
+        match sections.last_mut() {
+            Some((title, entries)) if *title == word.section => entries.push(word),
+            _ => sections.push((&word.section, vec![word])),
+        }
+    }
+    sections
+}
+
+// HTML の id。空白は -、英数字と - . 以外は _ と16進のコードにする
+fn anchor(kind: &str, name: &str) -> String {
+    let mut id = format!("{}-", kind);
+    for c in name.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '-' | '.') {
+            id.push(c);
+        } else if c == ' ' {
+            id.push('-');
+        } else {
+            id.push_str(&format!("_{:x}", c as u32));
+        }
+    }
+    id
+}
+
+fn scope_text(word: &WordDoc) -> String {
+    match &word.scopes {
+        None => "unknown without a stack effect".to_string(),
+        Some(scopes) => {
+            let forms = scopes.forms(&word.name);
+            if forms.is_empty() {
+                "none".to_string()
+            } else {
+                forms.iter().map(|form| format!("`{}`", form)).collect::<Vec<_>>().join(" ")
+            }
+        }
+    }
+}
+
+impl Reference {
+    pub fn to_markdown(&self) -> String {
+        let sections = sections(&self.words);
+        let mut out = format!("# {}\n\n<!-- Generated by `lycoris doc`. Do not edit by hand. -->\n\n{}\n\n", TITLE, INTRO);
+        for (title, _) in &sections {
+            out.push_str(&format!("- [{}](#{})\n", title, anchor("section", title)));
+        }
+        for (title, words) in &sections {
+            out.push_str(&format!("\n## <a id=\"{}\"></a>{}\n", anchor("section", title), title));
+            for word in words {
+                out.push_str(&format!("\n### <a id=\"{}\"></a>`{}`\n\n", anchor("word", &word.name), word.name));
+                if let Some(effect) = &word.effect {
+                    out.push_str(&format!("`{}` · ", effect));
+                }
+                out.push_str(&format!("Scopes: {}\n", scope_text(word)));
+                if let Some(doc) = &word.doc {
+                    out.push_str(&format!("\n{}\n", doc));
+                }
+                if !word.examples.is_empty() {
+                    out.push_str("\n```\n");
+                    for example in &word.examples {
+                        out.push_str(&format!("{} → {}\n", example.source, example.expected).replace(" \n", "\n"));
+                    }
+                    out.push_str("```\n");
+                }
+                if !word.see_also.is_empty() {
+                    let links: Vec<String> = word.see_also.iter()
+                        .map(|name| format!("[`{}`](#{})", name, anchor("word", name)))
+                        .collect();
+                    out.push_str(&format!("\nSee also: {}\n", links.join(", ")));
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let sections = sections(&self.words);
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+             body {{ font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; line-height: 1.5; }}\n\
+             article {{ border-top: 1px solid #ddd; }}\n\
+             pre {{ background: #f6f6f6; padding: 0.5em; }}\n\
+             </style>\n</head>\n<body>\n<h1>{}</h1>\n<p>{}</p>\n<nav>\n<ul>\n",
+            TITLE,
+            TITLE,
+            inline_html(INTRO)
+        );
+        for (title, _) in &sections {
+            out.push_str(&format!("<li><a href=\"#{}\">{}</a></li>\n", anchor("section", title), escape(title)));
+        }
+        out.push_str("</ul>\n</nav>\n");
+        for (title, words) in &sections {
+            out.push_str(&format!("<section id=\"{}\">\n<h2>{}</h2>\n", anchor("section", title), escape(title)));
+            for word in words {
+                out.push_str(&format!(
+                    "<article id=\"{}\">\n<h3><code>{}</code></h3>\n<p>",
+                    anchor("word", &word.name),
+                    escape(&word.name)
+                ));
+                if let Some(effect) = &word.effect {
+                    out.push_str(&format!("<code>{}</code> · ", escape(effect)));
+                }
+                out.push_str(&format!("Scopes: {}</p>\n", inline_html(&scope_text(word))));
+                if let Some(doc) = &word.doc {
+                    out.push_str(&format!("<p>{}</p>\n", escape(doc)));
+                }
+                if !word.examples.is_empty() {
+                    out.push_str("<pre>");
+                    for example in &word.examples {
+                        out.push_str(&escape(format!("{} → {}", example.source, example.expected).trim_end()));
+                        out.push('\n');
+                    }
+                    out.push_str("</pre>\n");
+                }
+                if !word.see_also.is_empty() {
+                    let links: Vec<String> = word.see_also.iter()
+                        .map(|name| format!("<a href=\"#{}\"><code>{}</code></a>", anchor("word", name), escape(name)))
+                        .collect();
+                    out.push_str(&format!("<p>See also: {}</p>\n", links.join(", ")));
+                }
+                out.push_str("</article>\n");
+            }
+            out.push_str("</section>\n");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// `code` を <code> にする
+fn inline_html(text: &str) -> String {
+    text.split('`').enumerate()
+        .map(|(i, part)| if i % 2 == 1 { format!("<code>{}</code>", escape(part)) } else { escape(part) })
+        .collect()
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+use crate::testing::Difference;
+
+// ============================================================================
+// エラー型
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LycorisError {
+    // 字句解析エラー
+    Syntax(String),
+    StackUnderflow,
+    // 引数の型が合わない
+    Type(String),
+    // 型は正しいが値が受け付けられない
+    InvalidArgument(String),
+    DivisionByZero,
+    IndexOutOfBounds,
+    UnknownWord(String),
+    // include のソースを読めない
+    Include(String),
+    // 資源制限
+    StepLimitExceeded(u64),
+    CallDepthExceeded(usize),
+    NestingLimitExceeded(usize),
+    StackLimitExceeded(usize),
+    NumberTooLarge(u64),
+    LengthLimitExceeded(usize),
+    // throw で送出されたエラー
+    Thrown { kind: String, message: String },
+    // assert 系のワードの失敗
+    Assertion { message: String, diff: Vec<Difference> },
+    // デバッガで実行をやめた
+    Aborted,
+}
+
+impl LycorisError {
+    // Lycoris 側から error-kind で参照する種類名
+    pub fn kind(&self) -> &str {
+        match self {
+            LycorisError::Syntax(_) => "syntax",
+            LycorisError::StackUnderflow => "stack-underflow",
+            LycorisError::Type(_) => "type",
+            LycorisError::InvalidArgument(_) => "invalid-argument",
+            LycorisError::DivisionByZero => "division-by-zero",
+            LycorisError::IndexOutOfBounds => "index-out-of-bounds",
+            LycorisError::UnknownWord(_) => "unknown-word",
+            LycorisError::Include(_) => "include",
+            LycorisError::StepLimitExceeded(_) => "step-limit",
+            LycorisError::CallDepthExceeded(_) => "call-depth-limit",
+            LycorisError::NestingLimitExceeded(_) => "nesting-limit",
+            LycorisError::StackLimitExceeded(_) => "stack-limit",
+            LycorisError::NumberTooLarge(_) => "number-too-large",
+            LycorisError::LengthLimitExceeded(_) => "length-limit",
+            LycorisError::Thrown { kind, .. } => kind,
+            LycorisError::Assertion { .. } => "assertion",
+            LycorisError::Aborted => "aborted",
+        }
+    }
+
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            LycorisError::StepLimitExceeded(_)
+                | LycorisError::CallDepthExceeded(_)
+                | LycorisError::NestingLimitExceeded(_)
+                | LycorisError::StackLimitExceeded(_)
+                | LycorisError::NumberTooLarge(_)
+                | LycorisError::LengthLimitExceeded(_)
+        )
+    }
+
+    // try で捕捉させないエラー
+    pub fn is_uncatchable(&self) -> bool {
+        self.is_limit() || matches!(self, LycorisError::Aborted)
+    }
+}
+
+impl fmt::Display for LycorisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LycorisError::Syntax(msg) => write!(f, "{}", msg),
+            LycorisError::StackUnderflow => write!(f, "Stack underflow"),
+            LycorisError::Type(msg) => write!(f, "{}", msg),
+            LycorisError::InvalidArgument(msg) => write!(f, "{}", msg),
+            LycorisError::DivisionByZero => write!(f, "Division by zero"),
+            LycorisError::IndexOutOfBounds => write!(f, "Index out of bounds"),
+            LycorisError::UnknownWord(name) => write!(f, "Unknown word: {}", name),
+            LycorisError::Include(msg) => write!(f, "{}", msg),
+            LycorisError::StepLimitExceeded(max) => write!(f, "Step limit exceeded (max {})", max),
+            LycorisError::CallDepthExceeded(max) => write!(f, "Call depth limit exceeded (max {})", max),
+            LycorisError::NestingLimitExceeded(max) => write!(f, "Nested call limit exceeded (max {})", max),
+            LycorisError::StackLimitExceeded(max) => write!(f, "Stack size limit exceeded (max {})", max),
+            LycorisError::NumberTooLarge(max) => write!(f, "Number too large (max {} bits)", max),
+            LycorisError::LengthLimitExceeded(max) => write!(f, "Value length limit exceeded (max {})", max),
+            LycorisError::Thrown { message, .. } => write!(f, "{}", message),
+            LycorisError::Assertion { message, .. } => write!(f, "{}", message),
+            LycorisError::Aborted => write!(f, "Execution aborted"),
+        }
+    }
+}
+
+impl std::error::Error for LycorisError {}
+
+impl From<LycorisError> for JsValue {
+    fn from(err: LycorisError) -> JsValue {
+        JsValue::from_str(&err.to_string())
+    }
+}
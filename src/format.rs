@@ -0,0 +1,264 @@
+use std::fmt;
+
+use crate::syntax::{Node, NodeKind, Span};
+use crate::LycorisError;
+
+// ============================================================================
+// フォーマッタ
+// ============================================================================
+//
+// ソースを空白とコメントも含めて元に戻せる構文木（SyntaxTree）に読み、
+// トークンの間を正規の空白にして書き直す。改行は利用者が入れた位置を残し、
+// 収まらない行は折り返す。入れ子のVector/Setは改行を含むか幅に収まらないときだけ
+// 開き括弧の後で改行して字下げする。
+
+const INDENT: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    // 1行の最大文字数。コメントと1つで収まらないトークンははみ出す
+    pub width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions { width: 80 }
+    }
+}
+
+// 要素の列。表示すると元のソースがそのまま得られる
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SyntaxTree {
+    pub items: Vec<Item>,
+    // 最後の要素の後の空白（グループでは閉じ括弧の前）
+    pub trailing: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    // 要素の前の空白
+    pub leading: String,
+    pub kind: ItemKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemKind {
+    // 数値・文字列・ワードなど（スコーププレフィックスを含むソースの文字列）
+    Token(String),
+    // # から行末まで（改行は含まない）
+    Comment(String),
+    // [...] または {...}
+    Group { open: char, body: SyntaxTree },
+}
+
+fn closing(open: char) -> char {
+    if open == '[' { ']' } else { '}' }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            write!(f, "{}", item.leading)?;
+            match &item.kind {
+                ItemKind::Token(text) | ItemKind::Comment(text) => write!(f, "{}", text)?,
+                ItemKind::Group { open, body } => write!(f, "{}{}{}", open, body, closing(*open))?,
+            }
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+// 字句解析の結果（位置つき）をもとに、間の空白とコメントを拾って構文木にする
+pub(crate) fn syntax_tree(source: &str, program: &[Node]) -> Result<SyntaxTree, LycorisError> {
+    build(source, 0, source.len(), program)
+}
+
+fn build(source: &str, start: usize, end: usize, nodes: &[Node]) -> Result<SyntaxTree, LycorisError> {
+    // `[names] -> [body]` は並べ替えられているのでソースの順に戻す
+    let mut nodes: Vec<&Node> = nodes.iter().collect();
+    nodes.sort_by_key(|node| node.span.start);
+
+    let mut tree = SyntaxTree::default();
+    let mut cursor = start;
+    for node in nodes {
+        let leading = trivia(source, cursor, node.span.start, &mut tree.items)?;
+        let text = &source[node.span.start..node.span.end];
+        let kind = match &node.kind {
+            NodeKind::Vector(children) | NodeKind::Set(children) => {
+                let open = if matches!(node.kind, NodeKind::Vector(_)) { '[' } else { '{' };
+                if text.len() < 2 || !text.ends_with(closing(open)) {
+                    return Err(LycorisError::Syntax(format!("Unclosed {} at position {}", open, node.span.start)));
+                }
+                let body = build(source, node.span.start + 1, node.span.end - 1, children)?;
+                ItemKind::Group { open, body }
+            }
+            _ => ItemKind::Token(text.to_string()),
+        };
+        tree.items.push(Item { leading, kind, span: node.span });
+        cursor = node.span.end;
+    }
+    tree.trailing = trivia(source, cursor, end, &mut tree.items)?;
+    Ok(tree)
+}
+
+// start..end にあるコメントを items に加え、最後のコメントの後の空白を返す
+fn trivia(source: &str, start: usize, end: usize, items: &mut Vec<Item>) -> Result<String, LycorisError> {
+    let mut whitespace = String::new();
+    let mut pos = start;
+    while let Some(c) = source[pos..end].chars().next() {
+        if c.is_whitespace() {
+            whitespace.push(c);
+            pos += c.len_utf8();
+        } else if c == '#' {
+            let len = source[pos..end].find('\n').unwrap_or(end - pos);
+            items.push(Item {
+                leading: std::mem::take(&mut whitespace),
+                kind: ItemKind::Comment(source[pos..pos + len].to_string()),
+                span: Span::new(pos, pos + len),
+            });
+            pos += len;
+        } else {
+            return Err(LycorisError::Syntax(format!("Unexpected text at position {}", pos)));
+        }
+    }
+    Ok(whitespace)
+}
+
+// 1行に書いたときの文字列。コメントや改行を含むグループは1行にできない
+fn flat(item: &Item) -> Option<String> {
+    match &item.kind {
+        ItemKind::Token(text) => Some(text.clone()),
+        ItemKind::Comment(_) => None,
+        ItemKind::Group { open, body } => {
+            let mut parts = Vec::with_capacity(body.items.len());
+            for child in &body.items {
+                if child.leading.contains('\n') {
+                    return None;
+                }
+                parts.push(flat(child)?);
+            }
+            Some(format!("{}{}{}", open, parts.join(" "), closing(*open)))
+        }
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+// 同じ行で続くトークンの幅（空白を含む）。次のグループ・コメント・改行まで
+fn trailer_width(items: &[Item]) -> usize {
+    items.iter()
+        .take_while(|item| !item.leading.contains('\n'))
+        .map_while(|item| match &item.kind {
+            ItemKind::Token(text) => Some(1 + width(text)),
+            _ => None,
+        })
+        .sum()
+}
+
+pub(crate) fn print(tree: &SyntaxTree, options: &FormatOptions) -> String {
+    let mut printer = Printer { width: options.width, out: String::new(), column: 0 };
+    printer.items(&tree.items, 0);
+    if !printer.out.is_empty() {
+        printer.end_line();
+        printer.out.push('\n');
+    }
+    printer.out
+}
+
+struct Printer {
+    width: usize,
+    out: String,
+    column: usize,
+}
+
+impl Printer {
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.column += width(text);
+    }
+
+    fn end_line(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.end_line();
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(indent));
+        self.column = indent;
+    }
+
+    // 先頭の要素は現在の位置から書き、2つ目からは元の改行（空行は1行まで）を残す
+    fn items(&mut self, items: &[Item], indent: usize) {
+        let mut after_comment = false;
+        for (i, item) in items.iter().enumerate() {
+            let flat = flat(item);
+            if i > 0 {
+                let breaks = item.leading.matches('\n').count();
+                if after_comment || breaks > 0 {
+                    if breaks > 1 {
+                        self.end_line();
+                        self.out.push('\n');
+                    }
+                    self.newline(indent);
+                } else {
+                    // 収まらなければ折り返す。折り返しても収まらないグループはその場で改行して字下げする
+                    let wrap = match (&item.kind, &flat) {
+                        (ItemKind::Comment(_), _) | (_, None) => false,
+                        (ItemKind::Group { .. }, Some(text)) => {
+                            self.column + 1 + width(text) > self.width && indent + width(text) <= self.width
+                        }
+                        (_, Some(text)) => self.column + 1 + width(text) > self.width,
+                    };
+                    if wrap {
+                        self.newline(indent);
+                    } else {
+                        self.write(" ");
+                    }
+                }
+            }
+
+            after_comment = false;
+            match &item.kind {
+                ItemKind::Token(text) => self.write(text),
+                ItemKind::Comment(text) => {
+                    self.write(text.trim_end());
+                    after_comment = true;
+                }
+                ItemKind::Group { open, body } => {
+                    // 行頭のグループは後に続くワード（'name' def など）も同じ行に収まるときだけ1行にする
+                    let trailer = if self.column == indent { trailer_width(&items[i + 1..]) } else { 0 };
+                    match flat {
+                        Some(text) if self.column + width(&text) + trailer <= self.width => self.write(&text),
+                        _ => self.group(*open, body, indent),
+                    }
+                }
+            }
+        }
+    }
+
+    fn group(&mut self, open: char, body: &SyntaxTree, indent: usize) {
+        self.write(&open.to_string());
+        let mut items = &body.items[..];
+        // 開き括弧と同じ行のコメントはその行に残す
+        if let Some((first, rest)) = items.split_first() {
+            if let ItemKind::Comment(text) = &first.kind {
+                if !first.leading.contains('\n') {
+                    self.write(" ");
+                    self.write(text.trim_end());
+                    items = rest;
+                }
+            }
+        }
+        if !items.is_empty() {
+            self.newline(indent + INDENT);
+            self.items(items, indent + INDENT);
+        }
+        self.newline(indent);
+        self.write(&closing(open).to_string());
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use crate::module::WordDef;
+use crate::Value;
+
+// ============================================================================
+// 取り消しログ
+// ============================================================================
+//
+// スタックの複製は取らず、セーブポイント時点より下の要素が pop されたときだけ
+// その値を記録する。スタックの下側はこれ以外に書き換わらないので、
+// 記録を逆順に戻して末尾を切り詰めればセーブポイント時点の状態に戻る。
+
+enum UndoEntry {
+    Popped(usize, Value),
+    Defined(String, Option<WordDef>),
+    OutputCleared(Vec<String>),
+}
+
+struct Savepoint {
+    log_len: usize,
+    stack_len: usize,
+    output_len: usize,
+    prev_watermark: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct Journal {
+    log: Vec<UndoEntry>,
+    savepoints: Vec<Savepoint>,
+    // この位置より下からの pop を記録する
+    watermark: usize,
+}
+
+impl Journal {
+    pub(crate) fn begin(&mut self, stack_len: usize, output_len: usize) {
+        self.savepoints.push(Savepoint {
+            log_len: self.log.len(),
+            stack_len,
+            output_len,
+            prev_watermark: self.watermark,
+        });
+        self.watermark = stack_len;
+    }
+
+    pub(crate) fn commit(&mut self) {
+        if let Some(savepoint) = self.savepoints.pop() {
+            if self.savepoints.is_empty() {
+                self.log.clear();
+                self.watermark = 0;
+            } else {
+                // 外側のセーブポイントのために記録は残す
+                self.watermark = self.watermark.min(savepoint.prev_watermark);
+            }
+        }
+    }
+
+    pub(crate) fn rollback(
+        &mut self,
+        stack: &mut Vec<Value>,
+        dictionary: &mut HashMap<String, WordDef>,
+        output: &mut Vec<String>,
+    ) {
+        let Some(savepoint) = self.savepoints.pop() else {
+            return;
+        };
+
+        while self.log.len() > savepoint.log_len {
+            match self.log.pop() {
+                Some(UndoEntry::Popped(index, value)) => {
+                    stack.truncate(index);
+                    stack.push(value);
+                }
+                Some(UndoEntry::Defined(name, Some(previous))) => {
+                    dictionary.insert(name, previous);
+                }
+                Some(UndoEntry::Defined(name, None)) => {
+                    dictionary.remove(&name);
+                }
+                Some(UndoEntry::OutputCleared(previous)) => {
+                    *output = previous;
+                }
+                None => break,
+            }
+        }
+
+        stack.truncate(savepoint.stack_len);
+        output.truncate(savepoint.output_len);
+        self.watermark = savepoint.prev_watermark;
+    }
+
+    // スタックだけをセーブポイント時点に戻し、辞書と出力の変更は残す
+    pub(crate) fn rollback_stack(&mut self, stack: &mut Vec<Value>) {
+        let Some(savepoint) = self.savepoints.pop() else {
+            return;
+        };
+
+        let entries = self.log.split_off(savepoint.log_len);
+        let mut kept = Vec::new();
+        for entry in entries.into_iter().rev() {
+            match entry {
+                UndoEntry::Popped(index, value) => {
+                    stack.truncate(index);
+                    stack.push(value);
+                }
+                other => kept.push(other),
+            }
+        }
+        stack.truncate(savepoint.stack_len);
+        self.watermark = savepoint.prev_watermark;
+
+        // 外側のセーブポイントが取り消せるように残りの記録は戻しておく
+        if self.savepoints.is_empty() {
+            self.log.clear();
+        } else {
+            self.log.extend(kept.into_iter().rev());
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        !self.savepoints.is_empty()
+    }
+
+    // pop された位置が記録対象かどうか
+    pub(crate) fn tracks(&self, index: usize) -> bool {
+        index < self.watermark
+    }
+
+    pub(crate) fn record_pop(&mut self, index: usize, value: Value) {
+        self.log.push(UndoEntry::Popped(index, value));
+        self.watermark = index;
+    }
+
+    pub(crate) fn record_define(&mut self, name: String, previous: Option<WordDef>) {
+        if self.is_active() {
+            self.log.push(UndoEntry::Defined(name, previous));
+        }
+    }
+
+    pub(crate) fn record_output_clear(&mut self, previous: Vec<String>) {
+        if self.is_active() {
+            self.log.push(UndoEntry::OutputCleared(previous));
+        }
+    }
+}
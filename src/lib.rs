@@ -1,11 +1,49 @@
 use wasm_bindgen::prelude::*;
 use num_bigint::BigInt;
 use num_rational::BigRational;
-use num_traits::{Zero, One, ToPrimitive, Signed};
+use num_traits::{Zero, ToPrimitive, Signed};
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 use std::str::FromStr;
 
+mod builtins;
+mod check;
+mod debug;
+mod docgen;
+mod error;
+mod format;
+mod journal;
+mod lint;
+pub mod lsp;
+mod module;
+mod profile;
+mod syntax;
+mod testing;
+mod trace;
+
+pub use check::{Diagnostic, Severity};
+pub use debug::{Breakpoint, CallFrame, DebugAction, DebugHandler, DebugReply, DebugSession, Pause};
+pub use docgen::{Example, Reference, Scopes, WordDoc};
+pub use error::LycorisError;
+pub use format::{FormatOptions, Item, ItemKind, SyntaxTree};
+pub use lint::Lint;
+pub use module::{SourceResolver, WordInfo};
+pub use profile::{Profile, ProfileClock, SystemClock, WordProfile};
+pub use syntax::{line_col, Node, NodeKind, Span};
+pub use testing::{Difference, TestResult};
+pub use trace::{Trace, TraceStep};
+use builtins::{builtin_info, builtins, takes_quotation};
+use debug::Debugger;
+use journal::Journal;
+use module::{qualify, Namespaces, StackEffect, WordDef};
+use profile::Profiler;
+use syntax::SourceMap;
+use testing::{diff_values, test_spans, TestCase};
+use trace::Tracer;
+
 // ============================================================================
 // Value型の定義
 // ============================================================================
@@ -17,6 +55,28 @@ pub enum Value {
     Bool(bool),
     Nil,
     Vector(Vec<Value>),
+    Word(String, Scope),
+    Set(BTreeSet<Value>),
+    Error { kind: String, message: String },
+}
+
+// 後置形の束縛 `[names] [body] ->` は中置形 `[names] -> [body]` で表示する。
+// 後置形のすぐ後にベクタが続くと、読み直したときにそのベクタが本体として扱われるため
+fn display_code(items: &[Value]) -> String {
+    let mut parts = Vec::with_capacity(items.len());
+    let mut i = 0;
+    while i < items.len() {
+        if let [names @ Value::Vector(_), body @ Value::Vector(_), Value::Word(arrow, Scope::Local), ..] = &items[i..] {
+            if arrow == "->" {
+                parts.push(format!("{} -> {}", names.to_display_string(), body.to_display_string()));
+                i += 3;
+                continue;
+            }
+        }
+        parts.push(items[i].to_display_string());
+        i += 1;
+    }
+    parts.join(" ")
 }
 
 impl Value {
@@ -32,15 +92,111 @@ impl Value {
             Value::String(s) => format!("'{}'", s),
             Value::Bool(b) => b.to_string(),
             Value::Nil => "nil".to_string(),
-            Value::Vector(v) => {
-                let items: Vec<String> = v.iter().map(|val| val.to_display_string()).collect();
-                format!("[{}]", items.join(" "))
+            Value::Vector(v) => format!("[{}]", display_code(v)),
+            Value::Word(name, scope) => format!("{}{}", scope.prefix(), name),
+            Value::Set(set) => {
+                let items: Vec<String> = set.iter().map(|val| val.to_display_string()).collect();
+                format!("{{{}}}", items.join(" "))
             }
+            Value::Error { kind, message } => format!("<{}: {}>", kind, message),
+        }
+    }
+
+    // tostring/join用: 文字列は引用符なしで表示
+    pub fn to_plain_string(&self) -> String {
+        match self {
+            Value::String(s) => s.clone(),
+            other => other.to_display_string(),
         }
     }
 
     pub fn is_function_name(&self) -> bool {
-        matches!(self, Value::String(_))
+        matches!(self, Value::Word(_, _))
+    }
+
+    // 偽とみなす値: false, nil, 0, 空文字列, 空Vector
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Nil => false,
+            Value::Rational(r) => !r.is_zero(),
+            Value::String(s) => !s.is_empty(),
+            Value::Vector(v) => !v.is_empty(),
+            Value::Word(_, _) => true,
+            Value::Set(set) => !set.is_empty(),
+            Value::Error { .. } => true,
+        }
+    }
+
+    // 型の順序: nil < bool < number < string < word < vector < set < error
+    fn type_rank(&self) -> u8 {
+        match self {
+            Value::Nil => 0,
+            Value::Bool(_) => 1,
+            Value::Rational(_) => 2,
+            Value::String(_) => 3,
+            Value::Word(_, _) => 4,
+            Value::Vector(_) => 5,
+            Value::Set(_) => 6,
+            Value::Error { .. } => 7,
+        }
+    }
+}
+
+// ============================================================================
+// 等価性・順序・ハッシュ
+// ============================================================================
+
+// 異なる型は type_rank で比較し、同じ型は値で比較する。
+// 数値は大小、文字列はコードポイント順、VectorとSetは辞書式順序、
+// ワードは名前→スコープ、エラーは種類→メッセージの順で比較する。
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Rational(a), Value::Rational(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Nil, Value::Nil) => Ordering::Equal,
+            (Value::Vector(a), Value::Vector(b)) => a.cmp(b),
+            (Value::Word(a, sa), Value::Word(b, sb)) => a.cmp(b).then(sa.cmp(sb)),
+            (Value::Set(a), Value::Set(b)) => a.cmp(b),
+            (
+                Value::Error { kind: ka, message: ma },
+                Value::Error { kind: kb, message: mb },
+            ) => ka.cmp(kb).then(ma.cmp(mb)),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_rank().hash(state);
+        match self {
+            // BigRationalのハッシュは約分後の値に対して一貫している
+            Value::Rational(r) => r.hash(state),
+            Value::String(s) => s.hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::Nil => {}
+            Value::Vector(v) => v.hash(state),
+            Value::Word(name, scope) => {
+                name.hash(state);
+                scope.hash(state);
+            }
+            Value::Set(set) => set.hash(state),
+            Value::Error { kind, message } => {
+                kind.hash(state);
+                message.hash(state);
+            }
+        }
     }
 }
 
@@ -48,7 +204,7 @@ impl Value {
 // スコープ指定
 // ============================================================================
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Scope {
     Local,   // デフォルト: スタックトップのN個
     Map,     // @: Vector各要素に適用
@@ -56,6 +212,17 @@ pub enum Scope {
     Global,  // #: スタック全体を対象
 }
 
+impl Scope {
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Scope::Local => "",
+            Scope::Map => "@",
+            Scope::Reduce => "*",
+            Scope::Global => "#",
+        }
+    }
+}
+
 // ============================================================================
 // トークン
 // ============================================================================
@@ -66,6 +233,53 @@ pub enum Token {
     Function(String, Scope),
 }
 
+impl Token {
+    // 関数名はワードとして保存（文字列リテラルと区別する）
+    fn into_value(self) -> Value {
+        match self {
+            Token::Value(v) => v,
+            Token::Function(name, scope) => Value::Word(name, scope),
+        }
+    }
+}
+
+// 実行するコード（ワード定義の本体やクオーテーション）。フレーム間で共有する
+pub(crate) type Code = Rc<[Value]>;
+
+// 実行中のコードと次に実行する位置
+struct Frame {
+    code: Code,
+    pc: usize,
+    // 名前解決に使うモジュール。None はトップレベルのコードで、開いている module を使う
+    module: Option<Rc<str>>,
+    // トレース中とデバッグ中だけ使う: 要素のソース上の位置と、このフレームを積んだトークンの位置
+    spans: Option<Rc<[Span]>>,
+    call_span: Option<Span>,
+    // プロファイル中とデバッグ中だけ使う: このフレームが本体を実行しているユーザー定義ワード
+    word: Option<Rc<str>>,
+    // ユーザー定義ワードの呼び出しのフレーム（末尾呼び出しで置き換えたフレームからも引き継ぐ）
+    call: bool,
+    // トレース中だけ使う: このフレームを積んだトークンのステップ。フレームが終わったときの
+    // スタックを実行後として記録する（末尾呼び出しで置き換えたフレームからも引き継ぐ）
+    traced: Vec<usize>,
+    // このフレームで実行している include のキー。フレームが最後まで終わったら include 済みにする
+    // （末尾呼び出しで置き換えたフレームからも引き継ぐ）
+    includes: Vec<Rc<str>>,
+}
+
+impl Frame {
+    // 実行中のトークンのソース上の位置。位置のないコードでは呼び出し元の位置
+    fn span(&self) -> Option<Span> {
+        self.token_span().or(self.call_span)
+    }
+
+    // 実行中のトークン自身の位置
+    fn token_span(&self) -> Option<Span> {
+        let index = self.pc.checked_sub(1)?;
+        self.spans.as_ref().and_then(|spans| spans.get(index).copied())
+    }
+}
+
 // ============================================================================
 // トライ木辞書
 // ============================================================================
@@ -73,15 +287,31 @@ pub enum Token {
 #[derive(Debug, Clone)]
 struct TrieNode {
     children: HashMap<char, TrieNode>,
-    is_word: bool,
+    // このノードで終わる単語の登録数（同じ短縮名を複数のモジュールが登録することがある）
+    count: usize,
 }
 
 impl TrieNode {
     fn new() -> Self {
         TrieNode {
             children: HashMap::new(),
-            is_word: false,
+            count: 0,
+        }
+    }
+
+    // 単語を1つ取り除き、このノードが不要になったら true
+    fn remove(&mut self, mut chars: std::str::Chars) -> bool {
+        match chars.next() {
+            None => self.count = self.count.saturating_sub(1),
+            Some(ch) => {
+                if let Some(child) = self.children.get_mut(&ch) {
+                    if child.remove(chars) {
+                        self.children.remove(&ch);
+                    }
+                }
+            }
         }
+        self.count == 0 && self.children.is_empty()
     }
 }
 
@@ -90,6 +320,12 @@ pub struct TrieDict {
     root: TrieNode,
 }
 
+impl Default for TrieDict {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TrieDict {
     pub fn new() -> Self {
         TrieDict {
@@ -102,7 +338,68 @@ impl TrieDict {
         for ch in word.chars() {
             node = node.children.entry(ch).or_insert_with(TrieNode::new);
         }
-        node.is_word = true;
+        node.count += 1;
+    }
+
+    pub fn remove(&mut self, word: &str) {
+        self.root.remove(word.chars());
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        let mut node = &self.root;
+        for ch in word.chars() {
+            match node.children.get(&ch) {
+                Some(next) => node = next,
+                None => return false,
+            }
+        }
+        node.count > 0
+    }
+
+    // text の先頭に一致する単語をすべて返す（短い順）
+    pub fn prefixes(&self, text: &str) -> Vec<String> {
+        let mut node = &self.root;
+        let mut found = Vec::new();
+        let mut current = String::new();
+
+        for ch in text.chars() {
+            match node.children.get(&ch) {
+                Some(next) => {
+                    current.push(ch);
+                    node = next;
+                    if node.count > 0 {
+                        found.push(current.clone());
+                    }
+                }
+                None => break,
+            }
+        }
+
+        found
+    }
+
+    // prefix で始まる単語（整列済み）
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        let mut node = &self.root;
+        for ch in prefix.chars() {
+            match node.children.get(&ch) {
+                Some(next) => node = next,
+                None => return Vec::new(),
+            }
+        }
+
+        let mut found = Vec::new();
+        let mut pending = vec![(node, prefix.to_string())];
+        while let Some((node, word)) = pending.pop() {
+            if node.count > 0 {
+                found.push(word.clone());
+            }
+            for (ch, child) in &node.children {
+                pending.push((child, format!("{}{}", word, ch)));
+            }
+        }
+        found.sort();
+        found
     }
 
     // 最長一致検索
@@ -115,7 +412,7 @@ impl TrieDict {
             if let Some(next) = node.children.get(&ch) {
                 current.push(ch);
                 node = next;
-                if node.is_word {
+                if node.count > 0 {
                     longest = Some(current.clone());
                 }
             } else {
@@ -127,6 +424,37 @@ impl TrieDict {
     }
 }
 
+// ============================================================================
+// 資源制限
+// ============================================================================
+
+// None は無制限を表す
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub max_steps: Option<u64>,
+    pub max_call_depth: Option<usize>,
+    pub max_stack: Option<usize>,
+    pub max_number_bits: Option<u64>,
+    // 文字列（バイト数）・ベクタ・集合の長さ
+    pub max_length: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            // 呼び出しはヒープ上のフレームで管理するので既定では無制限
+            max_call_depth: None,
+            max_stack: None,
+            max_number_bits: Some(1 << 20),
+            max_length: Some(1 << 20),
+        }
+    }
+}
+
+// 高階ワードからの呼び出しはネイティブ/wasmのスタックを使うので入れ子の深さを制限する
+const MAX_NESTING: usize = 256;
+
 // ============================================================================
 // インタープリタ
 // ============================================================================
@@ -134,9 +462,42 @@ impl TrieDict {
 #[wasm_bindgen]
 pub struct Interpreter {
     stack: Vec<Value>,
-    dictionary: HashMap<String, Vec<Token>>,
+    // 完全名（math.square など）で引く
+    dictionary: HashMap<String, WordDef>,
+    namespaces: Namespaces,
+    // test で登録した順
+    tests: Vec<TestCase>,
+    resolver: Option<Box<dyn SourceResolver>>,
     builtin_dict: TrieDict,
+    // ユーザー定義ワードの完全名と短縮名
+    user_words: TrieDict,
     output: Vec<String>,
+    limits: Limits,
+    steps: u64,
+    // 呼び出しフレーム。末尾呼び出しでは呼び出し元のフレームを再利用する
+    frames: Vec<Frame>,
+    // 現在の run_code が所有するフレームの開始位置
+    frame_base: usize,
+    // run_code の入れ子の深さ
+    nesting: usize,
+    // 実行中のユーザー定義ワードの呼び出しの数（max_call_depth と比べる）
+    call_depth: usize,
+    // エラー時に execute 前の状態へ戻すか
+    atomic: bool,
+    journal: Journal,
+    // トレースモードのときだけ Some
+    tracer: Option<Tracer>,
+    // プロファイル中だけ Some
+    profiler: Option<Profiler>,
+    debugger: Debugger,
+    // トレース中かデバッガがあるときだけ、実行中のソースの位置の表
+    source_map: Option<SourceMap>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
@@ -146,42 +507,89 @@ impl Interpreter {
         let mut builtin_dict = TrieDict::new();
         
         // 組み込みワードを登録
-        let builtins = vec![
-            "add", "sub", "mul", "div", "pow", "mod",
-            "dup", "drop", "swap", "over", "rot",
-            "vec", "unpack", "nth", "slice", "concat", "length",
-            "run", "step", "quote",
-            "def", "undef", "words",
-            "print", "clear",
-            "eq", "lt", "gt", "le", "ge",
-        ];
-        
-        for word in builtins {
-            builtin_dict.insert(word);
+        for info in builtins() {
+            builtin_dict.insert(info.name);
         }
 
         Interpreter {
             stack: Vec::new(),
             dictionary: HashMap::new(),
+            namespaces: Namespaces::default(),
+            tests: Vec::new(),
+            resolver: None,
             builtin_dict,
+            user_words: TrieDict::new(),
             output: Vec::new(),
+            limits: Limits::default(),
+            steps: 0,
+            frames: Vec::new(),
+            frame_base: 0,
+            nesting: 0,
+            call_depth: 0,
+            atomic: true,
+            journal: Journal::default(),
+            tracer: None,
+            profiler: None,
+            debugger: Debugger::default(),
+            source_map: None,
         }
     }
 
     pub fn execute(&mut self, input: String) -> Result<String, JsValue> {
-        let tokens = self.tokenize(&input)?;
-        
-        for token in tokens {
-            self.execute_token(token)?;
-        }
-        
-        Ok(self.output.join("\n"))
+        Ok(self.eval(&input)?)
+    }
+
+    // 制限値の設定（undefined で無制限）
+    pub fn set_max_steps(&mut self, max: Option<u32>) {
+        self.limits.max_steps = max.map(u64::from);
+    }
+
+    pub fn set_max_call_depth(&mut self, max: Option<u32>) {
+        self.limits.max_call_depth = max.map(|m| m as usize);
+    }
+
+    pub fn set_max_stack(&mut self, max: Option<u32>) {
+        self.limits.max_stack = max.map(|m| m as usize);
+    }
+
+    pub fn set_max_number_bits(&mut self, max: Option<u32>) {
+        self.limits.max_number_bits = max.map(u64::from);
+    }
+
+    pub fn set_max_length(&mut self, max: Option<u32>) {
+        self.limits.max_length = max.map(|m| m as usize);
+    }
+
+    // false にするとエラー前に実行した分を残す（部分コミット）
+    pub fn set_atomic(&mut self, atomic: bool) {
+        self.atomic = atomic;
+    }
+
+    // include のソースを返すコールバック: (path) => string
+    pub fn set_include_resolver(&mut self, callback: js_sys::Function) {
+        self.resolver = Some(Box::new(JsResolver(callback)));
+    }
+
+    fn tokenize(&self, input: &str) -> Result<Vec<Token>, LycorisError> {
+        Ok(self.parse(input)?.into_iter().map(Node::into_token).collect())
+    }
+
+    fn parse(&self, input: &str) -> Result<Vec<Node>, LycorisError> {
+        let pending = scan_definitions(input);
+        self.parse_nodes(input, &pending)
     }
 
-    fn tokenize(&self, input: &str) -> Result<Vec<Token>, JsValue> {
-        let mut tokens = Vec::new();
+    // pending: この入力の中で def される名前
+    fn parse_nodes(&self, input: &str, pending: &TrieDict) -> Result<Vec<Node>, LycorisError> {
+        let mut tokens: Vec<Node> = Vec::new();
+        // 開いている括弧: (括弧, 文字位置, 括弧より前のトークン)。入れ子は MAX_NESTING まで
+        let mut open: Vec<(char, usize, Vec<Node>)> = Vec::new();
         let mut pos = 0;
         let chars: Vec<char> = input.chars().collect();
+        // 文字位置からバイト位置への対応（残りのテキストを毎回コピーしない）
+        let offsets: Vec<usize> = input.char_indices().map(|(i, _)| i).collect();
+        let byte_at = |pos: usize| offsets.get(pos).copied().unwrap_or(input.len());
+        let span = |start: usize, end: usize| Span::new(byte_at(start), byte_at(end));
 
         while pos < chars.len() {
             let ch = chars[pos];
@@ -202,6 +610,7 @@ impl Interpreter {
 
             // 文字列リテラル
             if ch == '\'' {
+                let start = pos;
                 pos += 1;
                 let mut string = String::new();
                 while pos < chars.len() && chars[pos] != '\'' {
@@ -209,37 +618,64 @@ impl Interpreter {
                     pos += 1;
                 }
                 if pos >= chars.len() {
-                    return Err(JsValue::from_str("Unterminated string"));
+                    return Err(LycorisError::Syntax("Unterminated string".to_string()));
                 }
                 pos += 1; // closing '
-                tokens.push(Token::Value(Value::String(string)));
+                tokens.push(Node::new(NodeKind::Literal(Value::String(string)), span(start, pos)));
                 continue;
             }
 
-            // Vectorリテラル
-            if ch == '[' {
-                let start = pos;
-                let mut depth = 0;
-                while pos < chars.len() {
-                    if chars[pos] == '[' {
-                        depth += 1;
-                    } else if chars[pos] == ']' {
-                        depth -= 1;
-                        if depth == 0 {
-                            pos += 1;
-                            break;
-                        }
+            // Vectorリテラル / Setリテラル。開いた括弧の外側のトークンを open に退避して内側を読む
+            if ch == '[' || ch == '{' {
+                if open.len() >= MAX_NESTING {
+                    return Err(LycorisError::Syntax(format!(
+                        "Nesting deeper than {} at position {}", MAX_NESTING, byte_at(pos)
+                    )));
+                }
+                open.push((ch, pos, std::mem::take(&mut tokens)));
+                pos += 1;
+                continue;
+            }
+
+            if ch == ']' || ch == '}' {
+                let opener = if ch == ']' { '[' } else { '{' };
+                let (start, outer) = match open.pop() {
+                    Some((top, start, outer)) if top == opener => (start, outer),
+                    // 外側に対応する括弧があるなら、閉じていない内側の括弧を報告する
+                    Some((top, start, _)) if open.iter().any(|&(c, _, _)| c == opener) => {
+                        return Err(LycorisError::Syntax(format!("Unclosed {} at position {}", top, byte_at(start))));
                     }
-                    pos += 1;
+                    _ => return Err(LycorisError::Syntax(format!("Unknown token at position {}", pos))),
+                };
+                pos += 1;
+                let elements = std::mem::replace(&mut tokens, outer);
+                let kind = if ch == ']' {
+                    NodeKind::Vector(elements)
+                } else {
+                    NodeKind::Set(elements)
+                };
+                let node = Node::new(kind, span(start, pos));
+                // `[names] -> [body]` は後置形 `[names] [body] ->` に並べ替える。
+                // 並べ替え済みの -> は本体より前にあるので、続くベクタは動かさない。
+                // 名前のベクタに続かない -> も並べ替えない（表示して読み直すと形が変わるため）
+                let arrow_pending = match tokens.as_slice() {
+                    [.., previous, arrow] => {
+                        matches!(previous.kind, NodeKind::Vector(_)) && arrow.span.start > previous.span.start
+                    }
+                    _ => false,
+                };
+                if ch == ']' && arrow_pending && tokens.last().and_then(Node::word) == Some("->") {
+                    let arrow = tokens.pop();
+                    tokens.push(node);
+                    tokens.extend(arrow);
+                } else {
+                    tokens.push(node);
                 }
-                
-                let vector_str: String = chars[start..pos].iter().collect();
-                let vector = self.parse_vector(&vector_str)?;
-                tokens.push(Token::Value(vector));
                 continue;
             }
 
             // スコーププレフィックスの検出
+            let start = pos;
             let scope = match ch {
                 '@' => {
                     pos += 1;
@@ -257,215 +693,431 @@ impl Interpreter {
             };
 
             // 現在位置から残りのテキスト
-            let remaining: String = chars[pos..].iter().collect();
+            let remaining = offsets.get(pos).map_or("", |&offset| &input[offset..]);
 
-            // 数値リテラル
-            if let Some(num_token) = self.try_parse_number(&remaining) {
-                let num_len = self.get_number_length(&remaining);
+            // 数値リテラル（数値は空白や括弧をまたがないので、その手前までを調べる）
+            let run = &remaining[..remaining.find(|c| !is_name_char(c)).unwrap_or(remaining.len())];
+            let num_len = number_length(run);
+            if num_len > 0 {
+                let num_token = self.parse_number(&run[..num_len])?;
                 pos += num_len;
-                tokens.push(Token::Value(num_token));
+                tokens.push(Node::new(NodeKind::Literal(num_token), span(start, pos)));
                 continue;
             }
 
-            // 予約語
-            if remaining.starts_with("true") {
-                pos += 4;
-                tokens.push(Token::Value(Value::Bool(true)));
-                continue;
-            }
-            if remaining.starts_with("false") {
-                pos += 5;
-                tokens.push(Token::Value(Value::Bool(false)));
-                continue;
-            }
-            if remaining.starts_with("nil") {
-                pos += 3;
-                tokens.push(Token::Value(Value::Nil));
+            // 組み込みワードとユーザー定義ワードを合わせた最長一致。
+            // 組み込みワードと同名のワードは定義できないので、同じ長さなら組み込みワードになる
+            let builtin = self.builtin_dict.longest_match(remaining);
+            let builtin_len = builtin.as_ref().map_or(0, |w| w.len());
+            let user = self.find_user_word(remaining, pending);
+            let user_len = user.as_ref().map_or(0, |w| w.len());
+
+            // 予約語（より長い組み込みワードがあればそちらを優先: truthy など）
+            let reserved = [
+                ("true", Value::Bool(true)),
+                ("false", Value::Bool(false)),
+                ("nil", Value::Nil),
+            ];
+            if let Some((word, value)) = reserved.into_iter()
+                .find(|(word, _)| remaining.starts_with(word) && word.len() >= builtin_len.max(user_len))
+            {
+                pos += word.len();
+                tokens.push(Node::new(NodeKind::Literal(value), span(start, pos)));
                 continue;
             }
 
-            // 辞書の最長一致
-            if let Some(func_name) = self.builtin_dict.longest_match(&remaining) {
-                pos += func_name.len();
-                tokens.push(Token::Function(func_name, scope));
+            if let Some(func_name) = user.filter(|_| user_len > builtin_len).or(builtin) {
+                pos += func_name.chars().count();
+                tokens.push(Node::new(NodeKind::Word(func_name, scope), span(start, pos)));
                 continue;
             }
 
-            // カスタムワード
-            if let Some(func_name) = self.find_custom_word(&remaining) {
-                pos += func_name.len();
-                tokens.push(Token::Function(func_name, scope));
+            // 未定義の名前は実行時に解決する（定義前の再帰呼び出しなど）
+            let name: String = remaining.chars().take_while(|c| is_name_char(*c)).collect();
+            if !name.is_empty() {
+                pos += name.chars().count();
+                tokens.push(Node::new(NodeKind::Word(name, scope), span(start, pos)));
                 continue;
             }
 
-            return Err(JsValue::from_str(&format!("Unknown token at position {}", pos)));
+            return Err(LycorisError::Syntax(format!("Unknown token at position {}", pos)));
         }
 
+        if let Some(&(ch, start, _)) = open.first() {
+            return Err(LycorisError::Syntax(format!("Unclosed {} at position {}", ch, byte_at(start))));
+        }
         Ok(tokens)
     }
 
-    fn try_parse_number(&self, text: &str) -> Option<Value> {
-        // 科学記法
-        if let Some(e_pos) = text.find(|c| c == 'e' || c == 'E') {
-            let base_str = &text[..e_pos];
-            let exp_str = &text[e_pos + 1..];
-            
-            if let (Some(base), Some(exp)) = (self.try_parse_simple_number(base_str), exp_str.chars().take_while(|c| c.is_numeric() || *c == '-').collect::<String>().parse::<i32>().ok()) {
-                if let Value::Rational(base_rat) = base {
-                    let multiplier = BigRational::from_integer(
-                        BigInt::from(10).pow(exp.abs() as u32)
-                    );
-                    let result = if exp >= 0 {
-                        base_rat * multiplier
-                    } else {
-                        base_rat / multiplier
-                    };
-                    return Some(Value::Rational(result));
+    // number_length で切り出した数値リテラルの値。小数は10進のまま正確に分数にする
+    fn parse_number(&self, text: &str) -> Result<Value, LycorisError> {
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(i) => (&text[..i], Some(&text[i + 1..])),
+            None => (text, None),
+        };
+
+        let mut value = if let Some((numer, denom)) = mantissa.split_once('/') {
+            let denom = BigInt::from_str(denom).map_err(|_| invalid_number(text))?;
+            if denom.is_zero() {
+                return Err(LycorisError::DivisionByZero);
+            }
+            BigRational::new(BigInt::from_str(numer).map_err(|_| invalid_number(text))?, denom)
+        } else {
+            let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+            let numer = BigInt::from_str(&format!("{}{}", whole, fraction)).map_err(|_| invalid_number(text))?;
+            BigRational::new(numer, BigInt::from(10).pow(fraction.len() as u32))
+        };
+
+        if let Some(exponent) = exponent {
+            let (negative, digits) = match exponent.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, exponent.strip_prefix('+').unwrap_or(exponent)),
+            };
+            let power = digits.parse::<u32>().ok();
+            // 10^n は 3n ビット以上になるので計算する前に弾く
+            if let Some(max) = self.limits.max_number_bits {
+                if power.is_none_or(|power| u64::from(power) * 3 > max) {
+                    return Err(LycorisError::NumberTooLarge(max));
                 }
             }
+            let power = power.ok_or_else(|| LycorisError::InvalidArgument("Exponent out of range".to_string()))?;
+            let multiplier = BigRational::from_integer(BigInt::from(10).pow(power));
+            value = if negative { value / multiplier } else { value * multiplier };
         }
-
-        // 通常の数値
-        self.try_parse_simple_number(text)
+        Ok(Value::Rational(value))
     }
 
-    fn try_parse_simple_number(&self, text: &str) -> Option<Value> {
-        // 分数
-        if let Some(slash_pos) = text.find('/') {
-            let num_str = &text[..slash_pos];
-            let rest = &text[slash_pos + 1..];
-            let den_str: String = rest.chars().take_while(|c| c.is_numeric()).collect();
-            
-            if let (Ok(num), Ok(den)) = (BigInt::from_str(num_str), BigInt::from_str(&den_str)) {
-                return Some(Value::Rational(BigRational::new(num, den)));
-            }
-        }
+    // 見えるユーザー定義ワードと、この入力で定義されるワードの最長一致
+    fn find_user_word(&self, text: &str, pending: &TrieDict) -> Option<String> {
+        // user_words には短縮名も入っているので、開いているモジュールと use から見えるものに絞る
+        let current = self.namespaces.current();
+        let defined = self.user_words.prefixes(text).into_iter().rev().find(|word| {
+            self.namespaces.candidates(word, &current).iter()
+                .any(|candidate| self.dictionary.contains_key(candidate))
+        });
+        defined.into_iter().chain(pending.longest_match(text)).max_by_key(|word| word.len())
+    }
 
-        // 小数
-        if text.contains('.') {
-            let num_str: String = text.chars().take_while(|c| c.is_numeric() || *c == '.' || *c == '-').collect();
-            if let Ok(f) = num_str.parse::<f64>() {
-                return Some(self.float_to_rational(f));
-            }
+    // 完全名と、モジュール内のワードの短縮名（a.b.c なら b.c と c）を索引に入れる
+    fn index_word(&mut self, key: &str) {
+        self.user_words.insert(key);
+        for (i, _) in key.match_indices('.') {
+            self.user_words.insert(&key[i + 1..]);
         }
+    }
 
-        // 整数
-        let num_str: String = text.chars().take_while(|c| c.is_numeric() || *c == '-').collect();
-        if let Ok(n) = BigInt::from_str(&num_str) {
-            return Some(Value::Rational(BigRational::from_integer(n)));
+    fn unindex_word(&mut self, key: &str) {
+        self.user_words.remove(key);
+        for (i, _) in key.match_indices('.') {
+            self.user_words.remove(&key[i + 1..]);
         }
-
-        None
     }
 
-    fn get_number_length(&self, text: &str) -> usize {
-        if text.contains('e') || text.contains('E') {
-            // 科学記法の長さ
-            text.chars().take_while(|c| c.is_numeric() || *c == '.' || *c == '-' || *c == 'e' || *c == 'E').count()
-        } else if text.contains('/') {
-            // 分数の長さ
-            let slash_pos = text.find('/').unwrap();
-            slash_pos + 1 + text[slash_pos + 1..].chars().take_while(|c| c.is_numeric()).count()
-        } else {
-            // 通常の数値の長さ
-            text.chars().take_while(|c| c.is_numeric() || *c == '.' || *c == '-').count()
+    fn execute_function(&mut self, name: &str, scope: Scope) -> Result<(), LycorisError> {
+        match scope {
+            Scope::Local => self.execute_local(name),
+            Scope::Map => self.execute_map(name),
+            Scope::Reduce => self.execute_reduce(name),
+            Scope::Global => self.execute_global(name),
         }
     }
 
-    fn float_to_rational(&self, f: f64) -> Value {
-        let s = format!("{:.10}", f);
-        let parts: Vec<&str> = s.split('.').collect();
-        
-        if parts.len() == 1 {
-            return Value::Rational(BigRational::from_integer(BigInt::from(f as i64)));
+    // 実行制御とカスタムワードは Rust の再帰ではなくフレームを積んで実行する。
+    // 高階ワードを経由しない限り、ユーザーの再帰の深さはヒープだけで決まる
+    fn execute_local(&mut self, name: &str) -> Result<(), LycorisError> {
+        self.tick(1)?;
+        if matches!(name, "run" | "->" | "match" | "try" | "finally" | "if" | "ifelse" | "include") {
+            return self.execute_control(name);
         }
-        
-        let integer_part = parts[0].parse::<i64>().unwrap_or(0);
-        let decimal_part = parts[1].trim_end_matches('0');
-        
-        if decimal_part.is_empty() {
-            return Value::Rational(BigRational::from_integer(BigInt::from(integer_part)));
+        if self.builtin_dict.contains(name) {
+            return self.execute_builtin(name);
+        }
+
+        // カスタムワード
+        // 実行に要るのは本体とモジュールだけなので、説明文や例は複製しない
+        match self.resolve_word(name).map(|def| (def.code.clone(), def.module.clone())) {
+            Some((code, module)) => {
+                self.push_frame_in(code, Some(module), true)?;
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.call(name);
+                }
+                if self.profiler.is_some() || self.debugger.attached() {
+                    if let Some(frame) = self.frames.last_mut() {
+                        frame.word = Some(name.into());
+                    }
+                }
+                Ok(())
+            }
+            None => Err(LycorisError::UnknownWord(name.to_string())),
         }
-        
-        let decimal_value = decimal_part.parse::<i64>().unwrap_or(0);
-        let denominator = 10_i64.pow(decimal_part.len() as u32);
-        let numerator = integer_part * denominator + decimal_value;
-        
-        Value::Rational(BigRational::new(
-            BigInt::from(numerator),
-            BigInt::from(denominator)
-        ))
     }
 
-    fn find_custom_word(&self, text: &str) -> Option<String> {
-        let mut longest = None;
-        let mut max_len = 0;
+    // 呼び出し元のモジュールから見える定義を探す
+    fn resolve_word(&self, name: &str) -> Option<&WordDef> {
+        self.resolve_key(name).and_then(|key| self.dictionary.get(&key))
+    }
 
-        for word in self.dictionary.keys() {
-            if text.starts_with(word) && word.len() > max_len {
-                max_len = word.len();
-                longest = Some(word.clone());
-            }
+    fn resolve_key(&self, name: &str) -> Option<String> {
+        let context = self.current_context();
+        self.namespaces.candidates(name, &context).into_iter()
+            .find(|candidate| {
+                self.dictionary.get(candidate).is_some_and(|def| def.visible_from(&context))
+            })
+    }
+
+    // 組み込みワードと、ここから見えるユーザー定義ワードの名前（整列済み）
+    fn word_names(&self) -> Vec<String> {
+        let context = self.current_context();
+        let mut names: Vec<String> = builtins().map(|info| info.name.to_string())
+            .chain(
+                self.dictionary.iter()
+                    .filter(|(_, def)| def.visible_from(&context))
+                    .map(|(key, _)| key.clone()),
+            )
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn word_info(&self, name: &str) -> Option<WordInfo> {
+        if let Some(info) = builtin_info(name) {
+            return Some(WordInfo {
+                name: info.name.to_string(),
+                builtin: true,
+                module: String::new(),
+                private: false,
+                effect: Some(info.effect.to_string()),
+                doc: Some(info.doc.to_string()),
+                examples: info.examples.iter().map(|example| example.to_string()).collect(),
+                source: format!("# builtin {} {}", info.name, info.effect),
+            });
         }
 
-        longest
+        let key = self.resolve_key(name)?;
+        let def = self.dictionary.get(&key)?;
+        let short = key.strip_prefix(&format!("{}.", def.module)).unwrap_or(&key);
+
+        // see の出力はそのまま実行すると同じ定義になる
+        let mut meta: Vec<Value> = def.doc.iter().cloned().map(Value::String).collect();
+        meta.extend(def.effect.iter().map(|effect| Value::String(effect.to_string())));
+        meta.extend(def.examples.iter().cloned().map(Value::String));
+        let mut source = format!(
+            "{} '{}' ",
+            Value::Vector(def.code.to_vec()).to_display_string(),
+            short
+        );
+        if !meta.is_empty() {
+            source.push_str(&Value::Vector(meta).to_display_string());
+            source.push(' ');
+        }
+        source.push_str(if def.private { "defp" } else { "def" });
+        if !def.module.is_empty() {
+            source = format!("'{}' module {} end", def.module, source);
+        }
+
+        Some(WordInfo {
+            name: key.clone(),
+            builtin: false,
+            module: def.module.to_string(),
+            private: def.private,
+            effect: def.effect.as_ref().map(|effect| effect.to_string()),
+            doc: def.doc.clone(),
+            examples: def.examples.clone(),
+            source,
+        })
     }
 
-    fn parse_vector(&self, text: &str) -> Result<Value, JsValue> {
-        let inner = &text[1..text.len() - 1].trim();
-        
-        if inner.is_empty() {
-            return Ok(Value::Vector(Vec::new()));
+    fn current_context(&self) -> String {
+        match self.frames.last().and_then(|frame| frame.module.as_ref()) {
+            Some(module) => module.to_string(),
+            None => self.namespaces.current(),
         }
+    }
 
-        let tokens = self.tokenize(inner)?;
-        let mut values = Vec::new();
+    fn execute_control(&mut self, name: &str) -> Result<(), LycorisError> {
+        match name {
+            "run" => {
+                let v = self.pop()?;
+                match v {
+                    Value::Vector(elements) => self.push_frame(elements.into()),
+                    _ => Err(LycorisError::Type("run requires vector".to_string())),
+                }
+            }
 
-        for token in tokens {
-            match token {
-                Token::Value(v) => values.push(v),
-                Token::Function(name, _scope) => {
-                    // 関数名を文字列として保存
-                    values.push(Value::String(name));
+            // 局所束縛
+            "->" => {
+                let body = self.pop_vector("->")?;
+                let names = self.pop_vector("->")?;
+                let names = names.into_iter()
+                    .map(|n| match n {
+                        Value::Word(name, Scope::Local) => Ok(name),
+                        _ => Err(LycorisError::Type("-> requires a vector of names".to_string())),
+                    })
+                    .collect::<Result<Vec<String>, LycorisError>>()?;
+                if names.iter().collect::<HashSet<_>>().len() != names.len() {
+                    return Err(LycorisError::InvalidArgument("-> names must be distinct".to_string()));
+                }
+                if self.stack.len() < names.len() {
+                    return Err(LycorisError::StackUnderflow);
                 }
+                let values = self.pop_n(names.len());
+                let bindings: HashMap<String, Value> = names.into_iter().zip(values).collect();
+                self.push_frame(bind_locals(&body, &bindings).into())
             }
-        }
 
-        Ok(Value::Vector(values))
-    }
+            // パターンマッチ: value [[pattern action] [pattern guard action] ...] match
+            "match" => {
+                let clauses = self.pop_vector("match")?;
+                let value = self.pop()?;
+                for clause in clauses {
+                    let (pattern, guard, action) = match clause {
+                        Value::Vector(parts) => match parts.as_slice() {
+                            [pattern, Value::Vector(action)] => (pattern.clone(), None, action.clone()),
+                            [pattern, Value::Vector(guard), Value::Vector(action)] => {
+                                (pattern.clone(), Some(guard.clone()), action.clone())
+                            }
+                            _ => return Err(invalid_clause()),
+                        },
+                        _ => return Err(invalid_clause()),
+                    };
+
+                    let mut bindings = HashMap::new();
+                    if !match_pattern(&pattern, &value, &mut bindings) {
+                        continue;
+                    }
+                    if let Some(guard) = guard {
+                        if !self.call_quotation(&bind_locals(&guard, &bindings), Vec::new())?.is_truthy() {
+                            continue;
+                        }
+                    }
+                    return self.push_frame(bind_locals(&action, &bindings).into());
+                }
+                Err(LycorisError::InvalidArgument(format!(
+                    "No match clause for {}",
+                    value.to_display_string()
+                )))
+            }
 
-    fn execute_token(&mut self, token: Token) -> Result<(), JsValue> {
-        match token {
-            Token::Value(v) => {
-                self.stack.push(v);
+            // 例外処理
+            "try" => {
+                let handler = self.pop_vector("try")?;
+                let body = self.pop_vector("try")?;
+                self.begin_savepoint();
+                match self.run_quotation(&body) {
+                    Ok(()) => {
+                        self.commit_savepoint();
+                        Ok(())
+                    }
+                    // 資源制限とデバッガでの中断は捕捉させない
+                    Err(err) if err.is_uncatchable() => {
+                        self.commit_savepoint();
+                        Err(err)
+                    }
+                    Err(err) => {
+                        self.rollback_stack();
+                        self.push(error_value(&err))?;
+                        self.run_quotation(&handler)
+                    }
+                }
+            }
+            "finally" => {
+                let cleanup = self.pop_vector("finally")?;
+                let body = self.pop_vector("finally")?;
+                self.begin_savepoint();
+                match self.run_quotation(&body) {
+                    Ok(()) => {
+                        self.commit_savepoint();
+                        self.run_quotation(&cleanup)
+                    }
+                    Err(err) if err.is_uncatchable() => {
+                        self.commit_savepoint();
+                        Err(err)
+                    }
+                    Err(err) => {
+                        self.rollback_stack();
+                        self.run_quotation(&cleanup)?;
+                        Err(err)
+                    }
+                }
+            }
+
+            // 条件分岐
+            "if" => {
+                let then_branch = self.pop_vector("if")?;
+                if self.pop()?.is_truthy() {
+                    self.push_frame(then_branch.into())?;
+                }
                 Ok(())
             }
-            Token::Function(name, scope) => {
-                self.execute_function(&name, scope)
+            "ifelse" => {
+                let else_branch = self.pop_vector("ifelse")?;
+                let then_branch = self.pop_vector("ifelse")?;
+                let branch = if self.pop()?.is_truthy() { then_branch } else { else_branch };
+                self.push_frame(branch.into())
+            }
+
+            // 別ファイルのソースをこの位置で実行する（同じファイルは一度だけ）。
+            // 最後まで実行できたときだけ include 済みにするので、失敗した include はやり直せる
+            "include" => {
+                let path = self.pop_string("include")?;
+                let from = self.frames.iter().rev().find_map(|frame| frame.includes.last().cloned());
+                let resolver = self.resolver.as_mut()
+                    .ok_or_else(|| LycorisError::Include(format!("Cannot include {}: no resolver", path)))?;
+                let cannot = |msg| LycorisError::Include(format!("Cannot include {}: {}", path, msg));
+                let key = resolver.locate(&path, from.as_deref()).map_err(cannot)?;
+                // 実行中のファイル（循環 include）も include 済みとして扱う
+                let running = self.frames.iter().any(|frame| frame.includes.iter().any(|k| **k == *key));
+                if running || self.namespaces.is_included(&key) {
+                    return Ok(());
+                }
+                let source = resolver.resolve(&key).map_err(cannot)?;
+                let code: Code = self.tokenize(&source)?.into_iter().map(Token::into_value).collect();
+                self.push_frame_in(code, None, false)?;
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.includes.push(key.into());
+                }
+                Ok(())
             }
+
+            _ => Err(LycorisError::UnknownWord(name.to_string())),
         }
     }
 
-    fn execute_function(&mut self, name: &str, scope: Scope) -> Result<(), JsValue> {
-        match scope {
-            Scope::Local => self.execute_local(name),
-            Scope::Map => self.execute_map(name),
-            Scope::Reduce => self.execute_reduce(name),
-            Scope::Global => self.execute_global(name),
+    // カテゴリごとに関数を分け、高階ワード経由の再帰でも1段あたりのフレームを小さく保つ
+    fn execute_builtin(&mut self, name: &str) -> Result<(), LycorisError> {
+        let handled = self.execute_arithmetic(name)?
+            || self.execute_stack_op(name)?
+            || self.execute_vector_op(name)?
+            || self.execute_comparison(name)?
+            || self.execute_type_test(name)?
+            || self.execute_logic(name)?
+            || self.execute_vector_algorithm(name)?
+            || self.execute_set_op(name)?
+            || self.execute_string_op(name)?
+            || self.execute_quote(name)?
+            || self.execute_dictionary_op(name)?
+            || self.execute_error_op(name)?
+            || self.execute_test_op(name)?
+            || self.execute_io(name)?;
+        if handled {
+            Ok(())
+        } else {
+            // 登録済みだが未実装のワード
+            Err(LycorisError::UnknownWord(name.to_string()))
         }
     }
 
-    fn execute_local(&mut self, name: &str) -> Result<(), JsValue> {
+    // 算術演算
+    fn execute_arithmetic(&mut self, name: &str) -> Result<bool, LycorisError> {
         match name {
-            // 算術演算
             "add" => {
                 let b = self.pop()?;
                 let a = self.pop()?;
                 match (a, b) {
                     (Value::Rational(x), Value::Rational(y)) => {
-                        self.stack.push(Value::Rational(x + y));
+                        self.push_number(x + y)?;
                     }
-                    _ => return Err(JsValue::from_str("add requires two numbers")),
+                    _ => return Err(LycorisError::Type("add requires two numbers".to_string())),
                 }
             }
             "sub" => {
@@ -473,9 +1125,9 @@ impl Interpreter {
                 let a = self.pop()?;
                 match (a, b) {
                     (Value::Rational(x), Value::Rational(y)) => {
-                        self.stack.push(Value::Rational(x - y));
+                        self.push_number(x - y)?;
                     }
-                    _ => return Err(JsValue::from_str("sub requires two numbers")),
+                    _ => return Err(LycorisError::Type("sub requires two numbers".to_string())),
                 }
             }
             "mul" => {
@@ -483,9 +1135,9 @@ impl Interpreter {
                 let a = self.pop()?;
                 match (a, b) {
                     (Value::Rational(x), Value::Rational(y)) => {
-                        self.stack.push(Value::Rational(x * y));
+                        self.push_number(x * y)?;
                     }
-                    _ => return Err(JsValue::from_str("mul requires two numbers")),
+                    _ => return Err(LycorisError::Type("mul requires two numbers".to_string())),
                 }
             }
             "div" => {
@@ -494,11 +1146,11 @@ impl Interpreter {
                 match (a, b) {
                     (Value::Rational(x), Value::Rational(y)) => {
                         if y.is_zero() {
-                            return Err(JsValue::from_str("Division by zero"));
+                            return Err(LycorisError::DivisionByZero);
                         }
-                        self.stack.push(Value::Rational(x / y));
+                        self.push_number(x / y)?;
                     }
-                    _ => return Err(JsValue::from_str("div requires two numbers")),
+                    _ => return Err(LycorisError::Type("div requires two numbers".to_string())),
                 }
             }
             "pow" => {
@@ -507,29 +1159,43 @@ impl Interpreter {
                 match (a, b) {
                     (Value::Rational(base), Value::Rational(exp)) => {
                         if !exp.is_integer() {
-                            return Err(JsValue::from_str("pow requires integer exponent"));
+                            return Err(LycorisError::InvalidArgument("pow requires integer exponent".to_string()));
                         }
                         let exp_int = exp.to_integer();
                         if let Some(exp_i32) = exp_int.to_i32() {
-                            if exp_i32.abs() > 10000 {
-                                return Err(JsValue::from_str("Exponent too large (max 10000)"));
+                            // 結果のビット数の下限で事前に弾く
+                            if let Some(max) = self.limits.max_number_bits {
+                                let base_bits = base.numer().bits().max(base.denom().bits());
+                                let min_bits = base_bits.saturating_sub(1)
+                                    .saturating_mul(u64::from(exp_i32.unsigned_abs()));
+                                if min_bits > max {
+                                    return Err(LycorisError::NumberTooLarge(max));
+                                }
                             }
-                            let result = base.pow(exp_i32);
-                            self.stack.push(Value::Rational(result));
+                            if base.is_zero() && exp_i32 < 0 {
+                                return Err(LycorisError::DivisionByZero);
+                            }
+                            self.push_number(base.pow(exp_i32))?;
                         } else {
-                            return Err(JsValue::from_str("Exponent out of range"));
+                            return Err(LycorisError::InvalidArgument("Exponent out of range".to_string()));
                         }
                     }
-                    _ => return Err(JsValue::from_str("pow requires two numbers")),
+                    _ => return Err(LycorisError::Type("pow requires two numbers".to_string())),
                 }
             }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
 
-            // スタック操作
+    // スタック操作
+    fn execute_stack_op(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
             "dup" => {
                 let top = self.stack.last()
-                    .ok_or_else(|| JsValue::from_str("Stack underflow"))?
+                    .ok_or(LycorisError::StackUnderflow)?
                     .clone();
-                self.stack.push(top);
+                self.push(top)?;
             }
             "drop" => {
                 self.pop()?;
@@ -537,48 +1203,53 @@ impl Interpreter {
             "swap" => {
                 let b = self.pop()?;
                 let a = self.pop()?;
-                self.stack.push(b);
-                self.stack.push(a);
+                self.push(b)?;
+                self.push(a)?;
             }
             "over" => {
                 if self.stack.len() < 2 {
-                    return Err(JsValue::from_str("Stack underflow"));
+                    return Err(LycorisError::StackUnderflow);
                 }
                 let second = self.stack[self.stack.len() - 2].clone();
-                self.stack.push(second);
+                self.push(second)?;
             }
             "rot" => {
                 if self.stack.len() < 3 {
-                    return Err(JsValue::from_str("Stack underflow"));
+                    return Err(LycorisError::StackUnderflow);
                 }
                 let c = self.pop()?;
                 let b = self.pop()?;
                 let a = self.pop()?;
-                self.stack.push(b);
-                self.stack.push(c);
-                self.stack.push(a);
+                self.push(b)?;
+                self.push(c)?;
+                self.push(a)?;
             }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
 
-            // Vector操作
+    // Vector操作
+    fn execute_vector_op(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
             "vec" => {
                 let n = self.pop()?;
                 match n {
                     Value::Rational(r) => {
                         if !r.is_integer() {
-                            return Err(JsValue::from_str("vec requires integer count"));
+                            return Err(LycorisError::InvalidArgument("vec requires integer count".to_string()));
                         }
                         let count = r.to_integer().to_usize()
-                            .ok_or_else(|| JsValue::from_str("Invalid count"))?;
+                            .ok_or_else(|| LycorisError::InvalidArgument("Invalid count".to_string()))?;
                         
                         if self.stack.len() < count {
-                            return Err(JsValue::from_str("Stack underflow"));
+                            return Err(LycorisError::StackUnderflow);
                         }
                         
-                        let start = self.stack.len() - count;
-                        let elements: Vec<Value> = self.stack.drain(start..).collect();
-                        self.stack.push(Value::Vector(elements));
+                        let elements = self.pop_n(count);
+                        self.push(Value::Vector(elements))?;
                     }
-                    _ => return Err(JsValue::from_str("vec requires number")),
+                    _ => return Err(LycorisError::Type("vec requires number".to_string())),
                 }
             }
             "unpack" => {
@@ -586,44 +1257,58 @@ impl Interpreter {
                 match v {
                     Value::Vector(elements) => {
                         for elem in elements {
-                            self.stack.push(elem);
+                            self.push(elem)?;
                         }
                     }
-                    _ => return Err(JsValue::from_str("unpack requires vector")),
+                    _ => return Err(LycorisError::Type("unpack requires vector".to_string())),
                 }
             }
             "nth" => {
                 let idx = self.pop()?;
-                let vec = self.pop()?;
-                match (vec, idx) {
+                let target = self.pop()?;
+                match (target, idx) {
                     (Value::Vector(v), Value::Rational(n)) => {
-                        let index = n.to_integer().to_i64()
-                            .ok_or_else(|| JsValue::from_str("Invalid index"))?;
-                        
-                        let actual_idx = if index < 0 {
-                            (v.len() as i64 + index) as usize
-                        } else {
-                            index as usize
-                        };
-                        
-                        if actual_idx >= v.len() {
-                            return Err(JsValue::from_str("Index out of bounds"));
-                        }
-                        
-                        self.stack.push(v[actual_idx].clone());
+                        let actual_idx = resolve_index(&n, v.len())?;
+                        self.push(v[actual_idx].clone())?;
+                    }
+                    (Value::String(s), Value::Rational(n)) => {
+                        let chars: Vec<char> = s.chars().collect();
+                        let actual_idx = resolve_index(&n, chars.len())?;
+                        self.push(Value::String(chars[actual_idx].to_string()))?;
+                    }
+                    _ => return Err(LycorisError::Type("nth requires vector or string and number".to_string())),
+                }
+            }
+            "slice" => {
+                let end = self.pop()?;
+                let start = self.pop()?;
+                let target = self.pop()?;
+                match (target, start, end) {
+                    (Value::Vector(v), Value::Rational(s), Value::Rational(e)) => {
+                        let (from, to) = resolve_range(&s, &e, v.len())?;
+                        self.push(Value::Vector(v[from..to].to_vec()))?;
+                    }
+                    (Value::String(text), Value::Rational(s), Value::Rational(e)) => {
+                        let chars: Vec<char> = text.chars().collect();
+                        let (from, to) = resolve_range(&s, &e, chars.len())?;
+                        self.push(Value::String(chars[from..to].iter().collect()))?;
                     }
-                    _ => return Err(JsValue::from_str("nth requires vector and number")),
+                    _ => return Err(LycorisError::Type("slice requires vector or string and two numbers".to_string())),
                 }
             }
             "length" => {
                 let v = self.pop()?;
                 match v {
                     Value::Vector(vec) => {
-                        self.stack.push(Value::Rational(BigRational::from_integer(
-                            BigInt::from(vec.len())
-                        )));
+                        self.push(integer_value(vec.len()))?;
+                    }
+                    Value::String(s) => {
+                        self.push(integer_value(s.chars().count()))?;
+                    }
+                    Value::Set(set) => {
+                        self.push(integer_value(set.len()))?;
                     }
-                    _ => return Err(JsValue::from_str("length requires vector")),
+                    _ => return Err(LycorisError::Type("length requires vector, string or set".to_string())),
                 }
             }
             "concat" => {
@@ -631,139 +1316,1223 @@ impl Interpreter {
                 let a = self.pop()?;
                 match (a, b) {
                     (Value::Vector(mut v1), Value::Vector(v2)) => {
+                        self.check_length(v1.len().saturating_add(v2.len()))?;
                         v1.extend(v2);
-                        self.stack.push(Value::Vector(v1));
+                        self.push(Value::Vector(v1))?;
                     }
-                    _ => return Err(JsValue::from_str("concat requires two vectors")),
+                    (Value::String(mut s1), Value::String(s2)) => {
+                        self.check_length(s1.len().saturating_add(s2.len()))?;
+                        s1.push_str(&s2);
+                        self.push(Value::String(s1))?;
+                    }
+                    _ => return Err(LycorisError::Type("concat requires two vectors or two strings".to_string())),
                 }
             }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
 
-            // 実行制御
-            "run" => {
+    // 型判定
+    fn execute_type_test(&mut self, name: &str) -> Result<bool, LycorisError> {
+        if type_test(name, &Value::Nil).is_none() {
+            return Ok(false);
+        }
+        let v = self.pop()?;
+        self.push(Value::Bool(type_test(name, &v) == Some(true)))?;
+        Ok(true)
+    }
+
+    // 比較
+    fn execute_comparison(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
+            "eq" | "ne" | "lt" | "le" | "gt" | "ge" => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let ordering = a.cmp(&b);
+                let result = match name {
+                    "eq" => ordering == Ordering::Equal,
+                    "ne" => ordering != Ordering::Equal,
+                    "lt" => ordering == Ordering::Less,
+                    "le" => ordering != Ordering::Greater,
+                    "gt" => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                };
+                self.push(Value::Bool(result))?;
+            }
+            "cmp" => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let result: i64 = match a.cmp(&b) {
+                    Ordering::Less => -1,
+                    Ordering::Equal => 0,
+                    Ordering::Greater => 1,
+                };
+                self.push(integer_value(result))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // 論理演算
+    fn execute_logic(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
+            "and" | "or" | "xor" => {
+                let b = self.pop()?.is_truthy();
+                let a = self.pop()?.is_truthy();
+                let result = match name {
+                    "and" => a && b,
+                    "or" => a || b,
+                    _ => a != b,
+                };
+                self.push(Value::Bool(result))?;
+            }
+            "not" => {
                 let v = self.pop()?;
-                match v {
-                    Value::Vector(elements) => {
-                        for elem in elements {
-                            if let Value::String(func_name) = elem {
-                                self.execute_function(&func_name, Scope::Local)?;
-                            } else {
-                                self.stack.push(elem);
+                self.push(Value::Bool(!v.is_truthy()))?;
+            }
+            "truthy" => {
+                let v = self.pop()?;
+                self.push(Value::Bool(v.is_truthy()))?;
+            }
+            // 短絡評価: 右辺はクオーテーションで渡し、必要なときだけ実行する
+            "and-then" | "or-else" => {
+                let rhs = self.pop_vector(name)?;
+                let lhs = self.pop()?.is_truthy();
+                let decided = if name == "and-then" { !lhs } else { lhs };
+                if decided {
+                    self.push(Value::Bool(lhs))?;
+                } else {
+                    let result = self.call_quotation(&rhs, Vec::new())?;
+                    self.push(Value::Bool(result.is_truthy()))?;
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Vectorアルゴリズム
+    fn execute_vector_algorithm(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
+            "range" => {
+                let end = self.pop_integer("range")?;
+                let start = self.pop_integer("range")?;
+                let count = if start < end { (&end - &start).to_usize().unwrap_or(usize::MAX) } else { 0 };
+                let mut values = self.new_vector(count)?;
+                let mut n = start;
+                while n < end {
+                    values.push(Value::Rational(BigRational::from_integer(n.clone())));
+                    n += 1;
+                }
+                self.push(Value::Vector(values))?;
+            }
+            "iota" => {
+                let count = self.pop_count("iota")?;
+                let mut values = self.new_vector(count)?;
+                values.extend((0..count).map(integer_value));
+                self.push(Value::Vector(values))?;
+            }
+            "reverse" => {
+                let mut v = self.pop_vector("reverse")?;
+                v.reverse();
+                self.push(Value::Vector(v))?;
+            }
+            "sort" => {
+                let comparator = self.pop_vector("sort")?;
+                let v = self.pop_vector("sort")?;
+                let sorted = self.merge_sort(v, &comparator)?;
+                self.push(Value::Vector(sorted))?;
+            }
+            "filter" => {
+                let predicate = self.pop_vector("filter")?;
+                let v = self.pop_vector("filter")?;
+                let mut kept = Vec::new();
+                for elem in v {
+                    if self.call_quotation(&predicate, vec![elem.clone()])?.is_truthy() {
+                        kept.push(elem);
+                    }
+                }
+                self.push(Value::Vector(kept))?;
+            }
+            "zip" => {
+                let b = self.pop_vector("zip")?;
+                let a = self.pop_vector("zip")?;
+                let pairs = a.into_iter().zip(b)
+                    .map(|(x, y)| Value::Vector(vec![x, y]))
+                    .collect();
+                self.push(Value::Vector(pairs))?;
+            }
+            "flatten" => {
+                let v = self.pop_vector("flatten")?;
+                let mut flat = Vec::new();
+                for elem in v {
+                    match elem {
+                        Value::Vector(inner) => flat.extend(inner),
+                        other => flat.push(other),
+                    }
+                }
+                self.push(Value::Vector(flat))?;
+            }
+            "transpose" => {
+                let rows = self.pop_vector("transpose")?;
+                let mut matrix = Vec::new();
+                for row in rows {
+                    match row {
+                        Value::Vector(r) => matrix.push(r),
+                        _ => return Err(LycorisError::Type("transpose requires vector of vectors".to_string())),
+                    }
+                }
+                let width = matrix.first().map(|r| r.len()).unwrap_or(0);
+                if matrix.iter().any(|r| r.len() != width) {
+                    return Err(LycorisError::InvalidArgument("transpose requires rows of equal length".to_string()));
+                }
+                let mut columns = vec![Vec::with_capacity(matrix.len()); width];
+                for row in matrix {
+                    for (i, elem) in row.into_iter().enumerate() {
+                        columns[i].push(elem);
+                    }
+                }
+                self.push(Value::Vector(columns.into_iter().map(Value::Vector).collect()))?;
+            }
+            "take" => {
+                let count = self.pop_count("take")?;
+                let mut v = self.pop_vector("take")?;
+                v.truncate(count);
+                self.push(Value::Vector(v))?;
+            }
+            "drop-n" => {
+                let count = self.pop_count("drop-n")?;
+                let v = self.pop_vector("drop-n")?;
+                self.push(Value::Vector(v.into_iter().skip(count).collect()))?;
+            }
+            "uniq" => {
+                let v = self.pop_vector("uniq")?;
+                let mut seen = HashSet::new();
+                let unique = v.into_iter().filter(|elem| seen.insert(elem.clone())).collect();
+                self.push(Value::Vector(unique))?;
+            }
+            "index-of" => {
+                let target = self.pop()?;
+                let v = self.pop_vector("index-of")?;
+                match v.iter().position(|elem| *elem == target) {
+                    Some(i) => self.push(integer_value(i))?,
+                    None => self.push(Value::Nil)?,
+                }
+            }
+            "contains" => {
+                let target = self.pop()?;
+                let v = self.pop_vector("contains")?;
+                self.push(Value::Bool(v.contains(&target)))?;
+            }
+            "first" => {
+                let v = self.pop_vector("first")?;
+                let head = v.into_iter().next()
+                    .ok_or_else(|| LycorisError::InvalidArgument("first requires non-empty vector".to_string()))?;
+                self.push(head)?;
+            }
+            "rest" => {
+                let v = self.pop_vector("rest")?;
+                self.push(Value::Vector(v.into_iter().skip(1).collect()))?;
+            }
+            "scan" => {
+                let f = self.pop_vector("scan")?;
+                let v = self.pop_vector("scan")?;
+                let mut results: Vec<Value> = Vec::with_capacity(v.len());
+                for elem in v {
+                    let next = match results.last() {
+                        Some(acc) => self.call_quotation(&f, vec![acc.clone(), elem])?,
+                        None => elem,
+                    };
+                    results.push(next);
+                }
+                self.push(Value::Vector(results))?;
+            }
+            "group-by" => {
+                let key_fn = self.pop_vector("group-by")?;
+                let v = self.pop_vector("group-by")?;
+                // 最初に現れた順にグループを並べる
+                let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+                let mut group_index: HashMap<Value, usize> = HashMap::new();
+                for elem in v {
+                    let key = self.call_quotation(&key_fn, vec![elem.clone()])?;
+                    match group_index.get(&key) {
+                        Some(&i) => groups[i].1.push(elem),
+                        None => {
+                            group_index.insert(key.clone(), groups.len());
+                            groups.push((key, vec![elem]));
+                        }
+                    }
+                }
+                let result = groups.into_iter()
+                    .map(|(k, members)| Value::Vector(vec![k, Value::Vector(members)]))
+                    .collect();
+                self.push(Value::Vector(result))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Set操作
+    fn execute_set_op(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
+            "toset" => {
+                let v = self.pop_vector("toset")?;
+                self.push(Value::Set(v.into_iter().collect()))?;
+            }
+            "tovec" => {
+                let set = self.pop_set("tovec")?;
+                self.push(Value::Vector(set.into_iter().collect()))?;
+            }
+            "union" => {
+                let b = self.pop_set("union")?;
+                let mut a = self.pop_set("union")?;
+                a.extend(b);
+                self.push(Value::Set(a))?;
+            }
+            "intersect" => {
+                let b = self.pop_set("intersect")?;
+                let a = self.pop_set("intersect")?;
+                self.push(Value::Set(a.intersection(&b).cloned().collect()))?;
+            }
+            "diff" => {
+                let b = self.pop_set("diff")?;
+                let a = self.pop_set("diff")?;
+                self.push(Value::Set(a.difference(&b).cloned().collect()))?;
+            }
+            "subset?" => {
+                let b = self.pop_set("subset?")?;
+                let a = self.pop_set("subset?")?;
+                self.push(Value::Bool(a.is_subset(&b)))?;
+            }
+            "member?" => {
+                let elem = self.pop()?;
+                let set = self.pop_set("member?")?;
+                self.push(Value::Bool(set.contains(&elem)))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // 文字列操作
+    fn execute_string_op(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
+            "split" => {
+                let sep = self.pop()?;
+                let text = self.pop()?;
+                match (text, sep) {
+                    (Value::String(t), Value::String(sep)) => {
+                        let parts: Vec<Value> = if sep.is_empty() {
+                            t.chars().map(|c| Value::String(c.to_string())).collect()
+                        } else {
+                            t.split(sep.as_str()).map(|p| Value::String(p.to_string())).collect()
+                        };
+                        self.push(Value::Vector(parts))?;
+                    }
+                    _ => return Err(LycorisError::Type("split requires two strings".to_string())),
+                }
+            }
+            "join" => {
+                let sep = self.pop()?;
+                let v = self.pop()?;
+                match (v, sep) {
+                    (Value::Vector(items), Value::String(sep)) => {
+                        let parts: Vec<String> = items.iter().map(|item| item.to_plain_string()).collect();
+                        let separators = sep.len().saturating_mul(parts.len().saturating_sub(1));
+                        self.check_length(parts.iter().map(String::len).fold(separators, usize::saturating_add))?;
+                        self.push(Value::String(parts.join(&sep)))?;
+                    }
+                    _ => return Err(LycorisError::Type("join requires vector and string".to_string())),
+                }
+            }
+            "upper" => {
+                let s = self.pop_string("upper")?;
+                self.push(Value::String(s.to_uppercase()))?;
+            }
+            "lower" => {
+                let s = self.pop_string("lower")?;
+                self.push(Value::String(s.to_lowercase()))?;
+            }
+            "trim" => {
+                let s = self.pop_string("trim")?;
+                self.push(Value::String(s.trim().to_string()))?;
+            }
+            "replace" => {
+                let to = self.pop()?;
+                let from = self.pop()?;
+                let text = self.pop()?;
+                match (text, from, to) {
+                    (Value::String(t), Value::String(f), Value::String(r)) => {
+                        if f.is_empty() {
+                            return Err(LycorisError::InvalidArgument("replace requires non-empty pattern".to_string()));
+                        }
+                        // 置換で長くなる分を先に見積もる
+                        if r.len() > f.len() {
+                            let growth = (r.len() - f.len()).saturating_mul(t.matches(f.as_str()).count());
+                            self.check_length(t.len().saturating_add(growth))?;
+                        }
+                        self.push(Value::String(t.replace(f.as_str(), &r)))?;
+                    }
+                    _ => return Err(LycorisError::Type("replace requires three strings".to_string())),
+                }
+            }
+            "find" => {
+                let needle = self.pop()?;
+                let text = self.pop()?;
+                match (text, needle) {
+                    (Value::String(t), Value::String(n)) => {
+                        // バイト位置ではなく文字位置を返す
+                        match t.find(n.as_str()) {
+                            Some(byte_pos) => {
+                                self.push(integer_value(t[..byte_pos].chars().count()))?;
                             }
+                            None => self.push(Value::Nil)?,
                         }
                     }
-                    _ => return Err(JsValue::from_str("run requires vector")),
+                    _ => return Err(LycorisError::Type("find requires two strings".to_string())),
+                }
+            }
+            "startswith" => {
+                let prefix = self.pop()?;
+                let text = self.pop()?;
+                match (text, prefix) {
+                    (Value::String(t), Value::String(p)) => {
+                        self.push(Value::Bool(t.starts_with(p.as_str())))?;
+                    }
+                    _ => return Err(LycorisError::Type("startswith requires two strings".to_string())),
+                }
+            }
+            "endswith" => {
+                let suffix = self.pop()?;
+                let text = self.pop()?;
+                match (text, suffix) {
+                    (Value::String(t), Value::String(p)) => {
+                        self.push(Value::Bool(t.ends_with(p.as_str())))?;
+                    }
+                    _ => return Err(LycorisError::Type("endswith requires two strings".to_string())),
+                }
+            }
+            "chars" => {
+                let s = self.pop_string("chars")?;
+                let chars: Vec<Value> = s.chars().map(|c| Value::String(c.to_string())).collect();
+                self.push(Value::Vector(chars))?;
+            }
+            "ord" => {
+                let s = self.pop_string("ord")?;
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => self.push(integer_value(c as u32))?,
+                    _ => return Err(LycorisError::InvalidArgument("ord requires single-character string".to_string())),
+                }
+            }
+            "chr" => {
+                let n = self.pop()?;
+                match n {
+                    Value::Rational(r) => {
+                        let c = r.is_integer()
+                            .then(|| r.to_integer().to_u32())
+                            .flatten()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| LycorisError::InvalidArgument("Invalid code point".to_string()))?;
+                        self.push(Value::String(c.to_string()))?;
+                    }
+                    _ => return Err(LycorisError::Type("chr requires number".to_string())),
+                }
+            }
+            "tostring" => {
+                let v = self.pop()?;
+                self.push(Value::String(v.to_plain_string()))?;
+            }
+            "parse-number" => {
+                let s = self.pop_string("parse-number")?;
+                let text = s.trim();
+                if text.is_empty() || number_length(text) != text.len() {
+                    return Err(LycorisError::InvalidArgument(format!("Invalid number: '{}'", s)));
                 }
+                let n = self.parse_number(text)?;
+                self.push(n)?;
             }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // 実行制御
+    fn execute_quote(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
             "quote" => {
                 let v = self.pop()?;
-                self.stack.push(Value::Vector(vec![v]));
+                self.push(Value::Vector(vec![v]))?;
             }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
 
-            // 辞書操作
-            "def" => {
-                let name = self.pop()?;
+    // 辞書操作
+    fn execute_dictionary_op(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
+            "def" | "defp" => {
+                // [body] 'name' [meta] def: メタデータ（docstring、スタック効果、例）は省略できる
+                let meta = match self.stack.last() {
+                    Some(Value::Vector(_)) => self.pop_vector(name)?,
+                    _ => Vec::new(),
+                };
+                let (doc, effect, examples) = parse_metadata(meta)?;
+                let word_name = self.pop()?;
                 let body = self.pop()?;
                 
-                match (name, body) {
-                    (Value::String(n), Value::Vector(tokens)) => {
-                        // トークンを保存（簡易版）
-                        let token_list: Vec<Token> = tokens.into_iter().map(|v| {
-                            Token::Value(v)
-                        }).collect();
-                        
-                        self.dictionary.insert(n, token_list);
+                match (word_name, body) {
+                    (Value::String(n), Value::Vector(body)) => {
+                        // 組み込みワードと予約語は再定義できない（モジュールの中でも同じ）
+                        if self.builtin_dict.contains(&n) || matches!(n.as_str(), "true" | "false" | "nil") {
+                            return Err(LycorisError::InvalidArgument(format!("Cannot redefine builtin word: {}", n)));
+                        }
+                        // 開いているモジュールの中では完全名で登録する
+                        let module = self.namespaces.current();
+                        if name == "defp" && module.is_empty() {
+                            return Err(LycorisError::InvalidArgument("defp requires an open module".to_string()));
+                        }
+                        // ワードは関数呼び出し、それ以外は値として実行される
+                        let def = WordDef {
+                            code: body.into(),
+                            module: module.as_str().into(),
+                            private: name == "defp",
+                            doc,
+                            effect,
+                            examples,
+                        };
+                        let key = qualify(&module, &n);
+                        let previous = self.dictionary.insert(key.clone(), def);
+                        if previous.is_none() {
+                            self.index_word(&key);
+                        }
+                        self.journal.record_define(key, previous);
                     }
-                    _ => return Err(JsValue::from_str("def requires string name and vector body")),
+                    _ => return Err(LycorisError::Type(format!("{} requires string name and vector body", name))),
+                }
+            }
+
+            "words" | "words-prefix" => {
+                let prefix = if name == "words-prefix" { self.pop_string(name)? } else { String::new() };
+                let names = self.word_names().into_iter()
+                    .filter(|word| word.starts_with(&prefix))
+                    .map(Value::String)
+                    .collect();
+                self.push(Value::Vector(names))?;
+            }
+            "see" => {
+                let n = self.pop_string("see")?;
+                let info = self.word_info(&n).ok_or(LycorisError::UnknownWord(n))?;
+                self.output.push(info.source);
+            }
+            "help" => {
+                let n = self.pop_string("help")?;
+                let info = self.word_info(&n).ok_or(LycorisError::UnknownWord(n))?;
+                let mut line = info.name;
+                if let Some(effect) = info.effect {
+                    line = format!("{} {}", line, effect);
+                }
+                line = format!("{} - {}", line, info.doc.as_deref().unwrap_or("no documentation"));
+                self.output.push(line);
+            }
+            "undef" => {
+                let n = self.pop_string("undef")?;
+                // 開いているモジュールの中の定義を優先する
+                let qualified = qualify(&self.namespaces.current(), &n);
+                let key = if self.dictionary.contains_key(&qualified) { qualified } else { n };
+                let previous = self.dictionary.remove(&key)
+                    .ok_or_else(|| LycorisError::UnknownWord(key.clone()))?;
+                self.unindex_word(&key);
+                self.journal.record_define(key, Some(previous));
+            }
+
+            // 名前空間
+            "module" => {
+                let module = self.pop_string("module")?;
+                self.namespaces.open(module);
+            }
+            "end" => {
+                self.namespaces.close()?;
+            }
+            "use" => {
+                let namespace = self.pop_string("use")?;
+                self.namespaces.use_namespace(namespace);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // エラー値
+    fn execute_error_op(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
+            "throw" => {
+                let err = match self.pop()? {
+                    Value::String(message) => LycorisError::Thrown {
+                        kind: "user".to_string(),
+                        message,
+                    },
+                    Value::Error { kind, message } => LycorisError::Thrown { kind, message },
+                    _ => return Err(LycorisError::Type("throw requires string or error".to_string())),
+                };
+                return Err(err);
+            }
+            "error" => {
+                let message = self.pop_string("error")?;
+                let kind = self.pop_string("error")?;
+                self.push(Value::Error { kind, message })?;
+            }
+            "error-kind" | "error-message" => {
+                let (kind, message) = match self.pop()? {
+                    Value::Error { kind, message } => (kind, message),
+                    _ => return Err(LycorisError::Type(format!("{} requires error", name))),
+                };
+                let field = if name == "error-kind" { kind } else { message };
+                self.push(Value::String(field))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // テスト
+    fn execute_test_op(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
+            "assert" => {
+                let v = self.pop()?;
+                if !v.is_truthy() {
+                    return Err(LycorisError::Assertion {
+                        message: format!("assert failed: got {}", v.to_display_string()),
+                        diff: Vec::new(),
+                    });
+                }
+            }
+            "assert-eq" | "assert-stack" => {
+                // assert-stack は残りのスタック全体（下から順）を比べる
+                let (expected, actual) = if name == "assert-eq" {
+                    let expected = self.pop()?;
+                    (expected, self.pop()?)
+                } else {
+                    let expected = Value::Vector(self.pop_vector(name)?);
+                    (expected, Value::Vector(self.stack.clone()))
+                };
+                if actual != expected {
+                    let mut diff = Vec::new();
+                    diff_values(&expected, &actual, "$", &mut diff);
+                    return Err(LycorisError::Assertion {
+                        message: format!(
+                            "{} failed: expected {}, got {}",
+                            name,
+                            expected.to_display_string(),
+                            actual.to_display_string()
+                        ),
+                        diff,
+                    });
+                }
+            }
+            "test" => {
+                let test_name = self.pop_string(name)?;
+                let body = self.pop_vector(name)?;
+                let module = self.namespaces.current();
+                let test = TestCase {
+                    name: qualify(&module, &test_name),
+                    code: body.into(),
+                    module: module.as_str().into(),
+                };
+                // 同じ名前なら置き換える
+                match self.tests.iter_mut().find(|existing| existing.name == test.name) {
+                    Some(existing) => *existing = test,
+                    None => self.tests.push(test),
                 }
             }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
 
-            // I/O
+    // I/O
+    fn execute_io(&mut self, name: &str) -> Result<bool, LycorisError> {
+        match name {
             "print" => {
                 let v = self.pop()?;
                 self.output.push(v.to_display_string());
             }
             "clear" => {
-                self.output.clear();
+                let previous = std::mem::take(&mut self.output);
+                self.journal.record_output_clear(previous);
             }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // コードを最後まで実行してから戻る（高階ワードや try から使う）
+    fn run_code(&mut self, code: Code) -> Result<(), LycorisError> {
+        let module = self.frames.last().and_then(|frame| frame.module.clone());
+        self.run_code_in(code, module)
+    }
+
+    fn run_code_in(&mut self, code: Code, module: Option<Rc<str>>) -> Result<(), LycorisError> {
+        if self.nesting >= MAX_NESTING {
+            return Err(LycorisError::NestingLimitExceeded(MAX_NESTING));
+        }
+        let outer_base = self.frame_base;
+        let outer_depth = self.call_depth;
+        self.frame_base = self.frames.len();
+        self.nesting += 1;
+
+        let result = self.push_frame_in(code, module, false).and_then(|()| self.run_frames());
+
+        // エラー時は途中のフレームを捨てる
+        self.frames.truncate(self.frame_base);
+        self.frame_base = outer_base;
+        self.call_depth = outer_depth;
+        self.nesting -= 1;
+        result
+    }
+
+    fn run_frames(&mut self) -> Result<(), LycorisError> {
+        while self.frames.len() > self.frame_base {
+            let Some(frame) = self.frames.last_mut() else {
+                break;
+            };
+            let Some(elem) = frame.code.get(frame.pc).cloned() else {
+                self.pop_frame();
+                continue;
+            };
+            frame.pc += 1;
 
-            _ => {
-                // カスタムワード
-                if let Some(tokens) = self.dictionary.get(name).cloned() {
-                    for token in tokens {
-                        self.execute_token(token)?;
+            if self.debugger.attached() {
+                self.debug_check(&elem)?;
+            }
+            let step = self.trace_begin(&elem);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.begin(&elem, self.frames.iter().map(|frame| frame.word.as_deref()));
+            }
+            let result = match elem {
+                Value::Word(name, scope) => self.execute_function(&name, scope),
+                value => self.tick(1).and_then(|()| self.push(value)),
+            };
+            if let Some(profiler) = &mut self.profiler {
+                profiler.end();
+            }
+            if let (Some(step), Some(tracer)) = (step, &mut self.tracer) {
+                // ユーザー定義ワードや run、ifelse はフレームを積むだけなので、本体が終わるまで待つ
+                let pushed = self.frames.last_mut().filter(|frame| frame.pc == 0);
+                match (&result, pushed) {
+                    (Ok(()), Some(frame)) => frame.traced.push(step),
+                    _ => tracer.end(step, &self.stack, result.as_ref().err()),
+                }
+            }
+            if let Err(err) = result {
+                // 途中で止まったフレームの呼び出しにもエラーを記録する
+                if let Some(tracer) = &mut self.tracer {
+                    for frame in &self.frames[self.frame_base..] {
+                        for &step in &frame.traced {
+                            tracer.end(step, &self.stack, Some(&err));
+                        }
                     }
-                } else {
-                    return Err(JsValue::from_str(&format!("Unknown word: {}", name)));
                 }
+                return Err(err);
             }
         }
         Ok(())
     }
 
-    fn execute_map(&mut self, name: &str) -> Result<(), JsValue> {
-        let vec = self.pop()?;
-        
-        match vec {
-            Value::Vector(elements) => {
-                let mut results = Vec::new();
-                
-                for elem in elements {
-                    self.stack.push(elem);
-                    self.execute_local(name)?;
-                    results.push(self.pop()?);
+    // 最後まで実行したフレームを下ろす
+    fn pop_frame(&mut self) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        if frame.call {
+            self.call_depth -= 1;
+        }
+        for key in &frame.includes {
+            self.namespaces.mark_included(key);
+        }
+        if let Some(tracer) = &mut self.tracer {
+            for step in frame.traced {
+                tracer.end(step, &self.stack, None);
+            }
+        }
+    }
+
+    fn trace_begin(&mut self, token: &Value) -> Option<usize> {
+        let span = self.current_span();
+        let location = self.location(span);
+        let depth = self.frames.len().saturating_sub(1);
+        self.tracer.as_mut()?.begin(depth, token, &self.stack, span, location)
+    }
+
+    // ブレークポイントに当たるか、ステップ実行で止まる位置ならハンドラを呼ぶ
+    fn debug_check(&mut self, token: &Value) -> Result<(), LycorisError> {
+        let depth = self.frames.len();
+        let own_span = self.frames.last().and_then(Frame::token_span);
+        let Some(breakpoint) = self.debugger.check(token, own_span, depth) else {
+            return Ok(());
+        };
+        let last = self.frames.last().is_some_and(|frame| frame.pc >= frame.code.len());
+        let Some(mut handler) = self.debugger.handler.take() else {
+            return Ok(());
+        };
+        let action = handler.paused(&mut DebugSession::new(self, token, breakpoint));
+        self.debugger.handler = Some(handler);
+        self.debugger.resume(action, depth, last)
+    }
+
+    fn current_span(&self) -> Option<Span> {
+        self.frames.last()?.span()
+    }
+
+    fn location(&self, span: Option<Span>) -> Option<(usize, usize)> {
+        Some(self.source_map.as_ref()?.line_col(span?))
+    }
+
+    // クオーテーションは呼び出し元と同じモジュールで名前を解決する
+    fn push_frame(&mut self, code: Code) -> Result<(), LycorisError> {
+        let module = self.frames.last().and_then(|frame| frame.module.clone());
+        self.push_frame_in(code, module, false)
+    }
+
+    // コードの呼び出し。呼び出し元が最後まで実行済みなら（末尾呼び出し）そのフレームと置き換える。
+    // run_code が積んだ最初のフレームは残すので、深さ 0 は常に実行を頼まれたコードになる。
+    // call はユーザー定義ワードの呼び出しで、max_call_depth はこれだけを数える
+    fn push_frame_in(&mut self, code: Code, module: Option<Rc<str>>, mut call: bool) -> Result<(), LycorisError> {
+        // 末尾呼び出しで呼び出し元のフレームを捨てる前に、呼び出し位置を調べておく
+        let (spans, call_span) = match &self.source_map {
+            Some(source_map) => {
+                let call_span = self.current_span();
+                (source_map.spans_for(&code, call_span), call_span)
+            }
+            None => (None, None),
+        };
+        // 置き換えたフレームのワードを引き継ぐ（-> や ifelse の本体もそのワードの実行の一部）
+        let mut word = None;
+        let mut traced = Vec::new();
+        let mut includes = Vec::new();
+        if self.frames.len() > self.frame_base + 1 {
+            if let Some(top) = self.frames.last() {
+                if top.pc >= top.code.len() {
+                    if let Some(frame) = self.frames.pop() {
+                        word = frame.word;
+                        traced = frame.traced;
+                        includes = frame.includes;
+                        if frame.call {
+                            self.call_depth -= 1;
+                            call = true;
+                        }
+                    }
+                }
+            }
+        }
+        if call {
+            if let Some(max) = self.limits.max_call_depth {
+                if self.call_depth >= max {
+                    return Err(LycorisError::CallDepthExceeded(max));
                 }
-                
-                self.stack.push(Value::Vector(results));
             }
-            _ => return Err(JsValue::from_str("@ requires vector")),
+            self.call_depth += 1;
+        }
+        self.frames.push(Frame { code, pc: 0, module, spans, call_span, word, call, traced, includes });
+        Ok(())
+    }
+
+    fn run_quotation(&mut self, elements: &[Value]) -> Result<(), LycorisError> {
+        self.run_code(elements.into())
+    }
+
+    // ワードを1つ呼び出して完了を待つ（@ や * から使う）
+    fn call_word(&mut self, name: &str) -> Result<(), LycorisError> {
+        self.run_code(Rc::new([Value::Word(name.to_string(), Scope::Local)]))
+    }
+
+    // 引数を積んでクオーテーションを実行し、結果を1つ取り出す
+    fn call_quotation(&mut self, quotation: &[Value], args: Vec<Value>) -> Result<Value, LycorisError> {
+        for arg in args {
+            self.push(arg)?;
+        }
+        self.run_quotation(quotation)?;
+        self.pop()
+    }
+
+    // 比較クオーテーションによる安定マージソート
+    // 結果が負の数または true なら a を b より前に置く
+    fn merge_sort(&mut self, mut items: Vec<Value>, comparator: &[Value]) -> Result<Vec<Value>, LycorisError> {
+        if items.len() <= 1 {
+            return Ok(items);
+        }
+        let right = items.split_off(items.len() / 2);
+        let left = self.merge_sort(items, comparator)?;
+        let right = self.merge_sort(right, comparator)?;
+
+        let mut merged = Vec::with_capacity(left.len() + right.len());
+        let mut left = left.into_iter().peekable();
+        let mut right = right.into_iter().peekable();
+        while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+            let ordering = self.call_quotation(comparator, vec![r.clone(), l.clone()])?;
+            let right_first = match ordering {
+                Value::Rational(n) => n.is_negative(),
+                Value::Bool(b) => b,
+                _ => return Err(LycorisError::InvalidArgument("sort comparator must return number or bool".to_string())),
+            };
+            if right_first {
+                merged.extend(right.next());
+            } else {
+                merged.extend(left.next());
+            }
+        }
+        merged.extend(left);
+        merged.extend(right);
+        Ok(merged)
+    }
+
+    fn execute_map(&mut self, name: &str) -> Result<(), LycorisError> {
+        let top = self.pop()?;
+
+        // [1 2 3] 2 @mul 形式: 各要素に同じ引数を適用。
+        // filter や sort ではベクタの引数もクオーテーションとして渡す: [[1 2] [3 4]] [1 gt] @filter
+        let (collection, arg) = match top {
+            Value::Vector(_) if takes_quotation(name) => (self.pop()?, Some(top)),
+            Value::Vector(_) | Value::Set(_) => (top, None),
+            arg => (self.pop()?, Some(arg)),
+        };
+
+        let (elements, is_set) = match collection {
+            Value::Vector(v) => (v, false),
+            Value::Set(set) => (set.into_iter().collect(), true),
+            _ => return Err(LycorisError::Type("@ requires vector".to_string())),
+        };
+
+        let mut results = Vec::new();
+
+        for elem in elements {
+            self.push(elem)?;
+            if let Some(arg) = &arg {
+                self.push(arg.clone())?;
+            }
+            self.call_word(name)?;
+            results.push(self.pop()?);
+        }
+
+        // Setに対する写像はSetを返す
+        if is_set {
+            self.push(Value::Set(results.into_iter().collect()))?;
+        } else {
+            self.push(Value::Vector(results))?;
         }
         
         Ok(())
     }
 
-    fn execute_reduce(&mut self, name: &str) -> Result<(), JsValue> {
-        let vec = self.pop()?;
+    fn execute_reduce(&mut self, name: &str) -> Result<(), LycorisError> {
+        let vec = match self.pop()? {
+            Value::Set(set) => Value::Vector(set.into_iter().collect()),
+            other => other,
+        };
         
         match vec {
             Value::Vector(elements) => {
                 if elements.is_empty() {
-                    return Err(JsValue::from_str("Cannot reduce empty vector"));
+                    // 論理演算は単位元を返す（all/any パターン）
+                    return match name {
+                        "and" => {
+                            self.push(Value::Bool(true))?;
+                            Ok(())
+                        }
+                        "or" => {
+                            self.push(Value::Bool(false))?;
+                            Ok(())
+                        }
+                        _ => Err(LycorisError::InvalidArgument("Cannot reduce empty vector".to_string())),
+                    };
                 }
                 
                 let mut result = elements[0].clone();
                 
                 for elem in elements.into_iter().skip(1) {
-                    self.stack.push(result);
-                    self.stack.push(elem);
-                    self.execute_local(name)?;
+                    self.push(result)?;
+                    self.push(elem)?;
+                    self.call_word(name)?;
                     result = self.pop()?;
                 }
                 
-                self.stack.push(result);
+                // 要素が1つでも論理演算の結果は真偽値にする
+                if matches!(name, "and" | "or") {
+                    result = Value::Bool(result.is_truthy());
+                }
+                self.push(result)?;
             }
-            _ => return Err(JsValue::from_str("* requires vector")),
+            _ => return Err(LycorisError::Type("* requires vector".to_string())),
         }
         
         Ok(())
     }
 
-    fn execute_global(&mut self, name: &str) -> Result<(), JsValue> {
+    fn execute_global(&mut self, name: &str) -> Result<(), LycorisError> {
         // スタック全体を一つのVectorとして扱う
-        let all_elements = self.stack.drain(..).collect::<Vec<_>>();
+        let all_elements = self.pop_n(self.stack.len());
         
         if all_elements.is_empty() {
-            return Err(JsValue::from_str("Stack is empty"));
+            return Err(LycorisError::StackUnderflow);
         }
         
-        self.stack.push(Value::Vector(all_elements));
+        self.push(Value::Vector(all_elements))?;
         self.execute_reduce(name)?;
         
         Ok(())
     }
 
-    fn pop(&mut self) -> Result<Value, JsValue> {
-        self.stack.pop()
-            .ok_or_else(|| JsValue::from_str("Stack underflow"))
+    fn push(&mut self, value: Value) -> Result<(), LycorisError> {
+        if let Some(max) = self.limits.max_stack {
+            if self.stack.len() >= max {
+                return Err(LycorisError::StackLimitExceeded(max));
+            }
+        }
+        match &value {
+            Value::String(s) => self.check_length(s.len())?,
+            Value::Vector(v) => self.check_length(v.len())?,
+            Value::Set(set) => self.check_length(set.len())?,
+            _ => {}
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn push_number(&mut self, n: BigRational) -> Result<(), LycorisError> {
+        if let Some(max) = self.limits.max_number_bits {
+            if n.numer().bits().max(n.denom().bits()) > max {
+                return Err(LycorisError::NumberTooLarge(max));
+            }
+        }
+        self.push(Value::Rational(n))
+    }
+
+    // 大きな値を作る前に長さを確かめる
+    fn check_length(&self, len: usize) -> Result<(), LycorisError> {
+        match self.limits.max_length {
+            Some(max) if len > max => Err(LycorisError::LengthLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    // count 要素のベクタの領域を、長さと燃料を確かめてから確保する
+    fn new_vector(&mut self, count: usize) -> Result<Vec<Value>, LycorisError> {
+        self.check_length(count)?;
+        self.tick(count as u64)?;
+        let mut values = Vec::new();
+        values.try_reserve_exact(count)
+            .map_err(|_| LycorisError::InvalidArgument(format!("Cannot allocate a vector of {} elements", count)))?;
+        Ok(values)
+    }
+
+    // 実行ステップ（燃料）を消費する
+    fn tick(&mut self, amount: u64) -> Result<(), LycorisError> {
+        self.steps = self.steps.saturating_add(amount);
+        match self.limits.max_steps {
+            Some(max) if self.steps > max => Err(LycorisError::StepLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn pop(&mut self) -> Result<Value, LycorisError> {
+        let value = self.stack.pop()
+            .ok_or(LycorisError::StackUnderflow)?;
+        let index = self.stack.len();
+        if self.journal.tracks(index) {
+            self.journal.record_pop(index, value.clone());
+        }
+        Ok(value)
+    }
+
+    // 上からcount個をまとめて取り出す（呼び出し側で個数を確認済み）
+    fn pop_n(&mut self, count: usize) -> Vec<Value> {
+        let start = self.stack.len() - count;
+        for index in (start..self.stack.len()).rev() {
+            if self.journal.tracks(index) {
+                self.journal.record_pop(index, self.stack[index].clone());
+            }
+        }
+        self.stack.split_off(start)
+    }
+
+    // セーブポイント: 失敗したらスタック・辞書・出力を開始時点に戻す
+    fn begin_savepoint(&mut self) {
+        self.journal.begin(self.stack.len(), self.output.len());
+    }
+
+    fn commit_savepoint(&mut self) {
+        self.journal.commit();
+    }
+
+    fn rollback_savepoint(&mut self) {
+        self.journal.rollback(&mut self.stack, &mut self.dictionary, &mut self.output);
+        // 辞書が戻されたので索引を作り直す
+        self.user_words = TrieDict::new();
+        let keys: Vec<String> = self.dictionary.keys().cloned().collect();
+        for key in keys {
+            self.index_word(&key);
+        }
+    }
+
+    // try/finally用: 辞書の変更と出力は残す
+    fn rollback_stack(&mut self) {
+        self.journal.rollback_stack(&mut self.stack);
+    }
+
+    fn pop_vector(&mut self, word: &str) -> Result<Vec<Value>, LycorisError> {
+        match self.pop()? {
+            Value::Vector(v) => Ok(v),
+            _ => Err(LycorisError::Type(format!("{} requires vector", word))),
+        }
+    }
+
+    fn pop_set(&mut self, word: &str) -> Result<BTreeSet<Value>, LycorisError> {
+        match self.pop()? {
+            Value::Set(set) => Ok(set),
+            _ => Err(LycorisError::Type(format!("{} requires set", word))),
+        }
+    }
+
+    fn pop_integer(&mut self, word: &str) -> Result<BigInt, LycorisError> {
+        match self.pop()? {
+            Value::Rational(r) if r.is_integer() => Ok(r.to_integer()),
+            _ => Err(LycorisError::Type(format!("{} requires integer", word))),
+        }
+    }
+
+    fn pop_count(&mut self, word: &str) -> Result<usize, LycorisError> {
+        self.pop_integer(word)?.to_usize()
+            .ok_or_else(|| LycorisError::InvalidArgument("Invalid count".to_string()))
+    }
+
+    fn pop_string(&mut self, word: &str) -> Result<String, LycorisError> {
+        match self.pop()? {
+            Value::String(s) => Ok(s),
+            _ => Err(LycorisError::Type(format!("{} requires string", word))),
+        }
+    }
+
+    pub fn get_words_json(&self) -> String {
+        serde_json::to_string(&self.word_names()).unwrap_or("[]".to_string())
+    }
+
+    // { name, builtin, module, private, effect, doc, examples, source }
+    pub fn get_word_info_json(&self, name: &str) -> Option<String> {
+        self.word_info(name).and_then(|info| serde_json::to_string(&info).ok())
+    }
+
+    // 組み込みワードと公開のユーザー定義ワードのリファレンス
+    pub fn get_reference_markdown(&self) -> String {
+        self.reference().to_markdown()
+    }
+
+    pub fn get_reference_html(&self) -> String {
+        self.reference().to_html()
+    }
+
+    // { words: [{name, builtin, section, effect, doc, scopes, examples, see_also}] }
+    pub fn get_reference_json(&self) -> String {
+        serde_json::to_string(&self.reference()).unwrap_or("null".to_string())
+    }
+
+    // 組み込みワードを含むすべての例を実行した結果。形式は run_tests_json と同じ
+    pub fn run_doctests_json(&mut self) -> String {
+        serde_json::to_string(&self.run_doctests()).unwrap_or("[]".to_string())
+    }
+
+    // 静的検査の結果: [{severity, message, span: {start, end}, line, column}]
+    pub fn check_json(&self, source: &str) -> Result<String, JsValue> {
+        let diagnostics = self.check(source)?;
+        Ok(serde_json::to_string(&diagnostics).unwrap_or("[]".to_string()))
+    }
+
+    // リンターの指摘: [{code, severity, message, span: {start, end}, line, column}]
+    pub fn lint_json(&self, source: &str) -> Result<String, JsValue> {
+        let lints = self.lint(source)?;
+        Ok(serde_json::to_string(&lints).unwrap_or("[]".to_string()))
+    }
+
+    // 空白を整えたソース（width は1行の最大文字数）
+    pub fn format_source(&self, source: &str, width: usize) -> Result<String, JsValue> {
+        Ok(self.format(source, &FormatOptions { width })?)
+    }
+
+    // トレースモード: 以降の実行で、トークンごとに前後のスタックと位置を記録する
+    pub fn set_tracing(&mut self, enabled: bool) {
+        self.tracer = enabled.then(Tracer::default);
+    }
+
+    // 直前の実行のトレース: {steps: [{depth, word, scope, before, after, span, line, column, error}], truncated}
+    pub fn get_trace_json(&self) -> String {
+        self.trace().and_then(|trace| serde_json::to_string(trace).ok()).unwrap_or("null".to_string())
+    }
+
+    pub fn get_trace_table(&self) -> String {
+        self.trace().map(Trace::to_table).unwrap_or_default()
+    }
+
+    // ホストの時計（ミリ秒を返す関数。performance.now など）でプロファイルを始める
+    pub fn start_profiling_with_clock(&mut self, clock: js_sys::Function) {
+        self.start_profiling(JsClock(clock));
+    }
+
+    pub fn stop_profiling(&mut self) {
+        self.profiler = None;
+    }
+
+    // {words: [{name, builtin, calls, inclusive_ns, exclusive_ns, inclusive_bytes, exclusive_bytes}],
+    //  total_ns, total_bytes, stacks: {"a;b": ns}}
+    pub fn get_profile_json(&self) -> String {
+        self.profile().and_then(|profile| serde_json::to_string(&profile).ok()).unwrap_or("null".to_string())
+    }
+
+    pub fn get_profile_report(&self) -> String {
+        self.profile().map(|profile| profile.to_report()).unwrap_or_default()
+    }
+
+    // flamegraph 用の folded stacks
+    pub fn get_profile_folded(&self) -> String {
+        self.profile().map(|profile| profile.to_folded()).unwrap_or_default()
+    }
+
+    // 止まるたびに (pause, output) => command を呼ぶ。pause は
+    // {word, scope, depth, span, line, column, breakpoint, stack, calls} の JSON、output は直前のコマンドの結果。
+    // command は lycoris debug と同じ（continue, step, next, out, set INDEX VALUE など）
+    pub fn set_debug_callback(&mut self, callback: js_sys::Function) {
+        self.set_debug_handler(JsDebugHandler(callback));
+    }
+
+    pub fn clear_debug_handler(&mut self) {
+        self.debugger.handler = None;
+        self.debugger.stop_stepping();
+    }
+
+    // 次に実行するトークンで止まる（execute の前に呼べば最初のトークンで止まる）
+    pub fn request_pause(&mut self) {
+        self.debugger.request_pause();
+    }
+
+    // ブレークポイントの番号を返す。name はソースでの書き方（@sq など）
+    pub fn add_word_breakpoint(&mut self, name: String) -> usize {
+        self.add_breakpoint(Breakpoint::Word(name))
+    }
+
+    // 先頭が start..end（バイト位置）にあるトークンで止まる
+    pub fn add_span_breakpoint(&mut self, start: usize, end: usize) -> usize {
+        self.add_breakpoint(Breakpoint::Span(Span::new(start, end)))
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.debugger.remove(id)
+    }
+
+    // 登録済みのテストを実行した結果: [{name, passed, message, diff, output, ...}]
+    pub fn run_tests_json(&mut self) -> String {
+        serde_json::to_string(&self.run_tests()).unwrap_or("[]".to_string())
     }
 
     pub fn get_stack_json(&self) -> String {
@@ -786,6 +2555,535 @@ impl Interpreter {
     }
 }
 
+// 先頭の数値リテラルのバイト数（数値でなければ 0）。ASCII だけなので文字数と同じ
+//   整数 -12、分数 3/4、小数 1.5 .5 5.、指数 1e3 1.5e-2 2E+4
+fn number_length(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let digits_from = |i: usize| bytes[i.min(bytes.len())..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+    let mut i = usize::from(bytes.first() == Some(&b'-'));
+    let whole = digits_from(i);
+    i += whole;
+    if whole > 0 && bytes.get(i) == Some(&b'/') {
+        let denom = digits_from(i + 1);
+        return if denom > 0 { i + 1 + denom } else { i };
+    }
+    let mut fraction = 0;
+    if bytes.get(i) == Some(&b'.') {
+        fraction = digits_from(i + 1);
+        if whole + fraction > 0 {
+            i += 1 + fraction;
+        }
+    }
+    if whole + fraction == 0 {
+        return 0;
+    }
+    if matches!(bytes.get(i), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(i + 1), Some(b'-' | b'+')));
+        let exponent = digits_from(i + 1 + sign);
+        if exponent > 0 {
+            i += 1 + sign + exponent;
+        }
+    }
+    i
+}
+
+fn invalid_number(text: &str) -> LycorisError {
+    LycorisError::InvalidArgument(format!("Invalid number: '{}'", text))
+}
+
+// 束縛された名前を値に置き換える（呼び出しごとに新しい本体を作るので再帰しても混ざらない）
+// ネストしたクオーテーションにも適用し、内側の `->` が同じ名前を束縛していればそちらを優先する
+fn bind_locals(body: &[Value], bindings: &HashMap<String, Value>) -> Vec<Value> {
+    let mut result = Vec::with_capacity(body.len());
+    let mut i = 0;
+    while i < body.len() {
+        if let (Value::Vector(names), Some(Value::Vector(inner)), Some(Value::Word(arrow, Scope::Local))) =
+            (&body[i], body.get(i + 1), body.get(i + 2))
+        {
+            if arrow == "->" {
+                let mut visible = bindings.clone();
+                for name in names {
+                    if let Value::Word(name, _) = name {
+                        visible.remove(name);
+                    }
+                }
+                result.push(body[i].clone());
+                result.push(Value::Vector(bind_locals(inner, &visible)));
+                result.push(body[i + 2].clone());
+                i += 3;
+                continue;
+            }
+        }
+
+        match &body[i] {
+            Value::Word(name, Scope::Local) => match bindings.get(name) {
+                // ワード値はそのまま置くと実行されてしまうので包んで取り出す
+                Some(word @ Value::Word(_, _)) => {
+                    result.push(Value::Vector(vec![word.clone()]));
+                    result.push(Value::Word("unpack".to_string(), Scope::Local));
+                }
+                Some(value) => result.push(value.clone()),
+                None => result.push(body[i].clone()),
+            },
+            Value::Vector(inner) => result.push(Value::Vector(bind_locals(inner, bindings))),
+            other => result.push(other.clone()),
+        }
+        i += 1;
+    }
+    result
+}
+
+// 型判定ワード。型判定ワードでなければ None
+fn type_test(name: &str, value: &Value) -> Option<bool> {
+    let result = match name {
+        "rational?" => matches!(value, Value::Rational(_)),
+        "string?" => matches!(value, Value::String(_)),
+        "bool?" => matches!(value, Value::Bool(_)),
+        "nil?" => matches!(value, Value::Nil),
+        "vector?" => matches!(value, Value::Vector(_)),
+        "set?" => matches!(value, Value::Set(_)),
+        "word?" => matches!(value, Value::Word(_, _)),
+        "error?" => matches!(value, Value::Error { .. }),
+        _ => return None,
+    };
+    Some(result)
+}
+
+// パターン:
+//   _              何にでも一致
+//   rational? など  型判定
+//   名前           何にでも一致し、値を束縛する（同じ名前が2度出たら等しい値に限る）
+//   [p1 p2]        同じ長さのVectorを要素ごとに照合
+//   [p1 & rest]    先頭を照合し、残りのVectorを rest に照合
+//   その他         等しい値
+fn match_pattern(pattern: &Value, value: &Value, bindings: &mut HashMap<String, Value>) -> bool {
+    match pattern {
+        Value::Word(name, Scope::Local) => {
+            if name == "_" {
+                return true;
+            }
+            if let Some(result) = type_test(name, value) {
+                return result;
+            }
+            match bindings.get(name) {
+                Some(bound) => bound == value,
+                None => {
+                    bindings.insert(name.clone(), value.clone());
+                    true
+                }
+            }
+        }
+        Value::Vector(patterns) => {
+            let Value::Vector(items) = value else {
+                return false;
+            };
+            let rest_at = patterns.iter()
+                .position(|p| matches!(p, Value::Word(name, Scope::Local) if name == "&"));
+            match rest_at {
+                Some(split) => {
+                    if patterns.len() != split + 2 || items.len() < split {
+                        return false;
+                    }
+                    patterns[..split].iter().zip(items)
+                        .all(|(p, v)| match_pattern(p, v, bindings))
+                        && match_pattern(&patterns[split + 1], &Value::Vector(items[split..].to_vec()), bindings)
+                }
+                None => {
+                    patterns.len() == items.len()
+                        && patterns.iter().zip(items).all(|(p, v)| match_pattern(p, v, bindings))
+                }
+            }
+        }
+        literal => literal == value,
+    }
+}
+
+fn invalid_clause() -> LycorisError {
+    LycorisError::Type("match clause must be [pattern action] or [pattern guard action]".to_string())
+}
+
+// 捕捉したエラーをスタックに積める値に変換する
+fn error_value(err: &LycorisError) -> Value {
+    Value::Error {
+        kind: err.kind().to_string(),
+        message: err.to_string(),
+    }
+}
+
+// docstring、スタック効果、例
+type Metadata = (Option<String>, Option<StackEffect>, Vec<String>);
+
+// def のメタデータ: ['docstring' '( a b -- c )' '2 3 f → 5' ...]。例は → を含む文字列
+fn parse_metadata(meta: Vec<Value>) -> Result<Metadata, LycorisError> {
+    let mut doc = None;
+    let mut effect = None;
+    let mut examples = Vec::new();
+    for item in meta {
+        let Value::String(text) = item else {
+            return Err(LycorisError::Type("def metadata must be strings".to_string()));
+        };
+        if text.contains('→') {
+            examples.push(text);
+        } else if text.trim_start().starts_with('(') {
+            let parsed = StackEffect::parse(&text)
+                .ok_or_else(|| LycorisError::InvalidArgument(format!("Invalid stack effect: {}", text)))?;
+            if effect.replace(parsed).is_some() {
+                return Err(LycorisError::InvalidArgument("def metadata has more than one stack effect".to_string()));
+            }
+        } else if doc.replace(text).is_some() {
+            return Err(LycorisError::InvalidArgument("def metadata has more than one docstring".to_string()));
+        }
+    }
+    Ok((doc, effect, examples))
+}
+
+// 同じ入力の中で 'name' def される名前。定義より前にある本体（再帰呼び出し）や
+// 後続のコードでも組み込みワードに分割されないように、字句解析の前に集めておく
+fn scan_definitions(input: &str) -> TrieDict {
+    let mut names = TrieDict::new();
+    let mut rest = input;
+    while let Some(i) = rest.find(['\'', '#']) {
+        let after = &rest[i + 1..];
+        if rest[i..].starts_with('#') {
+            rest = after.find('\n').map_or("", |j| &after[j..]);
+            continue;
+        }
+        let Some(end) = after.find('\'') else {
+            break;
+        };
+        let name = &after[..end];
+        rest = &after[end + 1..];
+        // 字句解析と同じく def/defp は最長一致で読む（defp) も defp と ) になる）。
+        // 空白や # を含む名前は1つのトークンにならないので先読みしない
+        let next = skip_trivia(skip_metadata(skip_trivia(rest)));
+        if next.starts_with("def") && !name.is_empty() && name.chars().all(is_name_char) {
+            names.insert(name);
+        }
+    }
+    names
+}
+
+// 空白とコメントを読み飛ばす
+fn skip_trivia(mut text: &str) -> &str {
+    loop {
+        text = text.trim_start();
+        if !text.starts_with('#') {
+            return text;
+        }
+        text = text.find('\n').map_or("", |i| &text[i..]);
+    }
+}
+
+// 'name' [meta] def のメタデータ部分を読み飛ばす
+fn skip_metadata(text: &str) -> &str {
+    if !text.starts_with('[') {
+        return text;
+    }
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut in_comment = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\n' => in_comment = false,
+            _ if in_comment => {}
+            '\'' => in_string = !in_string,
+            '#' if !in_string => in_comment = true,
+            '[' if !in_string => depth += 1,
+            ']' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return &text[i + 1..];
+                }
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+struct JsResolver(js_sys::Function);
+
+impl SourceResolver for JsResolver {
+    fn resolve(&mut self, path: &str) -> Result<String, String> {
+        let source = self.0.call1(&JsValue::NULL, &JsValue::from_str(path))
+            .map_err(|err| err.as_string().unwrap_or_else(|| "resolver failed".to_string()))?;
+        source.as_string().ok_or_else(|| "resolver did not return a string".to_string())
+    }
+}
+
+struct JsClock(js_sys::Function);
+
+impl ProfileClock for JsClock {
+    fn now(&mut self) -> u64 {
+        let millis = self.0.call0(&JsValue::NULL).ok().and_then(|value| value.as_f64()).unwrap_or(0.0);
+        (millis * 1_000_000.0) as u64
+    }
+}
+
+struct JsDebugHandler(js_sys::Function);
+
+impl DebugHandler for JsDebugHandler {
+    // コールバックが再開のコマンドを返すまで呼び続ける。文字列を返さなければ中断する
+    fn paused(&mut self, session: &mut DebugSession) -> DebugAction {
+        let mut output = String::new();
+        loop {
+            let pause = serde_json::to_string(session.pause()).unwrap_or("null".to_string());
+            let command = self.0.call2(&JsValue::NULL, &JsValue::from_str(&pause), &JsValue::from_str(&output))
+                .ok()
+                .and_then(|value| value.as_string());
+            let Some(command) = command else {
+                return DebugAction::Abort;
+            };
+            match session.command(&command) {
+                DebugReply::Resume(action) => return action,
+                DebugReply::Output(text) => output = text,
+            }
+        }
+    }
+}
+
+// 名前として読み取る文字（空白・括弧・引用符・コメント以外）
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '[' | ']' | '{' | '}' | '\'' | '#')
+}
+
+// Rust向けAPI（wasmへは公開しない）
+impl Interpreter {
+    pub fn eval(&mut self, input: &str) -> Result<String, LycorisError> {
+        self.reset_counters();
+
+        let program = self.parse(input)?;
+        if let Some(tracer) = &mut self.tracer {
+            tracer.start();
+        }
+        self.source_map = (self.tracer.is_some() || self.debugger.attached()).then(|| SourceMap::new(input, &program));
+        let code: Code = program.into_iter().map(Node::into_value).collect();
+
+        let snapshot = self.atomic.then(|| (self.namespaces.clone(), self.tests.clone()));
+        if self.atomic {
+            self.begin_savepoint();
+        }
+        let result = self.run_code(code);
+        self.debugger.stop_stepping();
+        if let Some((namespaces, tests)) = snapshot {
+            if result.is_ok() {
+                self.commit_savepoint();
+            } else {
+                self.rollback_savepoint();
+                self.namespaces = namespaces;
+                self.tests = tests;
+            }
+        }
+        result?;
+        
+        Ok(self.output.join("\n"))
+    }
+
+    // 制限カウンタは実行ごとにリセットする
+    fn reset_counters(&mut self) {
+        self.steps = 0;
+        self.frames.clear();
+        self.frame_base = 0;
+        self.nesting = 0;
+        self.call_depth = 0;
+    }
+
+    // 登録済みのテストとユーザー定義ワードの例を順に実行する。スタックは元に戻す
+    pub fn run_tests(&mut self) -> Vec<TestResult> {
+        let mut cases: Vec<_> = self.tests.iter().cloned().map(Ok).collect();
+        cases.extend(docgen::doctests(self, false));
+        self.run_cases(cases)
+    }
+
+    // 組み込みワードとユーザー定義ワードの例だけを実行する
+    pub fn run_doctests(&mut self) -> Vec<TestResult> {
+        let cases = docgen::doctests(self, true);
+        self.run_cases(cases)
+    }
+
+    pub fn reference(&self) -> Reference {
+        docgen::reference(self)
+    }
+
+    // 読めなかった例はすでに失敗した結果になっている
+    fn run_cases(&mut self, cases: Vec<Result<TestCase, TestResult>>) -> Vec<TestResult> {
+        let stack = std::mem::take(&mut self.stack);
+        let results = cases.into_iter()
+            .map(|case| case.map_or_else(|result| result, |test| self.run_test(&test)))
+            .collect();
+        self.stack = stack;
+        results
+    }
+
+    // ソースを実行し、登録されたテストを実行する。このソースで定義したテストには位置をつける
+    pub fn test_source(&mut self, source: &str) -> Result<Vec<TestResult>, LycorisError> {
+        let program = self.parse(source)?;
+        self.eval(source)?;
+        let spans = test_spans(&program);
+        let mut results = self.run_tests();
+        for result in &mut results {
+            if let Some(&span) = spans.get(&result.name) {
+                let (line, column) = line_col(source, span.start);
+                result.span = Some(span);
+                result.line = Some(line);
+                result.column = Some(column);
+            }
+        }
+        Ok(results)
+    }
+
+    // 空のスタックで実行し、辞書や出力への変更も含めて元に戻す
+    fn run_test(&mut self, test: &TestCase) -> TestResult {
+        self.reset_counters();
+        let namespaces = self.namespaces.clone();
+        let tests = self.tests.clone();
+        let output_start = self.output.len();
+
+        self.begin_savepoint();
+        let result = self.run_code_in(test.code.clone(), Some(test.module.clone()));
+        let output = self.output.get(output_start..).map_or(Vec::new(), <[String]>::to_vec);
+        self.rollback_savepoint();
+        self.stack.clear();
+        self.namespaces = namespaces;
+        self.tests = tests;
+
+        let (message, diff) = match result {
+            Ok(()) => (None, Vec::new()),
+            Err(LycorisError::Assertion { message, diff }) => (Some(message), diff),
+            Err(err) => (Some(err.to_string()), Vec::new()),
+        };
+        TestResult {
+            name: test.name.clone(),
+            passed: message.is_none(),
+            message,
+            diff,
+            output,
+            span: None,
+            line: None,
+            column: None,
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_resolver(&mut self, resolver: impl SourceResolver + 'static) {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    // 実行せずにスタック効果を検査する。スタックは現在の内容から始める
+    pub fn check(&self, source: &str) -> Result<Vec<Diagnostic>, LycorisError> {
+        let program = self.parse(source)?;
+        Ok(check::check_program(self, source, &program))
+    }
+
+    // 最長一致で思わぬ区切られ方をする書き方の指摘
+    pub fn lint(&self, source: &str) -> Result<Vec<Lint>, LycorisError> {
+        let program = self.parse(source)?;
+        let tree = format::syntax_tree(source, &program)?;
+        Ok(lint::lint_program(self, source, &program, &tree))
+    }
+
+    // 実行せずにソースを読む。Node::into_value で実行時の値になる
+    pub fn read(&self, source: &str) -> Result<Vec<Node>, LycorisError> {
+        self.parse(source)
+    }
+
+    // 空白とコメントを含めてソースを読む。表示すると元のソースに戻る
+    pub fn syntax_tree(&self, source: &str) -> Result<SyntaxTree, LycorisError> {
+        format::syntax_tree(source, &self.parse(source)?)
+    }
+
+    // 空白を整えたソース。トークン列が元と変わる場合はエラーにする
+    pub fn format(&self, source: &str, options: &FormatOptions) -> Result<String, LycorisError> {
+        let formatted = format::print(&self.syntax_tree(source)?, options);
+        if self.tokenize(&formatted)? != self.tokenize(source)? {
+            return Err(LycorisError::Syntax("Formatting would change the meaning of the program".to_string()));
+        }
+        Ok(formatted)
+    }
+
+    pub fn words(&self) -> Vec<String> {
+        self.word_names()
+    }
+
+    // 以降の実行を clock で計測する。それまでの計測は捨てる
+    pub fn start_profiling(&mut self, clock: impl ProfileClock + 'static) {
+        self.profiler = Some(Profiler::new(Box::new(clock)));
+    }
+
+    // start_profiling からの計測結果
+    pub fn profile(&self) -> Option<Profile> {
+        self.profiler.as_ref().map(Profiler::profile)
+    }
+
+    // 以降の実行でブレークポイントやステップ実行で止まったときに handler を呼ぶ
+    pub fn set_debug_handler(&mut self, handler: impl DebugHandler + 'static) {
+        self.debugger.handler = Some(Box::new(handler));
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.debugger.add(breakpoint)
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.debugger.breakpoints
+    }
+
+    // トレースモードで直前に実行した分の記録
+    pub fn trace(&self) -> Option<&Trace> {
+        self.tracer.as_ref().map(|tracer| &tracer.trace)
+    }
+
+    pub fn describe(&self, name: &str) -> Option<WordInfo> {
+        self.word_info(name)
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+}
+
+fn integer_value(n: impl Into<BigInt>) -> Value {
+    Value::Rational(BigRational::from_integer(n.into()))
+}
+
+// 負のインデックスは末尾から数える
+fn resolve_index(n: &BigRational, len: usize) -> Result<usize, LycorisError> {
+    let index = n.to_integer().to_i64()
+        .ok_or_else(|| LycorisError::InvalidArgument("Invalid index".to_string()))?;
+
+    let actual_idx = if index < 0 {
+        index.checked_add(len as i64).filter(|i| *i >= 0)
+    } else {
+        Some(index)
+    };
+
+    match actual_idx {
+        Some(i) if (i as u64) < len as u64 => Ok(i as usize),
+        _ => Err(LycorisError::IndexOutOfBounds),
+    }
+}
+
+// slice用: 範囲外は端に丸める
+fn resolve_range(start: &BigRational, end: &BigRational, len: usize) -> Result<(usize, usize), LycorisError> {
+    let clamp = |n: &BigRational| -> Result<usize, LycorisError> {
+        let index = n.to_integer().to_i64()
+            .ok_or_else(|| LycorisError::InvalidArgument("Invalid index".to_string()))?;
+        let actual = if index < 0 { index.saturating_add(len as i64) } else { index };
+        Ok(actual.clamp(0, len as i64) as usize)
+    };
+    let from = clamp(start)?;
+    let to = clamp(end)?;
+    Ok((from, to.max(from)))
+}
+
 // パニックフック設定
 #[wasm_bindgen(start)]
 pub fn main() {
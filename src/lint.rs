@@ -0,0 +1,171 @@
+use serde::Serialize;
+
+use crate::check::Severity;
+use crate::format::{ItemKind, SyntaxTree};
+use crate::syntax::{forms, line_col, Node, NodeKind, Span};
+use crate::{is_name_char, scan_definitions, Interpreter, TrieDict, Value};
+
+// ============================================================================
+// リンター
+// ============================================================================
+//
+// 最長一致の字句解析が思わぬ位置で区切る書き方を指摘する。
+// 実行はせず、字句解析の結果と辞書だけを見る。
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Lint {
+    // 指摘の種類: split-word, glued-number, redefines-builtin, shadows-builtin,
+    // prefixes-builtin, global-scope
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    // span の開始位置（1始まり）
+    pub line: usize,
+    pub column: usize,
+}
+
+struct Linter<'a> {
+    interpreter: &'a Interpreter,
+    source: &'a str,
+    // このソースで def される名前
+    pending: TrieDict,
+    lints: Vec<Lint>,
+}
+
+pub(crate) fn lint_program(interpreter: &Interpreter, source: &str, program: &[Node], tree: &SyntaxTree) -> Vec<Lint> {
+    let mut linter = Linter { interpreter, source, pending: scan_definitions(source), lints: Vec::new() };
+    linter.runs(program);
+    linter.definitions(program);
+    linter.comments(tree);
+
+    let mut lints = linter.lints;
+    lints.sort_by_key(|lint| (lint.span.start, lint.span.end));
+    lints
+}
+
+// 空白がなくても前後と区切られるのは文字列と括弧だけ
+fn is_bare(node: &Node) -> bool {
+    match &node.kind {
+        NodeKind::Word(..) => true,
+        NodeKind::Literal(value) => !matches!(value, Value::String(_)),
+        NodeKind::Vector(_) | NodeKind::Set(_) => false,
+    }
+}
+
+impl Linter<'_> {
+    fn report(&mut self, code: &'static str, severity: Severity, span: Span, message: String) {
+        let (line, column) = line_col(self.source, span.start);
+        self.lints.push(Lint { code, severity, message, span, line, column });
+    }
+
+    fn text(&self, span: Span) -> &str {
+        &self.source[span.start..span.end]
+    }
+
+    // 空白を挟まずに続くトークンを1つの並びとして調べる
+    fn runs(&mut self, nodes: &[Node]) {
+        // `[names] -> [body]` は並べ替えられているのでソースの順に戻す
+        let mut nodes: Vec<&Node> = nodes.iter().collect();
+        nodes.sort_by_key(|node| node.span.start);
+
+        let mut run: Vec<&Node> = Vec::new();
+        for node in nodes {
+            if let NodeKind::Vector(children) | NodeKind::Set(children) = &node.kind {
+                self.runs(children);
+            }
+            let glued = run.last().is_some_and(|last| last.span.end == node.span.start);
+            if !glued || !is_bare(node) {
+                self.run(&run);
+                run.clear();
+            }
+            if is_bare(node) {
+                run.push(node);
+            }
+        }
+        self.run(&run);
+    }
+
+    fn run(&mut self, run: &[&Node]) {
+        let (Some(first), Some(last)) = (run.first(), run.last()) else {
+            return;
+        };
+        if run.len() < 2 {
+            return;
+        }
+        let span = Span::new(first.span.start, last.span.end);
+        let words: Vec<&str> = run.iter().map(|node| self.text(node.span)).collect();
+        if run.iter().any(|node| matches!(node.kind, NodeKind::Literal(Value::Rational(_)))) {
+            let message = format!("'{}' glues numbers and words together and is read as {}", self.text(span), words.join(" "));
+            self.report("glued-number", Severity::Warning, span, message);
+        } else {
+            let message = format!("'{}' is read as {} words: {}", self.text(span), run.len(), words.join(" "));
+            self.report("split-word", Severity::Warning, span, message);
+        }
+    }
+
+    // 入れ子（テストの本体など）の定義も含めて、名前と組み込みワードの重なりを調べる
+    fn definitions(&mut self, program: &[Node]) {
+        for form in forms(program, &["def", "defp"]) {
+            self.definition(form.short, form.name_span);
+        }
+        for node in program {
+            if let NodeKind::Vector(children) | NodeKind::Set(children) = &node.kind {
+                self.definitions(children);
+            }
+        }
+    }
+
+    fn definition(&mut self, name: &str, span: Span) {
+        let builtins = &self.interpreter.builtin_dict;
+        if builtins.contains(name) || matches!(name, "true" | "false" | "nil") {
+            let message = format!("'{}' is a builtin word and cannot be redefined", name);
+            self.report("redefines-builtin", Severity::Error, span, message);
+            return;
+        }
+        if let Some(builtin) = builtins.longest_match(name) {
+            let message = format!(
+                "'{}' starts with the builtin '{}'; where '{}' is not defined it is read as {}",
+                name, builtin, name, self.read_undefined(name)
+            );
+            self.report("shadows-builtin", Severity::Warning, span, message);
+        }
+        if let Some(builtin) = builtins.complete(name).into_iter().next() {
+            let message = format!("'{}' is a prefix of the builtin '{}', which is always read as the builtin", name, builtin);
+            self.report("prefixes-builtin", Severity::Warning, span, message);
+        }
+    }
+
+    // このソースの定義がないものとして字句解析した結果
+    fn read_undefined(&self, name: &str) -> String {
+        match self.interpreter.parse_nodes(name, &TrieDict::new()) {
+            Ok(nodes) => nodes.iter().map(|node| &name[node.span.start..node.span.end]).collect::<Vec<_>>().join(" "),
+            Err(_) => name.to_string(),
+        }
+    }
+
+    // #word はスコープではなくコメントとして読まれる
+    fn comments(&mut self, tree: &SyntaxTree) {
+        for item in &tree.items {
+            match &item.kind {
+                ItemKind::Comment(text) => {
+                    let word: String = text[1..].chars().take_while(|c| is_name_char(*c)).collect();
+                    if !word.is_empty() && self.is_known_word(&word) {
+                        let message = format!(
+                            "'#{}' is a comment, not '{}' with the # scope; the rest of the line is ignored",
+                            word, word
+                        );
+                        self.report("global-scope", Severity::Warning, item.span, message);
+                    }
+                }
+                ItemKind::Group { body, .. } => self.comments(body),
+                ItemKind::Token(_) => {}
+            }
+        }
+    }
+
+    fn is_known_word(&self, word: &str) -> bool {
+        self.interpreter.builtin_dict.contains(word)
+            || self.interpreter.find_user_word(word, &self.pending).is_some_and(|found| found == word)
+    }
+}
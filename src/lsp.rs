@@ -0,0 +1,422 @@
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::builtins::builtin_info;
+use crate::check::Severity;
+use crate::syntax::{forms, Form, Node, NodeKind, Span};
+use crate::{is_name_char, Interpreter, Scope, TrieDict, Value};
+
+// ============================================================================
+// 言語サーバー (Language Server Protocol)
+// ============================================================================
+//
+// 標準入出力の JSON-RPC で .lyc ファイルを編集するエディタに応える。
+// 文書は保存せずに毎回解析し直す（文書を実行はしない）。
+//
+//   診断             字句解析のエラーと静的検査の結果
+//   定義へ移動       同じ文書の 'name' def
+//   ホバー           組み込みワードと文書中の定義のスタック効果と docstring
+//   補完             組み込みワードと文書中の定義をトライ木で前方一致
+//   セマンティック   スコーププレフィックス、ワード、文字列、数値を区別する
+
+const TOKEN_TYPES: &[&str] = &["function", "string", "number", "keyword", "operator"];
+const TOKEN_MODIFIERS: &[&str] = &["defaultLibrary", "definition"];
+
+const FUNCTION: u32 = 0;
+const STRING: u32 = 1;
+const NUMBER: u32 = 2;
+const KEYWORD: u32 = 3;
+const OPERATOR: u32 = 4;
+
+const DEFAULT_LIBRARY: u32 = 1;
+const DEFINITION: u32 = 2;
+
+// JSON-RPC のエラーコード
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// Content-Length で区切ったメッセージを読み、応答を書く。exit を受け取るか入力が終わったら戻る
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match serde_json::from_str::<Json>(&body) {
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![error_response(Json::Null, PARSE_ERROR, &err.to_string())],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn response(id: Json, result: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: Json, code: i64, message: &str) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+#[derive(Default)]
+pub struct Server {
+    // URI ごとの最新の内容
+    documents: HashMap<String, String>,
+    exited: bool,
+}
+
+impl Server {
+    // 1つのメッセージに対する応答と通知
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let Some(id) = message.get("id").cloned() else {
+            return self.handle_notification(method, params);
+        };
+
+        let result = match method {
+            "initialize" => capabilities(),
+            "shutdown" => Json::Null,
+            "textDocument/hover" => self.with_document(params, hover),
+            "textDocument/definition" => self.with_document(params, definition),
+            "textDocument/completion" => self.with_document(params, completion),
+            "textDocument/semanticTokens/full" => self.with_document(params, semantic_tokens),
+            _ => return vec![error_response(id, METHOD_NOT_FOUND, &format!("Method not found: {}", method))],
+        };
+        vec![response(id, result)]
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("").to_string();
+                self.documents.insert(uri.clone(), text);
+                vec![self.diagnostics(&uri)]
+            }
+            "textDocument/didChange" => {
+                // 全体を送る同期方式なので最後の変更が文書全体
+                let Some(text) = params["contentChanges"].as_array().and_then(|changes| changes.last()) else {
+                    return Vec::new();
+                };
+                self.documents.insert(uri.clone(), text["text"].as_str().unwrap_or("").to_string());
+                vec![self.diagnostics(&uri)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }))]
+            }
+            "exit" => {
+                self.exited = true;
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn with_document(&self, params: &Json, f: fn(&Document, &Json) -> Json) -> Json {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        match self.documents.get(uri) {
+            Some(text) => f(&Document::new(uri, text), params),
+            None => Json::Null,
+        }
+    }
+
+    fn diagnostics(&self, uri: &str) -> Json {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let document = Document::new(uri, text);
+        let diagnostics: Vec<Json> = match document.interpreter.check(text) {
+            Ok(diagnostics) => diagnostics.iter()
+                .map(|d| {
+                    let severity = match d.severity {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    };
+                    json!({
+                        "range": document.range(d.span),
+                        "severity": severity,
+                        "source": "lycoris",
+                        "message": d.message,
+                    })
+                })
+                .collect(),
+            // 字句解析のエラーには位置がないので文書の先頭に出す
+            Err(err) => vec![json!({
+                "range": document.range(Span::default()),
+                "severity": 1,
+                "source": "lycoris",
+                "message": err.to_string(),
+            })],
+        };
+        notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics }))
+    }
+}
+
+fn capabilities() -> Json {
+    json!({
+        "capabilities": {
+            "textDocumentSync": 1,
+            "hoverProvider": true,
+            "definitionProvider": true,
+            "completionProvider": { "triggerCharacters": ["@", "*", "#"] },
+            "semanticTokensProvider": {
+                "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": TOKEN_MODIFIERS },
+                "full": true,
+            },
+        },
+        "serverInfo": { "name": "lycoris-lsp", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+// 解析済みの文書
+struct Document<'a> {
+    uri: &'a str,
+    text: &'a str,
+    // 各行の先頭のバイト位置
+    line_starts: Vec<usize>,
+    interpreter: Interpreter,
+    program: Option<Vec<Node>>,
+}
+
+impl<'a> Document<'a> {
+    fn new(uri: &'a str, text: &'a str) -> Document<'a> {
+        let line_starts = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        let interpreter = Interpreter::new();
+        let program = interpreter.parse(text).ok();
+        Document { uri, text, line_starts, interpreter, program }
+    }
+
+    // バイト位置を LSP の位置（行と UTF-16 での列）にする
+    fn position(&self, offset: usize) -> Json {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character: usize = self.text[start..offset].chars().map(char::len_utf16).sum();
+        json!({ "line": line, "character": character })
+    }
+
+    fn range(&self, span: Span) -> Json {
+        json!({ "start": self.position(span.start), "end": self.position(span.end) })
+    }
+
+    fn offset(&self, position: &Json) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let Some(&start) = self.line_starts.get(line) else {
+            return self.text.len();
+        };
+        let mut units = position["character"].as_u64().unwrap_or(0) as usize;
+        let mut offset = start;
+        for ch in self.text[start..].chars() {
+            if units == 0 || ch == '\n' {
+                break;
+            }
+            units = units.saturating_sub(ch.len_utf16());
+            offset += ch.len_utf8();
+        }
+        offset
+    }
+
+    fn definitions(&self) -> Vec<Form<'_>> {
+        self.program.as_deref().map_or(Vec::new(), |program| forms(program, &["def", "defp"]))
+    }
+
+    // 名前に当たる定義。完全名の一致を優先する
+    fn definition(&self, name: &str) -> Option<Form<'_>> {
+        let mut found: Vec<Form> = self.definitions().into_iter()
+            .filter(|form| form.name == name || form.short == name)
+            .collect();
+        let exact = found.iter().position(|form| form.name == name).unwrap_or(0);
+        (!found.is_empty()).then(|| found.swap_remove(exact))
+    }
+
+    // カーソル位置のワード
+    fn word_at(&self, params: &Json) -> Option<(&str, Span)> {
+        let offset = self.offset(&params["position"]);
+        find_word(self.program.as_deref()?, offset)
+    }
+}
+
+fn find_word(nodes: &[Node], offset: usize) -> Option<(&str, Span)> {
+    nodes.iter()
+        .filter(|node| node.span.start <= offset && offset <= node.span.end)
+        .find_map(|node| match &node.kind {
+            NodeKind::Word(name, _) => Some((name.as_str(), node.span)),
+            NodeKind::Vector(items) | NodeKind::Set(items) => find_word(items, offset),
+            NodeKind::Literal(_) => None,
+        })
+}
+
+fn hover(document: &Document, params: &Json) -> Json {
+    let Some((name, span)) = document.word_at(params) else {
+        return Json::Null;
+    };
+    let (signature, doc) = if let Some(info) = builtin_info(name) {
+        (format!("{} {}", info.name, info.effect), Some(info.doc.to_string()))
+    } else if let Some(form) = document.definition(name) {
+        let (doc, effect) = form.metadata();
+        let mut signature = form.name.clone();
+        if let Some(effect) = effect {
+            signature = format!("{} {}", signature, effect);
+        }
+        if form.word == "defp" {
+            signature = format!("{} (private)", signature);
+        }
+        (signature, doc.map(String::from))
+    } else {
+        return Json::Null;
+    };
+
+    let mut value = format!("```lycoris\n{}\n```", signature);
+    if let Some(doc) = doc {
+        value = format!("{}\n\n{}", value, doc);
+    }
+    json!({
+        "contents": { "kind": "markdown", "value": value },
+        "range": document.range(span),
+    })
+}
+
+fn definition(document: &Document, params: &Json) -> Json {
+    let Some(form) = document.word_at(params).and_then(|(name, _)| document.definition(name)) else {
+        return Json::Null;
+    };
+    json!({ "uri": document.uri, "range": document.range(form.name_span) })
+}
+
+fn completion(document: &Document, params: &Json) -> Json {
+    // カーソルの手前の名前（スコーププレフィックスは除く）
+    let offset = document.offset(&params["position"]);
+    let before = &document.text[..offset];
+    let start = before.rfind(|c| !is_name_char(c)).map_or(0, |i| i + before[i..].chars().next().map_or(1, char::len_utf8));
+    let prefix = before[start..].trim_start_matches(['@', '*']);
+
+    let definitions = document.definitions();
+    let mut user_words = TrieDict::new();
+    for form in &definitions {
+        user_words.insert(&form.name);
+        user_words.insert(form.short);
+    }
+
+    let mut items: Vec<Json> = document.interpreter.builtin_dict.complete(prefix).into_iter()
+        .map(|name| {
+            let info = builtin_info(&name);
+            json!({
+                "label": name,
+                "kind": 3,
+                "detail": info.map(|info| info.effect),
+                "documentation": info.map(|info| info.doc),
+            })
+        })
+        .collect();
+    for name in user_words.complete(prefix) {
+        let Some(form) = definitions.iter().find(|form| form.name == name || form.short == name) else {
+            continue;
+        };
+        let (doc, effect) = form.metadata();
+        items.push(json!({
+            "label": name,
+            "kind": 3,
+            "detail": effect.map(|effect| effect.to_string()),
+            "documentation": doc,
+        }));
+    }
+    for reserved in ["true", "false", "nil"].into_iter().filter(|word| word.starts_with(prefix)) {
+        items.push(json!({ "label": reserved, "kind": 21 }));
+    }
+    json!({ "isIncomplete": false, "items": items })
+}
+
+fn semantic_tokens(document: &Document, _params: &Json) -> Json {
+    let Some(program) = &document.program else {
+        return json!({ "data": [] });
+    };
+    let definitions: Vec<Span> = document.definitions().iter().map(|form| form.name_span).collect();
+
+    // (開始, 終了, 種類, 修飾)
+    let mut tokens = Vec::new();
+    collect_tokens(program, &document.interpreter, &definitions, &mut tokens);
+    // -> の並べ替えで順序が崩れているので位置順に戻す
+    tokens.sort_by_key(|token| token.0);
+
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_character) = (0, 0);
+    for (start, end, kind, modifiers) in tokens {
+        let start_position = document.position(start);
+        let line = start_position["line"].as_u64().unwrap_or(0);
+        let character = start_position["character"].as_u64().unwrap_or(0);
+        // 複数行にまたがる文字列は最初の行の分だけ
+        let text = &document.text[start..end];
+        let length: usize = text.split('\n').next().unwrap_or("").chars().map(char::len_utf16).sum();
+
+        let delta_character = if line == previous_line { character - previous_character } else { character };
+        data.extend([line - previous_line, delta_character, length as u64, u64::from(kind), u64::from(modifiers)]);
+        previous_line = line;
+        previous_character = character;
+    }
+    json!({ "data": data })
+}
+
+fn collect_tokens(nodes: &[Node], interpreter: &Interpreter, definitions: &[Span], tokens: &mut Vec<(usize, usize, u32, u32)>) {
+    for node in nodes {
+        let Span { start, end } = node.span;
+        match &node.kind {
+            NodeKind::Literal(Value::String(_)) => {
+                let modifiers = if definitions.contains(&node.span) { DEFINITION } else { 0 };
+                tokens.push((start, end, STRING, modifiers));
+            }
+            NodeKind::Literal(Value::Rational(_)) => tokens.push((start, end, NUMBER, 0)),
+            NodeKind::Literal(_) => tokens.push((start, end, KEYWORD, 0)),
+            NodeKind::Word(name, scope) => {
+                let name_start = if *scope == Scope::Local {
+                    start
+                } else {
+                    tokens.push((start, start + 1, OPERATOR, 0));
+                    start + 1
+                };
+                let modifiers = if interpreter.builtin_dict.contains(name) { DEFAULT_LIBRARY } else { 0 };
+                tokens.push((name_start, end, FUNCTION, modifiers));
+            }
+            NodeKind::Vector(items) | NodeKind::Set(items) => collect_tokens(items, interpreter, definitions, tokens),
+        }
+    }
+}
@@ -9,7 +9,7 @@ class LycorisUI {
     async init() {
         try {
             await init();
-            this.interpreter = new Interpreter();
+            this.interpreter = this.createInterpreter();
             this.setupUI();
             this.showWelcomeMessage();
         } catch (error) {
@@ -18,6 +18,13 @@ class LycorisUI {
         }
     }
 
+    private createInterpreter(): Interpreter {
+        const interpreter = new Interpreter();
+        // 無限ループでタブが固まらないよう実行量を制限する
+        interpreter.set_max_steps(10_000_000);
+        return interpreter;
+    }
+
     private setupUI() {
         const executeBtn = document.getElementById('execute-btn');
         const clearBtn = document.getElementById('clear-btn');
@@ -57,7 +64,7 @@ class LycorisUI {
                         <code>[1 2 3] 2 @mul</code> → [2 4 6] (map)<br>
                         <code>[1 2 3 4 5] *add</code> → 15 (reduce)<br>
                         <code>5 dup mul</code> → 25 (duplicate and multiply)<br>
-                        <code>[dup mul] 'square def</code> → define 'square'<br>
+                        <code>[dup mul] 'square' def</code> → define 'square'<br>
                         <code>7 [square] run</code> → 49<br>
                     </div>
                     <p class="hint">Press Ctrl+Enter to execute</p>
@@ -147,7 +154,7 @@ class LycorisUI {
     private clearStack() {
         if (this.interpreter) {
             // スタックをクリアするために新しいインタープリタを作成
-            this.interpreter = new Interpreter();
+            this.interpreter = this.createInterpreter();
             this.updateDisplay();
             this.appendOutput('Stack cleared');
         }
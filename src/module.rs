@@ -0,0 +1,166 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::{Code, LycorisError};
+
+// ============================================================================
+// ワード定義と名前空間
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub(crate) struct WordDef {
+    pub(crate) code: Code,
+    // 定義されたモジュール（トップレベルは空文字列）
+    pub(crate) module: Rc<str>,
+    // 同じモジュール（とその内側）からしか呼べない
+    pub(crate) private: bool,
+    pub(crate) doc: Option<String>,
+    pub(crate) effect: Option<StackEffect>,
+    // 「ソース → 実行後のスタック」の例
+    pub(crate) examples: Vec<String>,
+}
+
+impl WordDef {
+    // context から呼び出せるか
+    pub(crate) fn visible_from(&self, context: &str) -> bool {
+        !self.private || is_within(context, &self.module)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Namespaces {
+    // module ... end の入れ子
+    open: Vec<String>,
+    // use で取り込んだ名前空間（後から取り込んだものを優先する）
+    uses: Vec<String>,
+    // 最後まで実行した include のキー（SourceResolver::locate が返したもの）
+    included: HashSet<String>,
+}
+
+impl Namespaces {
+    // 開いているモジュールの完全名
+    pub(crate) fn current(&self) -> String {
+        self.open.join(".")
+    }
+
+    pub(crate) fn open(&mut self, name: String) {
+        self.open.push(name);
+    }
+
+    pub(crate) fn close(&mut self) -> Result<(), LycorisError> {
+        self.open.pop()
+            .map(|_| ())
+            .ok_or_else(|| LycorisError::InvalidArgument("end without module".to_string()))
+    }
+
+    pub(crate) fn use_namespace(&mut self, namespace: String) {
+        self.uses.retain(|ns| *ns != namespace);
+        self.uses.push(namespace);
+    }
+
+    pub(crate) fn is_included(&self, key: &str) -> bool {
+        self.included.contains(key)
+    }
+
+    pub(crate) fn mark_included(&mut self, key: &str) {
+        self.included.insert(key.to_string());
+    }
+
+    // 名前を探す順序:
+    //   呼び出し元のモジュールから外側へ (a.b.name, a.name)
+    //   そのままの名前（トップレベルの定義や math.square のような完全名）
+    //   use した名前空間（新しいものから）
+    pub(crate) fn candidates(&self, name: &str, context: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut module = context;
+        while !module.is_empty() {
+            names.push(qualify(module, name));
+            module = module.rfind('.').map_or("", |i| &module[..i]);
+        }
+        names.push(name.to_string());
+        for namespace in self.uses.iter().rev() {
+            names.push(qualify(namespace, name));
+        }
+        names
+    }
+}
+
+pub(crate) fn qualify(module: &str, name: &str) -> String {
+    if module.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", module, name)
+    }
+}
+
+// context が module そのものか、その内側のモジュールか
+fn is_within(context: &str, module: &str) -> bool {
+    context == module
+        || (context.starts_with(module) && context[module.len()..].starts_with('.'))
+}
+
+// ============================================================================
+// スタック効果
+// ============================================================================
+
+// ( a b -- c ) の形の宣言。`..` は個数が実行時に決まることを表す
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StackEffect {
+    pub(crate) inputs: Vec<String>,
+    pub(crate) outputs: Vec<String>,
+}
+
+impl StackEffect {
+    pub(crate) fn parse(text: &str) -> Option<StackEffect> {
+        let inner = text.trim().strip_prefix('(')?.strip_suffix(')')?;
+        let (inputs, outputs) = inner.split_once("--")?;
+        Some(StackEffect {
+            inputs: inputs.split_whitespace().map(String::from).collect(),
+            outputs: outputs.split_whitespace().map(String::from).collect(),
+        })
+    }
+}
+
+impl fmt::Display for StackEffect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec!["("];
+        parts.extend(self.inputs.iter().map(String::as_str));
+        parts.push("--");
+        parts.extend(self.outputs.iter().map(String::as_str));
+        parts.push(")");
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+// UI向けのワード情報
+#[derive(Debug, Clone, Serialize)]
+pub struct WordInfo {
+    pub name: String,
+    pub builtin: bool,
+    pub module: String,
+    pub private: bool,
+    pub effect: Option<String>,
+    pub doc: Option<String>,
+    pub examples: Vec<String>,
+    // see と同じ再解析できるソース（組み込みワードはコメント）
+    pub source: String,
+}
+
+// ============================================================================
+// include のソース解決
+// ============================================================================
+
+// include 'path' のソースを返す。CLIではファイル、wasmではJSのコールバックから読む
+pub trait SourceResolver {
+    // 書かれたパスを、同じファイルなら同じになるキーに直す。from は include を書いたファイルのキー
+    // （トップレベルなら None）。既定では書かれたパスをそのまま使う
+    fn locate(&mut self, path: &str, from: Option<&str>) -> Result<String, String> {
+        let _ = from;
+        Ok(path.to_string())
+    }
+
+    // locate が返したキーのソースを読む
+    fn resolve(&mut self, key: &str) -> Result<String, String>;
+}
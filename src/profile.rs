@@ -0,0 +1,250 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use crate::Value;
+
+// ============================================================================
+// プロファイラ
+// ============================================================================
+//
+// 実行したトークンごとに、かかった時間と確保したバイト数を測る。入れ子で実行された
+// トークンの分を引いたものをそのトークンの自己時間とし、その時点の呼び出しの並び
+// （ユーザー定義ワードのフレームと、@ など入れ子で実行中のワード）に振り分ける。
+// 包括時間はワードが並びのどこかにあったときの自己時間の合計になる。
+
+// 計測に使う時計。wasm ではホストの時計（performance.now など）を渡す
+pub trait ProfileClock {
+    // 単調に増えるナノ秒
+    fn now(&mut self) -> u64;
+
+    // それまでに確保したバイト数の累計。測れなければ 0
+    fn allocated(&mut self) -> u64 {
+        0
+    }
+}
+
+// std::time::Instant による時計（wasm では使えない）
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProfileClock for SystemClock {
+    fn now(&mut self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WordProfile {
+    // ソースでの書き方（スコーププレフィックスを含む）
+    pub name: String,
+    // false ならユーザー定義ワードの呼び出し
+    pub builtin: bool,
+    pub calls: u64,
+    // 入れ子の呼び出しを含む時間と含まない時間（ナノ秒）
+    pub inclusive_ns: u64,
+    pub exclusive_ns: u64,
+    // 同じく確保したバイト数
+    pub inclusive_bytes: u64,
+    pub exclusive_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Profile {
+    // 自己時間の長い順
+    pub words: Vec<WordProfile>,
+    pub total_ns: u64,
+    pub total_bytes: u64,
+    // 呼び出しの並び（; 区切り）ごとの自己時間
+    pub stacks: BTreeMap<String, u64>,
+}
+
+impl Profile {
+    // 自己時間の長い順の表
+    pub fn to_report(&self) -> String {
+        let name_width = self.words.iter().map(|word| word.name.chars().count()).max().unwrap_or(0).max(4);
+        let mut report = format!(
+            "{:<name_width$}  {:>8}  {:>12}  {:>12}  {:>12}\n",
+            "word", "calls", "inclusive", "exclusive", "allocated"
+        );
+        for word in &self.words {
+            report.push_str(&format!(
+                "{:<name_width$}  {:>8}  {:>12}  {:>12}  {:>12}\n",
+                word.name,
+                word.calls,
+                format_ns(word.inclusive_ns),
+                format_ns(word.exclusive_ns),
+                format_bytes(word.inclusive_bytes),
+            ));
+        }
+        report.push_str(&format!("total {}, {} allocated\n", format_ns(self.total_ns), format_bytes(self.total_bytes)));
+        report
+    }
+
+    // flamegraph.pl や inferno が読む形式: 1行に「a;b;c 自己時間」
+    pub fn to_folded(&self) -> String {
+        self.stacks.iter().map(|(stack, ns)| format!("{} {}\n", stack, ns)).collect()
+    }
+}
+
+fn format_ns(ns: u64) -> String {
+    format!("{:.3} ms", ns as f64 / 1_000_000.0)
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{} B", bytes)
+}
+
+// 実行中のトークン
+struct Open {
+    // 入れ子で実行中のときに並びに出す名前
+    label: Option<String>,
+    // 実行しているフレームの位置
+    frame: usize,
+    start_ns: u64,
+    start_bytes: u64,
+    // 入れ子で実行したトークンの分
+    nested_ns: u64,
+    nested_bytes: u64,
+    // 呼び出し元の並び
+    stack: Vec<String>,
+    // ユーザー定義ワードの呼び出しなら、その名前
+    user: Option<String>,
+}
+
+pub(crate) struct Profiler {
+    clock: Box<dyn ProfileClock>,
+    open: Vec<Open>,
+    words: HashMap<String, WordProfile>,
+    stacks: BTreeMap<String, u64>,
+    total_ns: u64,
+    total_bytes: u64,
+}
+
+impl Profiler {
+    pub(crate) fn new(clock: Box<dyn ProfileClock>) -> Self {
+        Profiler {
+            clock,
+            open: Vec::new(),
+            words: HashMap::new(),
+            stacks: BTreeMap::new(),
+            total_ns: 0,
+            total_bytes: 0,
+        }
+    }
+
+    // frames はフレームごとのユーザー定義ワード名（下から順）
+    pub(crate) fn begin<'a>(&mut self, token: &Value, frames: impl Iterator<Item = Option<&'a str>>) {
+        let mut stack = Vec::new();
+        let mut open = self.open.iter().peekable();
+        let mut depth = 0;
+        for (i, word) in frames.enumerate() {
+            stack.extend(word.map(str::to_string));
+            while let Some(token) = open.next_if(|token| token.frame == i) {
+                stack.extend(token.label.clone());
+            }
+            depth = i;
+        }
+        let label = match token {
+            Value::Word(name, scope) => Some(format!("{}{}", scope.prefix(), name)),
+            _ => None,
+        };
+        let start_ns = self.clock.now();
+        let start_bytes = self.clock.allocated();
+        self.open.push(Open {
+            label,
+            frame: depth,
+            start_ns,
+            start_bytes,
+            nested_ns: 0,
+            nested_bytes: 0,
+            stack,
+            user: None,
+        });
+    }
+
+    // 実行中のトークンがユーザー定義ワードを呼び出した
+    pub(crate) fn call(&mut self, name: &str) {
+        if let Some(token) = self.open.last_mut() {
+            token.user = Some(name.to_string());
+        }
+    }
+
+    pub(crate) fn end(&mut self) {
+        let now_ns = self.clock.now();
+        let now_bytes = self.clock.allocated();
+        let Some(token) = self.open.pop() else {
+            return;
+        };
+        let elapsed_ns = now_ns.saturating_sub(token.start_ns);
+        let elapsed_bytes = now_bytes.saturating_sub(token.start_bytes);
+        let self_ns = elapsed_ns.saturating_sub(token.nested_ns);
+        let self_bytes = elapsed_bytes.saturating_sub(token.nested_bytes);
+        if let Some(parent) = self.open.last_mut() {
+            parent.nested_ns += elapsed_ns;
+            parent.nested_bytes += elapsed_bytes;
+        }
+
+        let builtin = token.user.is_none();
+        let leaf = token.user.or(token.label);
+        let mut stack = token.stack;
+        stack.extend(leaf.clone());
+
+        // 再帰で同じワードが並びに何度あっても包括時間には1回だけ足す
+        let mut seen: Vec<&str> = Vec::new();
+        for name in &stack {
+            if seen.contains(&name.as_str()) {
+                continue;
+            }
+            seen.push(name);
+            let word = self.words.entry(name.clone()).or_default();
+            word.inclusive_ns += self_ns;
+            word.inclusive_bytes += self_bytes;
+        }
+        // リテラルを積む時間は呼び出し元のワードの自己時間にする
+        if let Some(name) = stack.last() {
+            let word = self.words.entry(name.clone()).or_default();
+            word.exclusive_ns += self_ns;
+            word.exclusive_bytes += self_bytes;
+            if leaf.is_some() {
+                word.calls += 1;
+                word.builtin = builtin;
+            }
+        }
+
+        let key = if stack.is_empty() { "(top)".to_string() } else { stack.join(";") };
+        *self.stacks.entry(key).or_default() += self_ns;
+        self.total_ns += self_ns;
+        self.total_bytes += self_bytes;
+    }
+
+    pub(crate) fn profile(&self) -> Profile {
+        let mut words: Vec<WordProfile> = self.words.iter()
+            .map(|(name, word)| WordProfile { name: name.clone(), ..word.clone() })
+            .collect();
+        words.sort_by(|a, b| {
+            b.exclusive_ns.cmp(&a.exclusive_ns)
+                .then(b.inclusive_ns.cmp(&a.inclusive_ns))
+                .then(a.name.cmp(&b.name))
+        });
+        Profile {
+            words,
+            total_ns: self.total_ns,
+            total_bytes: self.total_bytes,
+            stacks: self.stacks.clone(),
+        }
+    }
+}
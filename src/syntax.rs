@@ -0,0 +1,252 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::module::{qualify, StackEffect};
+use crate::{Scope, Token, Value};
+
+// ============================================================================
+// 位置つき構文木
+// ============================================================================
+//
+// 字句解析の結果にソース上の位置を残したもの。実行には Token/Value に変換して使い、
+// 静的検査や診断の表示ではこちらを使う。
+
+// ソース上のバイト範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    // 数値・文字列・真偽値・nil
+    Literal(Value),
+    Word(String, Scope),
+    Vector(Vec<Node>),
+    Set(Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+}
+
+impl Node {
+    pub(crate) fn new(kind: NodeKind, span: Span) -> Node {
+        Node { kind, span }
+    }
+
+    // 実行時の値からノードを作る（辞書にある定義の本体など、ソースのないコード用）
+    pub(crate) fn from_value(value: &Value, span: Span) -> Node {
+        let kind = match value {
+            Value::Word(name, scope) => NodeKind::Word(name.clone(), *scope),
+            Value::Vector(items) => {
+                NodeKind::Vector(items.iter().map(|item| Node::from_value(item, span)).collect())
+            }
+            Value::Set(items) => {
+                NodeKind::Set(items.iter().map(|item| Node::from_value(item, span)).collect())
+            }
+            other => NodeKind::Literal(other.clone()),
+        };
+        Node::new(kind, span)
+    }
+
+    pub fn into_value(self) -> Value {
+        match self.kind {
+            NodeKind::Literal(value) => value,
+            NodeKind::Word(name, scope) => Value::Word(name, scope),
+            NodeKind::Vector(items) => Value::Vector(items.into_iter().map(Node::into_value).collect()),
+            NodeKind::Set(items) => Value::Set(items.into_iter().map(Node::into_value).collect()),
+        }
+    }
+
+    pub(crate) fn into_token(self) -> Token {
+        match self.kind {
+            NodeKind::Word(name, scope) => Token::Function(name, scope),
+            _ => Token::Value(self.into_value()),
+        }
+    }
+
+    // 関数呼び出しなら名前
+    pub(crate) fn word(&self) -> Option<&str> {
+        match &self.kind {
+            NodeKind::Word(name, Scope::Local) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+// バイト位置から 1 始まりの行と列（列は文字単位）
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |text| text.chars().count()) + 1;
+    (line, column)
+}
+
+// ============================================================================
+// 実行時のコードの位置
+// ============================================================================
+//
+// 実行時のコードは位置を持たないので、実行前にVectorリテラルの要素の位置を
+// 中身をキーにして覚えておき、フレームを積むときに引く（トレースとデバッガで使う）。
+
+#[derive(Debug, Default)]
+pub(crate) struct SourceMap {
+    source: String,
+    // コードの中身 -> 要素の位置。同じ中身のコードはソースの順に並ぶ
+    spans: BTreeMap<Vec<Value>, Vec<Rc<[Span]>>>,
+}
+
+impl SourceMap {
+    pub(crate) fn new(source: &str, program: &[Node]) -> SourceMap {
+        let mut map = SourceMap { source: source.to_string(), spans: BTreeMap::new() };
+        map.register(program);
+        map
+    }
+
+    // トップレベルと入れ子のVectorリテラル（クオーテーションになりうるもの）を覚える
+    fn register(&mut self, nodes: &[Node]) {
+        let code: Vec<Value> = nodes.iter().map(|node| node.clone().into_value()).collect();
+        let spans: Rc<[Span]> = nodes.iter().map(|node| node.span).collect();
+        self.spans.entry(code).or_default().push(spans);
+        for node in nodes {
+            self.register_nested(node);
+        }
+    }
+
+    fn register_nested(&mut self, node: &Node) {
+        match &node.kind {
+            NodeKind::Vector(children) => self.register(children),
+            // Setは実行されないが、要素のVectorはクオーテーションとして取り出せる
+            NodeKind::Set(children) => {
+                for child in children {
+                    self.register_nested(child);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // code の要素の位置。同じ中身が複数あれば、呼び出した位置より前で最も近いもの
+    pub(crate) fn spans_for(&self, code: &[Value], call: Option<Span>) -> Option<Rc<[Span]>> {
+        let candidates = self.spans.get(code)?;
+        let preceding = call.and_then(|call| {
+            candidates.iter().rev().find(|spans| spans.first().is_some_and(|first| first.start <= call.start))
+        });
+        preceding.or(candidates.first()).cloned()
+    }
+
+    pub(crate) fn line_col(&self, span: Span) -> (usize, usize) {
+        line_col(&self.source, span.start)
+    }
+
+    // 1 始まりの行全体の範囲。行がなければ None
+    pub(crate) fn line_span(&self, line: usize) -> Option<Span> {
+        let mut start = 0;
+        for (i, text) in self.source.split_inclusive('\n').enumerate() {
+            if i + 1 == line {
+                return Some(Span::new(start, start + text.len()));
+            }
+            start += text.len();
+        }
+        None
+    }
+}
+
+// ============================================================================
+// 定義の形
+// ============================================================================
+
+// トップレベルの [body] 'name' word。def と defp はメタデータ [meta] も取れる
+pub(crate) struct Form<'a> {
+    pub(crate) word: &'a str,
+    // 開いているモジュールで修飾した名前と、書かれたままの名前
+    pub(crate) name: String,
+    pub(crate) short: &'a str,
+    pub(crate) body: &'a [Node],
+    pub(crate) meta: Option<&'a [Node]>,
+    // 本体から word まで
+    pub(crate) span: Span,
+    pub(crate) name_span: Span,
+}
+
+impl Form<'_> {
+    // メタデータの docstring とスタック効果（→ を含む例は除く）
+    pub(crate) fn metadata(&self) -> (Option<&str>, Option<StackEffect>) {
+        let mut doc = None;
+        let mut effect = None;
+        for item in self.meta.unwrap_or(&[]) {
+            if let NodeKind::Literal(Value::String(text)) = &item.kind {
+                if text.contains('→') {
+                    continue;
+                }
+                if text.trim_start().starts_with('(') {
+                    effect = effect.or_else(|| StackEffect::parse(text));
+                } else {
+                    doc = doc.or(Some(text.as_str()));
+                }
+            }
+        }
+        (doc, effect)
+    }
+}
+
+// words のどれかで終わる定義の形を、'name' module ... end を追いながら集める
+pub(crate) fn forms<'a>(program: &'a [Node], words: &[&str]) -> Vec<Form<'a>> {
+    let mut found = Vec::new();
+    let mut modules: Vec<String> = Vec::new();
+    for (i, node) in program.iter().enumerate() {
+        let Some(word) = node.word() else {
+            continue;
+        };
+        let before = &program[..i];
+        match word {
+            "module" => {
+                if let Some(NodeKind::Literal(Value::String(name))) = before.last().map(|n| &n.kind) {
+                    modules.push(name.clone());
+                }
+            }
+            "end" => {
+                modules.pop();
+            }
+            _ if words.contains(&word) => {
+                let (meta, rest) = match before.split_last() {
+                    Some((Node { kind: NodeKind::Vector(meta), .. }, rest)) if matches!(word, "def" | "defp") => {
+                        (Some(meta.as_slice()), rest)
+                    }
+                    _ => (None, before),
+                };
+                let [.., body, name] = rest else {
+                    continue;
+                };
+                if let (NodeKind::Vector(items), NodeKind::Literal(Value::String(text))) = (&body.kind, &name.kind) {
+                    found.push(Form {
+                        word,
+                        name: qualify(&modules.join("."), text),
+                        short: text,
+                        body: items,
+                        meta,
+                        span: Span::new(body.span.start, node.span.end),
+                        name_span: name.span,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
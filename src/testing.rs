@@ -0,0 +1,77 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::syntax::{forms, Node, Span};
+use crate::{Code, Value};
+
+// ============================================================================
+// テスト
+// ============================================================================
+
+// [body] 'name' test で登録したテスト
+#[derive(Debug, Clone)]
+pub(crate) struct TestCase {
+    pub(crate) name: String,
+    pub(crate) code: Code,
+    // 名前解決に使うモジュール
+    pub(crate) module: Rc<str>,
+}
+
+// 期待値と実際の値が食い違う位置。path は $ が全体、$[1][0] が入れ子の要素
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub passed: bool,
+    // 失敗したときのエラーメッセージ
+    pub message: Option<String>,
+    // assert-eq / assert-stack の食い違い
+    pub diff: Vec<Difference>,
+    // テスト中に print した内容
+    pub output: Vec<String>,
+    // テストを定義した位置（ソースから実行したときだけ分かる）
+    pub span: Option<Span>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+// 食い違いを外側から順に集める。Vectorは長さと共通部分の要素ごとに比べる
+pub(crate) fn diff_values(expected: &Value, actual: &Value, path: &str, out: &mut Vec<Difference>) {
+    if expected == actual {
+        return;
+    }
+    let mut push = |expected: String, actual: String| {
+        out.push(Difference { path: path.to_string(), expected, actual });
+    };
+    match (expected, actual) {
+        (Value::Vector(expected), Value::Vector(actual)) => {
+            if expected.len() != actual.len() {
+                push(format!("length {}", expected.len()), format!("length {}", actual.len()));
+            }
+            for (i, (e, a)) in expected.iter().zip(actual).enumerate() {
+                diff_values(e, a, &format!("{}[{}]", path, i), out);
+            }
+        }
+        (Value::Set(expected), Value::Set(actual)) => {
+            for missing in expected.difference(actual) {
+                push(missing.to_display_string(), "missing".to_string());
+            }
+            for extra in actual.difference(expected) {
+                push("absent".to_string(), extra.to_display_string());
+            }
+        }
+        _ => push(expected.to_display_string(), actual.to_display_string()),
+    }
+}
+
+// トップレベルの [body] 'name' test の位置（本体から test まで）を完全名で引く
+pub(crate) fn test_spans(program: &[Node]) -> HashMap<String, Span> {
+    forms(program, &["test"]).into_iter().map(|form| (form.name, form.span)).collect()
+}
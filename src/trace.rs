@@ -0,0 +1,143 @@
+use serde::Serialize;
+
+use crate::syntax::Span;
+use crate::{LycorisError, Scope, Value};
+
+// ============================================================================
+// 実行トレース
+// ============================================================================
+//
+// トレースモードでは実行したトークンごとに、ワード・スコープ・前後のスタック・
+// ソース上の位置を記録する。位置は SourceMap でフレームに付けたものを使う。
+
+// 記録するステップ数の上限
+const MAX_TRACE_STEPS: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceStep {
+    // フレームの深さ（トップレベルが 0）。末尾呼び出しは呼び出し元と同じ深さで続く
+    pub depth: usize,
+    // ワード名（プレフィックスなし）、またはリテラルの表示形式
+    pub word: String,
+    // ワードのスコープ。リテラルは None
+    pub scope: Option<Scope>,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+    // ソース上の位置。実行中に作られたコードでは、それを呼び出したトークンの位置
+    pub span: Option<Span>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    // このトークンの実行中に起きたエラー
+    pub error: Option<String>,
+}
+
+impl TraceStep {
+    // ソースでの書き方（@square など）
+    pub fn token(&self) -> String {
+        match self.scope {
+            Some(scope) => format!("{}{}", scope.prefix(), self.word),
+            None => self.word.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+    // 上限に達して記録をやめたか
+    pub truncated: bool,
+}
+
+impl Trace {
+    // 1ステップ1行の表: 番号、位置、深さで字下げしたトークン、前 -> 後のスタック
+    pub fn to_table(&self) -> String {
+        let locations: Vec<String> = self.steps.iter()
+            .map(|step| match (step.line, step.column) {
+                (Some(line), Some(column)) => format!("{}:{}", line, column),
+                _ => "-".to_string(),
+            })
+            .collect();
+        let tokens: Vec<String> = self.steps.iter()
+            .map(|step| format!("{}{}", "  ".repeat(step.depth), step.token()))
+            .collect();
+        let number_width = self.steps.len().to_string().len();
+        let location_width = locations.iter().map(String::len).max().unwrap_or(0);
+        let token_width = tokens.iter().map(|token| token.chars().count()).max().unwrap_or(0);
+
+        let mut table = String::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let mut line = format!(
+                "{:>number_width$}  {:<location_width$}  {:<token_width$}  {} -> {}",
+                i + 1,
+                locations[i],
+                tokens[i],
+                step.before.join(" "),
+                step.after.join(" "),
+            );
+            if let Some(error) = &step.error {
+                line.push_str("  error: ");
+                line.push_str(error);
+            }
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+        if self.truncated {
+            table.push_str(&format!("(trace stopped after {} steps)\n", MAX_TRACE_STEPS));
+        }
+        table
+    }
+}
+
+fn display_stack(stack: &[Value]) -> Vec<String> {
+    stack.iter().map(Value::to_display_string).collect()
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Tracer {
+    pub(crate) trace: Trace,
+}
+
+impl Tracer {
+    // 実行を始める。トレースは前回の実行の分を捨てる
+    pub(crate) fn start(&mut self) {
+        self.trace = Trace::default();
+    }
+
+    // トークンの実行前に記録する。上限に達していれば None
+    pub(crate) fn begin(
+        &mut self,
+        depth: usize,
+        token: &Value,
+        stack: &[Value],
+        span: Option<Span>,
+        location: Option<(usize, usize)>,
+    ) -> Option<usize> {
+        if self.trace.steps.len() >= MAX_TRACE_STEPS {
+            self.trace.truncated = true;
+            return None;
+        }
+        let (word, scope) = match token {
+            Value::Word(name, scope) => (name.clone(), Some(*scope)),
+            value => (value.to_display_string(), None),
+        };
+        let (line, column) = location.unzip();
+        self.trace.steps.push(TraceStep {
+            depth,
+            word,
+            scope,
+            before: display_stack(stack),
+            after: Vec::new(),
+            span,
+            line,
+            column,
+            error: None,
+        });
+        Some(self.trace.steps.len() - 1)
+    }
+
+    pub(crate) fn end(&mut self, step: usize, stack: &[Value], error: Option<&LycorisError>) {
+        let step = &mut self.trace.steps[step];
+        step.after = display_stack(stack);
+        step.error = error.map(ToString::to_string);
+    }
+}
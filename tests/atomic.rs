@@ -0,0 +1,66 @@
+use lycoris::{Interpreter, LycorisError};
+
+// ============================================================================
+// atomic モード
+// ============================================================================
+//
+// 失敗した実行はスタック、辞書、出力を実行前に戻す。set_atomic(false) では残す。
+
+fn stack(interpreter: &Interpreter) -> Vec<String> {
+    interpreter.stack().iter().map(|value| value.to_display_string()).collect()
+}
+
+#[test]
+fn a_failed_word_restores_the_stack() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("1 2").unwrap();
+    let error = interpreter.eval("'x' add").unwrap_err();
+    assert_eq!(error.kind(), "type");
+    assert_eq!(stack(&interpreter), ["1", "2"]);
+
+    // 実行前より下の要素を pop してから失敗しても戻る
+    assert_eq!(interpreter.eval("add add"), Err(LycorisError::StackUnderflow));
+    assert_eq!(stack(&interpreter), ["1", "2"]);
+
+    let mut fresh = Interpreter::new();
+    assert!(fresh.eval("1 2 'x' add").is_err());
+    assert!(fresh.stack().is_empty());
+}
+
+#[test]
+fn definitions_after_the_last_success_are_rolled_back() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("[1] 'one' def").unwrap();
+    assert!(interpreter.eval("[2] 'two' def [3] 'one' def 'one' undef [4] 'four' def 1 0 div").is_err());
+    let mut expected = Interpreter::new().words();
+    expected.push("one".to_string());
+    expected.sort();
+    assert_eq!(interpreter.words(), expected);
+
+    interpreter.eval("one").unwrap();
+    assert_eq!(stack(&interpreter), ["1"]);
+}
+
+#[test]
+fn output_and_clear_are_restored() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("1 print").unwrap();
+    assert!(interpreter.eval("2 print clear 3 print 1 0 div").is_err());
+    assert_eq!(interpreter.get_output(), "1");
+
+    assert!(interpreter.eval("clear 1 0 div").is_err());
+    assert_eq!(interpreter.get_output(), "1");
+}
+
+#[test]
+fn without_atomic_the_partial_work_is_kept() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_atomic(false);
+    interpreter.eval("1 print").unwrap();
+    assert!(interpreter.eval("1 2 [5] 'five' def clear 2 print 'x' add").is_err());
+
+    assert_eq!(stack(&interpreter), ["1"]);
+    assert_eq!(interpreter.get_output(), "2");
+    interpreter.eval("five").unwrap();
+    assert_eq!(stack(&interpreter), ["1", "5"]);
+}
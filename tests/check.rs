@@ -0,0 +1,94 @@
+use lycoris::{Interpreter, Severity};
+
+// ============================================================================
+// 静的検査
+// ============================================================================
+
+// (重要度, メッセージ, 指している範囲のソース, 行, 列)
+fn check(source: &str) -> Vec<(Severity, String, &str, usize, usize)> {
+    let diagnostics = Interpreter::new().check(source).unwrap();
+    diagnostics
+        .into_iter()
+        .map(|d| (d.severity, d.message, &source[d.span.start..d.span.end], d.line, d.column))
+        .collect()
+}
+
+fn error<'a>(message: &str, text: &'a str, line: usize, column: usize) -> (Severity, String, &'a str, usize, usize) {
+    (Severity::Error, message.to_string(), text, line, column)
+}
+
+fn warning<'a>(message: &str, text: &'a str, line: usize, column: usize) -> (Severity, String, &'a str, usize, usize) {
+    (Severity::Warning, message.to_string(), text, line, column)
+}
+
+#[test]
+fn well_typed_programs_have_no_diagnostics() {
+    assert!(check("1 2 add [1 2 3] 2 @mul drop 'a' 'b' concat").is_empty());
+    assert!(check("[dup mul] 'sq' ['( n -- n2 )'] def 3 sq [1 2] @sq *add").is_empty());
+    assert!(check("true [1] [2] ifelse [[a b] -> [a b sub]] 'minus' def 5 minus").is_empty());
+}
+
+#[test]
+fn type_mismatches() {
+    assert_eq!(check("1 'a' add"), [error("add expects number but got string", "add", 1, 7)]);
+    assert_eq!(check("1 2 add\n'a' 3 mul"), [error("mul expects number but got string", "mul", 2, 7)]);
+    // 1つの間違いには診断を1つだけ出す
+    assert_eq!(check("[1 2] 3 concat"), [error("concat expects vector or string but got number", "concat", 1, 9)]);
+    assert_eq!(check("1 2 concat"), [error("concat expects vector or string but got number", "concat", 1, 5)]);
+    assert_eq!(
+        check("[1 2] 'a' concat"),
+        [error("concat expects two vectors or two strings but got vector and string", "concat", 1, 11)]
+    );
+}
+
+#[test]
+fn stack_underflow_points_at_the_word() {
+    assert_eq!(check("dup"), [error("dup needs 1 value but the stack has 0", "dup", 1, 1)]);
+    assert_eq!(check("1 add"), [error("add needs 2 values but the stack has 1", "add", 1, 3)]);
+    // ユーザー定義ワードは推論したスタック効果で確かめる
+    assert_eq!(
+        check("[[a b] -> [a b add]] 'plus' def\n1 plus"),
+        [error("plus needs 2 values but the stack has 1", "plus", 2, 3)]
+    );
+}
+
+#[test]
+fn unbalanced_branches_are_warnings() {
+    assert_eq!(
+        check("true [1] [1 2] ifelse"),
+        [warning("ifelse branches have different stack effects: ( -- number ) and ( -- number number )", "ifelse", 1, 16)]
+    );
+    assert_eq!(check("true [1] if"), [warning("if branch changes the stack depth: ( -- number )", "if", 1, 10)]);
+}
+
+#[test]
+fn declared_effects_must_match_the_body() {
+    assert_eq!(
+        check("[dup mul] 'sq' ['( n -- a b )'] def"),
+        [error("sq declares ( n -- a b ) but its body has effect ( number -- number )", "'sq'", 1, 11)]
+    );
+    assert_eq!(
+        check("[dup mul] 'sq' ['( a b -- n )'] def"),
+        [error("sq declares ( a b -- n ) but its body has effect ( number -- number )", "'sq'", 1, 11)]
+    );
+    // 宣言どおりなら呼び出し側を型で確かめる
+    assert_eq!(
+        check("[dup mul] 'sq' ['( n -- n )'] def 'x' sq"),
+        [error("sq expects number but got string", "sq", 1, 39)]
+    );
+}
+
+#[test]
+fn scoped_calls_need_a_fitting_effect() {
+    assert_eq!(
+        check("[1 2] @dup"),
+        [error("@dup needs a word with effect ( x -- x ) but dup has ( x -- x x )", "@dup", 1, 7)]
+    );
+    assert_eq!(
+        check("[1 2] *dup"),
+        [error("*dup needs a word with effect ( x x -- x ) but dup has ( x -- x x )", "*dup", 1, 7)]
+    );
+    // 最後の入力がクオーテーションなら、一番上のベクタは引数になる
+    assert!(check("[[1 2] [3 4]] [1 gt] @filter [sub] @sort [add] @scan drop").is_empty());
+    assert_eq!(check("foo"), [warning("Unknown word: foo", "foo", 1, 1)]);
+}
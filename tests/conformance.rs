@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+use lycoris::{Interpreter, SourceResolver};
+
+// ============================================================================
+// 言語仕様の適合テスト
+// ============================================================================
+//
+// tests/conformance/*.lyc の `[body] 'name' test` をすべて実行する。
+// include のパスは tests/conformance からの相対パス。
+
+struct DirResolver(PathBuf);
+
+impl SourceResolver for DirResolver {
+    fn resolve(&mut self, path: &str) -> Result<String, String> {
+        std::fs::read_to_string(self.0.join(path)).map_err(|err| err.to_string())
+    }
+}
+
+fn conformance_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance")
+}
+
+fn suite_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(conformance_dir())
+        .expect("tests/conformance exists")
+        .map(|entry| entry.expect("readable entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lyc"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn conformance_suite_passes() {
+    let files = suite_files();
+    assert!(!files.is_empty(), "no conformance files found");
+
+    let mut failures = Vec::new();
+    for file in &files {
+        let source = std::fs::read_to_string(file).expect("readable source");
+        let mut interpreter = Interpreter::new();
+        interpreter.set_resolver(DirResolver(conformance_dir()));
+
+        let name = file.file_name().unwrap().to_string_lossy().into_owned();
+        let results = match interpreter.test_source(&source) {
+            Ok(results) => results,
+            Err(err) => {
+                failures.push(format!("{}: {}", name, err));
+                continue;
+            }
+        };
+        assert!(!results.is_empty(), "{} registers no tests", name);
+        for result in results.iter().filter(|result| !result.passed) {
+            let mut failure = format!(
+                "{}:{}:{}: {}: {}",
+                name,
+                result.line.unwrap_or(0),
+                result.column.unwrap_or(0),
+                result.name,
+                result.message.as_deref().unwrap_or("")
+            );
+            for difference in &result.diff {
+                failure.push_str(&format!("\n    at {}: expected {}, got {}", difference.path, difference.expected, difference.actual));
+            }
+            failures.push(failure);
+        }
+    }
+
+    assert!(failures.is_empty(), "conformance failures:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn failing_tests_are_reported_with_spans_and_diffs() {
+    let source = "[1 2 add 3 assert-eq] 'ok' test\n  [[1 2 3] [1 2 4] assert-eq] 'bad' test";
+    let mut interpreter = Interpreter::new();
+    let results = interpreter.test_source(source).unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert!(results[0].passed);
+
+    let bad = &results[1];
+    assert!(!bad.passed);
+    assert_eq!((bad.line, bad.column), (Some(2), Some(3)));
+    assert_eq!(bad.message.as_deref(), Some("assert-eq failed: expected [1 2 4], got [1 2 3]"));
+    assert_eq!(bad.diff.len(), 1);
+    assert_eq!(bad.diff[0].path, "$[2]");
+    assert_eq!((bad.diff[0].expected.as_str(), bad.diff[0].actual.as_str()), ("4", "3"));
+}
+
+#[test]
+fn tests_run_on_a_fresh_stack_and_leave_no_trace() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("1 2 [[] assert-stack [9] 'tmp' def 'hi' print] 'isolated' test").unwrap();
+
+    let results = interpreter.run_tests();
+    assert!(results[0].passed, "{:?}", results[0].message);
+    assert_eq!(results[0].output, vec!["'hi'".to_string()]);
+    assert_eq!(interpreter.stack().len(), 2);
+    assert!(interpreter.describe("tmp").is_none());
+}
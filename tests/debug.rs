@@ -0,0 +1,201 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use lycoris::{
+    Breakpoint, DebugAction, DebugHandler, DebugReply, DebugSession, Interpreter, LycorisError, Pause, Span,
+};
+
+// ============================================================================
+// デバッガ
+// ============================================================================
+
+// 止まるたびに用意したコマンドを順に実行する。コマンドが尽きたら最後まで実行する
+#[derive(Default)]
+struct Script {
+    commands: VecDeque<String>,
+    pauses: Rc<RefCell<Vec<Pause>>>,
+    outputs: Rc<RefCell<Vec<String>>>,
+}
+
+impl DebugHandler for Script {
+    fn paused(&mut self, session: &mut DebugSession) -> DebugAction {
+        self.pauses.borrow_mut().push(session.pause().clone());
+        while let Some(command) = self.commands.pop_front() {
+            match session.command(&command) {
+                DebugReply::Resume(action) => return action,
+                DebugReply::Output(text) => self.outputs.borrow_mut().push(text),
+            }
+        }
+        DebugAction::Continue
+    }
+}
+
+struct Session {
+    interpreter: Interpreter,
+    pauses: Rc<RefCell<Vec<Pause>>>,
+    outputs: Rc<RefCell<Vec<String>>>,
+}
+
+impl Session {
+    fn new(commands: &[&str]) -> Session {
+        let script = Script { commands: commands.iter().map(|command| command.to_string()).collect(), ..Script::default() };
+        let pauses = script.pauses.clone();
+        let outputs = script.outputs.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_debug_handler(script);
+        Session { interpreter, pauses, outputs }
+    }
+
+    // 止まった位置を「トークン@行:列」で
+    fn stops(&self) -> Vec<String> {
+        self.pauses.borrow().iter()
+            .map(|pause| format!("{}@{}:{}", pause.token(), pause.line.unwrap_or(0), pause.column.unwrap_or(0)))
+            .collect()
+    }
+}
+
+const SQUARES: &str = "[dup mul] 'sq' def\n3 sq 4 sq add\n[1 2] @sq";
+
+#[test]
+fn stepping_enters_steps_over_and_leaves_calls() {
+    let mut session = Session::new(&["n", "n", "n", "n", "s", "s", "n", "n", "o", "c"]);
+    session.interpreter.request_pause();
+    assert_eq!(session.interpreter.eval(SQUARES).unwrap(), "");
+    assert_eq!(
+        session.stops(),
+        [
+            "[dup mul]@1:1",
+            "'sq'@1:11",
+            "def@1:16",
+            "3@2:1",
+            // step で sq の本体に入る
+            "sq@2:3",
+            "dup@1:2",
+            // 本体の最後で next すると呼び出し元に戻る
+            "mul@1:6",
+            "4@2:6",
+            // sq の本体の外で out するとトップレベルの終わりまで進む
+            "sq@2:8",
+        ]
+    );
+    assert_eq!(session.pauses.borrow()[6].depth, 1);
+}
+
+#[test]
+fn next_over_a_tail_call_does_not_stop_inside_it() {
+    let source = "[dup 0 eq [drop] [1 sub down] ifelse] 'down' def\n[2 down] run 'done'";
+    let mut session = Session::new(&["delete 1", "n", "c"]);
+    session.interpreter.add_breakpoint(Breakpoint::Word("down".to_string()));
+    session.interpreter.eval(source).unwrap();
+    // down の本体は run のフレームを置き換えて実行されるので、next は 'done' まで進む
+    assert_eq!(session.stops(), ["down@2:4", "'done'@2:14"]);
+}
+
+#[test]
+fn word_and_span_breakpoints_stop_before_the_token_runs() {
+    let mut session = Session::new(&["stack", "c", "c", "c", "c"]);
+    let word = session.interpreter.add_breakpoint(Breakpoint::Word("mul".to_string()));
+    let span = session.interpreter.add_breakpoint(Breakpoint::Span(Span::new(39, 42)));
+    assert_eq!(session.interpreter.breakpoints().len(), 2);
+    session.interpreter.eval(SQUARES).unwrap();
+
+    assert_eq!(session.stops(), ["mul@1:6", "mul@1:6", "@sq@3:7", "mul@1:6", "mul@1:6"]);
+    let pauses = session.pauses.borrow();
+    assert_eq!(pauses[0].breakpoint, Some(word));
+    assert_eq!(pauses[2].breakpoint, Some(span));
+    assert_eq!(pauses[0].stack, vec!["3", "3"]);
+    assert_eq!(session.outputs.borrow()[0], "0: 3\n1: 3");
+
+    // スコープつきの書き方は、そのスコープの呼び出しだけに当たる
+    let mut session = Session::new(&[]);
+    session.interpreter.add_breakpoint(Breakpoint::Word("@sq".to_string()));
+    session.interpreter.eval(SQUARES).unwrap();
+    assert_eq!(session.stops(), ["@sq@3:7"]);
+
+    assert!(session.interpreter.remove_breakpoint(1));
+    assert!(!session.interpreter.remove_breakpoint(1));
+}
+
+#[test]
+fn the_call_chain_lists_user_words_with_their_positions() {
+    let source = "[dup 1 le [drop 0] [1 sub count 0 add] ifelse] 'count' def\n3 count";
+    let mut session = Session::new(&["where"]);
+    session.interpreter.add_breakpoint(Breakpoint::Word("le".to_string()));
+    session.interpreter.eval(source).unwrap();
+
+    let pauses = session.pauses.borrow();
+    assert_eq!(pauses.len(), 3);
+    let words: Vec<Option<&str>> = pauses[2].calls.iter().map(|frame| frame.word.as_deref()).collect();
+    assert_eq!(words, [None, Some("count"), Some("count"), Some("count")]);
+    assert_eq!(pauses[2].depth, 3);
+    // 呼び出し元のフレームは呼び出したトークンの位置にいる
+    assert_eq!((pauses[2].calls[1].line, pauses[2].calls[1].column), (Some(1), Some(27)));
+    assert_eq!((pauses[2].calls[3].line, pauses[2].calls[3].column), (Some(1), Some(8)));
+    assert_eq!(session.outputs.borrow()[0], "1  count at 1:8\n0  (code) at 2:3");
+}
+
+#[test]
+fn stack_values_can_be_changed_while_paused() {
+    let mut session = Session::new(&["set 1 10", "push [1 2]", "pop", "set 5 1", "push add", "c"]);
+    session.interpreter.add_breakpoint(Breakpoint::Word("add".to_string()));
+    session.interpreter.eval("1 2 add").unwrap();
+    assert_eq!(session.interpreter.get_stack_json(), r#"["11"]"#);
+
+    let outputs = session.outputs.borrow();
+    assert_eq!(outputs[0], "0: 1\n1: 10");
+    assert_eq!(outputs[1], "0: 1\n1: 10\n2: [1 2]");
+    assert_eq!(outputs[3], "error: Index out of bounds");
+    assert_eq!(outputs[4], "error: Expected a single value: add");
+}
+
+#[test]
+fn changes_made_while_paused_roll_back_with_a_failed_run() {
+    let mut session = Session::new(&["set 0 99", "c"]);
+    session.interpreter.eval("1 2").unwrap();
+    session.interpreter.add_breakpoint(Breakpoint::Word("drop".to_string()));
+    assert!(session.interpreter.eval("drop 'x' add").is_err());
+    assert_eq!(session.interpreter.get_stack_json(), r#"["1","2"]"#);
+}
+
+#[test]
+fn quitting_aborts_even_inside_try() {
+    let mut session = Session::new(&["q"]);
+    session.interpreter.add_breakpoint(Breakpoint::Word("mul".to_string()));
+    let result = session.interpreter.eval("5 [[2 3 mul] ['caught'] try] run");
+    assert_eq!(result, Err(LycorisError::Aborted));
+    assert!(session.interpreter.stack().is_empty());
+
+    // 中断した後も次の実行はふつうに進む
+    session.interpreter.eval("2 3 mul").unwrap();
+    assert_eq!(session.pauses.borrow().len(), 2);
+    session.interpreter.clear_debug_handler();
+    session.interpreter.eval("2 3 mul").unwrap();
+    assert_eq!(session.pauses.borrow().len(), 2);
+}
+
+#[test]
+fn pauses_serialize_for_the_web_ui() {
+    let mut session = Session::new(&["break 2", "breakpoints", "delete 7", "bogus"]);
+    session.interpreter.request_pause();
+    session.interpreter.eval("1\n2 add").unwrap();
+    assert_eq!(
+        serde_json::to_value(&session.pauses.borrow()[0]).unwrap(),
+        serde_json::json!({
+            "word": "1",
+            "scope": null,
+            "depth": 0,
+            "span": { "start": 0, "end": 1 },
+            "line": 1,
+            "column": 1,
+            "breakpoint": null,
+            "stack": [],
+            "calls": [{ "word": null, "span": { "start": 0, "end": 1 }, "line": 1, "column": 1 }],
+        })
+    );
+    assert_eq!(session.stops(), ["1@1:1", "2@2:1", "add@2:3"]);
+    assert_eq!(
+        *session.outputs.borrow(),
+        ["breakpoint 1 at 2", "1  2:1", "error: No breakpoint 7", "error: Unknown command: bogus (try help)"]
+    );
+}
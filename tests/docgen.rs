@@ -0,0 +1,113 @@
+use lycoris::{Interpreter, Scopes, WordDoc};
+
+// ============================================================================
+// リファレンスと doctest
+// ============================================================================
+
+const SQUARES: &str = "\
+[dup mul] 'sq' ['Square a number' '( n -- n2 )' '3 sq → 9' '[1 2] @sq → [1 4]'] def
+'geo' module
+[sq swap sq add] 'dist2' ['( x y -- d )' '3 4 dist2 → 25'] def
+[sq] 'helper' defp
+end
+[1] 'one' def";
+
+fn word<'a>(words: &'a [WordDoc], name: &str) -> &'a WordDoc {
+    words.iter().find(|word| word.name == name).unwrap_or_else(|| panic!("{} is not documented", name))
+}
+
+#[test]
+fn every_builtin_example_passes() {
+    let results = Interpreter::new().run_doctests();
+    assert!(results.len() > 100);
+    let failures: Vec<_> = results.iter().filter(|result| !result.passed).collect();
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
+
+// 変わったら cargo run --bin lycoris -- doc > docs/reference.md で作り直す
+#[test]
+fn the_checked_in_reference_is_up_to_date() {
+    let markdown = Interpreter::new().reference().to_markdown();
+    assert!(markdown == include_str!("../docs/reference.md"), "docs/reference.md is out of date");
+}
+
+#[test]
+fn scopes_follow_the_stack_effect() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval(SQUARES).unwrap();
+    let reference = interpreter.reference();
+    let all = Scopes { map: true, reduce: true, global: true };
+
+    assert_eq!(word(&reference.words, "add").scopes, Some(all));
+    assert_eq!(word(&reference.words, "length").scopes, Some(Scopes { map: true, ..Scopes::default() }));
+    assert_eq!(word(&reference.words, "dup").scopes, Some(Scopes::default()));
+    assert_eq!(word(&reference.words, "if").scopes, Some(Scopes::default()));
+    assert_eq!(word(&reference.words, "geo.dist2").scopes, Some(all));
+    assert_eq!(word(&reference.words, "one").scopes, None);
+}
+
+#[test]
+fn user_words_are_grouped_by_module_and_linked() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval(SQUARES).unwrap();
+    let reference = interpreter.reference();
+
+    let sq = word(&reference.words, "sq");
+    assert!(!sq.builtin);
+    assert_eq!(sq.section, "User Words");
+    assert_eq!(sq.doc.as_deref(), Some("Square a number"));
+    assert_eq!(sq.examples.len(), 2);
+    assert_eq!((sq.examples[1].source.as_str(), sq.examples[1].expected.as_str()), ("[1 2] @sq", "[1 4]"));
+    assert_eq!(sq.see_also, ["dup", "mul"]);
+
+    // モジュールの中の短い名前も完全名に結びつく。非公開のワードは載せない
+    let dist2 = word(&reference.words, "geo.dist2");
+    assert_eq!(dist2.section, "Module geo");
+    assert_eq!(dist2.see_also, ["sq", "swap", "add"]);
+    assert!(!reference.words.iter().any(|word| word.name == "geo.helper"));
+
+    let markdown = reference.to_markdown();
+    assert!(markdown.contains("- [Module geo](#section-module-geo)\n"));
+    assert!(markdown.contains("### <a id=\"word-sq\"></a>`sq`\n\n`( n -- n2 )` · Scopes: `@sq`\n"));
+    assert!(markdown.contains("```\n3 sq → 9\n[1 2] @sq → [1 4]\n```\n"));
+    assert!(markdown.contains("See also: [`dup`](#word-dup), [`mul`](#word-mul)\n"));
+}
+
+#[test]
+fn html_is_escaped_and_anchored() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("[1] '<b>' ['Say \"<hi>\" & leave' '<b> → 1'] def").unwrap();
+    let html = interpreter.reference().to_html();
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<article id=\"word-_3cb_3e\">\n<h3><code>&lt;b&gt;</code></h3>"));
+    assert!(html.contains("<p>Say &quot;&lt;hi&gt;&quot; &amp; leave</p>"));
+    assert!(html.contains("<pre>&lt;b&gt; → 1\n</pre>"));
+    assert!(html.contains("<a href=\"#section-stack-operations\">Stack Operations</a>"));
+    assert!(html.contains("<code>@add</code> <code>*add</code> <code>#add</code>"));
+}
+
+#[test]
+fn user_examples_run_with_the_registered_tests() {
+    let mut interpreter = Interpreter::new();
+    let results = interpreter
+        .test_source("[dup add] 'twice' ['2 twice → 4' '2 twice → 5' 'no arrow here'] def [1 1 assert-eq] 'one' test")
+        .unwrap();
+    let summary: Vec<(&str, bool)> = results.iter().map(|result| (result.name.as_str(), result.passed)).collect();
+    assert_eq!(summary, [("one", true), ("twice example 1", true), ("twice example 2", false)]);
+    assert_eq!(results[2].diff[0].expected, "5");
+    assert_eq!(results[2].diff[0].actual, "4");
+
+    // 例は定義のメタデータとして残り、説明文にはならない
+    let info = interpreter.get_word_info_json("twice").unwrap();
+    let info: serde_json::Value = serde_json::from_str(&info).unwrap();
+    assert_eq!(info["doc"], "no arrow here");
+    assert_eq!(info["examples"], serde_json::json!(["2 twice → 4", "2 twice → 5"]));
+
+    // see の出力を実行し直すと例も戻る
+    let source = info["source"].as_str().unwrap().to_string();
+    let mut copy = Interpreter::new();
+    copy.eval(&source).unwrap();
+    assert_eq!(copy.get_word_info_json("twice").unwrap(), interpreter.get_word_info_json("twice").unwrap());
+    assert_eq!(copy.run_tests().len(), 2);
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use lycoris::{FormatOptions, Interpreter, ItemKind};
+
+// ============================================================================
+// フォーマッタ
+// ============================================================================
+
+fn format(source: &str) -> String {
+    Interpreter::new().format(source, &FormatOptions::default()).unwrap()
+}
+
+fn format_width(source: &str, width: usize) -> String {
+    Interpreter::new().format(source, &FormatOptions { width }).unwrap()
+}
+
+#[test]
+fn tokens_are_separated_by_single_spaces() {
+    assert_eq!(format("1 2add3mul   print"), "1 2 add 3 mul print\n");
+    assert_eq!(format("[dup    mul]'square'def"), "[dup mul] 'square' def\n");
+    assert_eq!(format("{1 2   3} [a b]->[a b add]"), "{1 2 3} [a b] -> [a b add]\n");
+    assert_eq!(format("[dup mul] 'sq' def [1 2]@sq*add"), "[dup mul] 'sq' def [1 2] @sq *add\n");
+    assert_eq!(format(""), "");
+}
+
+#[test]
+fn line_breaks_and_comments_are_kept() {
+    let source = "# header\n1 2 add   # sum\n\n\n\nprint\n";
+    assert_eq!(format(source), "# header\n1 2 add # sum\n\nprint\n");
+}
+
+#[test]
+fn multi_line_vectors_are_indented() {
+    let source = "[\n  dup 0 eq\n      [drop]\n  [1 sub down]   ifelse\n] 'down' def";
+    assert_eq!(format(source), "[\n    dup 0 eq\n    [drop]\n    [1 sub down] ifelse\n] 'down' def\n");
+
+    let nested = "[ # note\n[1\n2] 3]";
+    assert_eq!(format(nested), "[ # note\n    [\n        1\n        2\n    ] 3\n]\n");
+}
+
+#[test]
+fn long_lines_wrap_at_the_width() {
+    assert_eq!(format_width("1 2 3 4 5 6 7 8", 7), "1 2 3 4\n5 6 7 8\n");
+    // 行頭のクオーテーションは後ろのワードを閉じ括弧の行に残して改行する
+    assert_eq!(
+        format_width("[1 2 add 3 assert-eq] 'sum' test", 24),
+        "[\n    1 2 add 3 assert-eq\n] 'sum' test\n"
+    );
+    assert_eq!(format_width("1 [2 3] [4 5]", 9), "1 [2 3]\n[4 5]\n");
+}
+
+#[test]
+fn syntax_tree_reproduces_the_source() {
+    let source = "  [1  {2}\t# c\n ] 'x' def\n\n# end  ";
+    let tree = Interpreter::new().syntax_tree(source).unwrap();
+    assert_eq!(tree.to_string(), source);
+    assert_eq!(tree.items.len(), 4);
+    assert!(matches!(&tree.items[3].kind, ItemKind::Comment(text) if text == "# end  "));
+    let ItemKind::Group { open: '[', body } = &tree.items[0].kind else {
+        panic!("expected a vector: {:?}", tree.items[0]);
+    };
+    assert_eq!(body.items.len(), 3);
+    assert_eq!(body.trailing, "\n ");
+}
+
+#[test]
+fn unclosed_vectors_are_rejected() {
+    assert!(Interpreter::new().format("[1 2", &FormatOptions::default()).is_err());
+    assert!(Interpreter::new().format("'open", &FormatOptions::default()).is_err());
+}
+
+#[test]
+fn conformance_sources_keep_their_tokens() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "lyc") {
+            continue;
+        }
+        let source = std::fs::read_to_string(&path).unwrap();
+        let interpreter = Interpreter::new();
+        assert_eq!(interpreter.syntax_tree(&source).unwrap().to_string(), source);
+
+        // format が元とトークン列を比べるので、成功すれば意味は変わっていない
+        let formatted = format(&source);
+        assert_eq!(format(&formatted), formatted, "{} is not stable", path.display());
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+
+use lycoris::{Interpreter, LycorisError, SourceResolver};
+
+// ============================================================================
+// include
+// ============================================================================
+//
+// ファイルを読むのはホストのリゾルバなので、ここでは決まったソースを返すリゾルバと
+// CLI（実際のファイル）の両方で確かめる。
+
+struct Sources(HashMap<String, String>);
+
+impl SourceResolver for Sources {
+    fn resolve(&mut self, key: &str) -> Result<String, String> {
+        self.0.get(key).cloned().ok_or_else(|| "not found".to_string())
+    }
+}
+
+fn with_sources(sources: &[(&str, &str)]) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    let sources = sources.iter().map(|(path, source)| (path.to_string(), source.to_string())).collect();
+    interpreter.set_resolver(Sources(sources));
+    interpreter
+}
+
+// 失敗した include は include 済みにならず、もう一度試すと同じエラーになる
+#[test]
+fn a_failed_include_can_be_retried() {
+    let mut interpreter = with_sources(&[("bad.lyc", "[1] 'one' def 1 0 div")]);
+    let error = interpreter.eval("['nope.lyc' include] [error-kind] try print 'nope.lyc' include 'after' print").unwrap_err();
+    assert_eq!(error, LycorisError::Include("Cannot include nope.lyc: not found".to_string()));
+
+    // 本体の途中で失敗したファイルも同じ
+    interpreter.eval("['bad.lyc' include] [error-kind] try drop").unwrap();
+    assert_eq!(interpreter.eval("'bad.lyc' include"), Err(LycorisError::DivisionByZero));
+}
+
+#[test]
+fn a_file_runs_once_even_when_it_includes_itself() {
+    let mut interpreter = with_sources(&[
+        ("a.lyc", "1 print 'b.lyc' include"),
+        ("b.lyc", "2 print 'a.lyc' include"),
+    ]);
+    let output = interpreter.eval("'a.lyc' include 'b.lyc' include 'a.lyc' include").unwrap();
+    assert_eq!(output, "1\n2");
+}
+
+// CLI はファイルを include を書いたファイルのディレクトリから読み、正規化したパスで見分ける
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lycoris-include-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    dir
+}
+
+fn run(file: PathBuf) -> (bool, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lycoris")).arg(file).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).trim_end().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+    (output.status.success(), stdout, stderr)
+}
+
+#[test]
+fn nested_includes_are_relative_to_the_including_file() {
+    let dir = scratch_dir("nested");
+    std::fs::write(dir.join("lib.lyc"), "0 print [2 mul] 'double' def").unwrap();
+    std::fs::write(dir.join("sub").join("c.lyc"), "'../lib.lyc' include [double 1 add] 'f' def").unwrap();
+    std::fs::write(dir.join("main.lyc"), "'sub/c.lyc' include './lib.lyc' include 'lib.lyc' include 5 f print").unwrap();
+
+    assert_eq!(run(dir.join("main.lyc")), (true, "0\n11".to_string(), String::new()));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_missing_file_is_an_include_error() {
+    let dir = scratch_dir("missing");
+    std::fs::write(dir.join("sub").join("c.lyc"), "'lib.lyc' include").unwrap();
+    std::fs::write(dir.join("lib.lyc"), "1").unwrap();
+    std::fs::write(dir.join("main.lyc"), "'sub/c.lyc' include").unwrap();
+
+    let (success, _, stderr) = run(dir.join("main.lyc"));
+    assert!(!success);
+    assert!(stderr.contains("Cannot include lib.lyc"), "{}", stderr);
+    std::fs::remove_dir_all(dir).unwrap();
+}
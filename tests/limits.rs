@@ -0,0 +1,154 @@
+use lycoris::{Interpreter, Limits, LycorisError};
+
+// ============================================================================
+// 資源制限
+// ============================================================================
+//
+// 制限に当たったら決まったエラーで止まり、同じインタプリタで実行を続けられること。
+
+fn limited(limits: Limits) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_limits(limits);
+    interpreter
+}
+
+fn stack(interpreter: &Interpreter) -> Vec<String> {
+    interpreter.stack().iter().map(|value| value.to_display_string()).collect()
+}
+
+// 制限のエラーは try で捕まえられない
+fn assert_uncatchable(interpreter: &mut Interpreter, source: &str, expected: LycorisError) {
+    assert_eq!(interpreter.eval(source), Err(expected.clone()));
+    let caught = format!("[{}] [error-kind] try", source);
+    assert_eq!(interpreter.eval(&caught), Err(expected));
+}
+
+#[test]
+fn steps() {
+    let mut interpreter = limited(Limits { max_steps: Some(1_000), ..Limits::default() });
+    interpreter.eval("[1 add loop] 'loop' def 7").unwrap();
+    let error = interpreter.eval("0 loop").unwrap_err();
+    assert_eq!((error.kind(), &error), ("step-limit", &LycorisError::StepLimitExceeded(1_000)));
+
+    // 回数は execute ごとに数え直す
+    interpreter.eval("0 100 range *add").unwrap();
+    assert_eq!(stack(&interpreter), ["7", "4950"]);
+    assert_uncatchable(&mut interpreter, "0 loop", LycorisError::StepLimitExceeded(1_000));
+}
+
+#[test]
+fn call_depth() {
+    let mut interpreter = limited(Limits { max_call_depth: Some(10), ..Limits::default() });
+    interpreter.eval("[dup 0 eq [] [1 sub deep 1 add] ifelse] 'deep' def").unwrap();
+    let error = interpreter.eval("100 deep").unwrap_err();
+    assert_eq!((error.kind(), &error), ("call-depth-limit", &LycorisError::CallDepthExceeded(10)));
+
+    interpreter.eval("5 deep").unwrap();
+    assert_eq!(stack(&interpreter), ["5"]);
+    assert_uncatchable(&mut interpreter, "100 deep", LycorisError::CallDepthExceeded(10));
+}
+
+// run、if、match などのクオーテーションや末尾呼び出しは呼び出しの深さに数えない
+#[test]
+fn call_depth_counts_only_user_word_calls() {
+    let mut interpreter = limited(Limits { max_call_depth: Some(2), ..Limits::default() });
+    interpreter.eval("[[[[[[1] run] run] run] run] run] run true [[2] run] if").unwrap();
+    interpreter.eval("[[n] -> [[[n 1 add] run] run]] 'inc' def [inc inc] 'inc2' def 0 inc2").unwrap();
+    interpreter.eval("[dup 0 eq [drop] [1 sub down] ifelse] 'down' def 1000 down").unwrap();
+    assert_eq!(stack(&interpreter), ["1", "2", "2"]);
+
+    interpreter.eval("[inc] 'a' def [a 0 add] 'b' def [b 0 add] 'c' def").unwrap();
+    assert_eq!(interpreter.eval("0 c"), Err(LycorisError::CallDepthExceeded(2)));
+    interpreter.eval("0 b").unwrap();
+}
+
+#[test]
+fn stack_size() {
+    let mut interpreter = limited(Limits { max_stack: Some(10), ..Limits::default() });
+    let error = interpreter.eval("0 20 range unpack").unwrap_err();
+    assert_eq!((error.kind(), &error), ("stack-limit", &LycorisError::StackLimitExceeded(10)));
+    assert!(interpreter.stack().is_empty());
+
+    interpreter.eval("0 5 range unpack").unwrap();
+    assert_eq!(stack(&interpreter), ["0", "1", "2", "3", "4"]);
+    assert_uncatchable(&mut interpreter, "0 20 range unpack", LycorisError::StackLimitExceeded(10));
+}
+
+#[test]
+fn number_size() {
+    let mut interpreter = limited(Limits { max_number_bits: Some(64), ..Limits::default() });
+    let error = interpreter.eval("2 100 pow").unwrap_err();
+    assert_eq!((error.kind(), &error), ("number-too-large", &LycorisError::NumberTooLarge(64)));
+    assert_eq!(interpreter.eval("1e30"), Err(LycorisError::NumberTooLarge(64)));
+
+    interpreter.eval("2 10 pow").unwrap();
+    assert_eq!(stack(&interpreter), ["1024"]);
+    assert_uncatchable(&mut interpreter, "2 63 pow 2 mul 2 mul", LycorisError::NumberTooLarge(64));
+}
+
+#[test]
+fn value_length() {
+    let mut interpreter = limited(Limits { max_length: Some(16), ..Limits::default() });
+    let error = interpreter.eval("'abcdefghij' dup concat").unwrap_err();
+    assert_eq!((error.kind(), &error), ("length-limit", &LycorisError::LengthLimitExceeded(16)));
+    assert_eq!(interpreter.eval("0 10 range dup concat"), Err(LycorisError::LengthLimitExceeded(16)));
+    assert_eq!(interpreter.eval("['abcdef' 'abcdef' 'abcdef'] '' join"), Err(LycorisError::LengthLimitExceeded(16)));
+    assert_eq!(interpreter.eval("'aaaa' 'a' 'bbbbb' replace"), Err(LycorisError::LengthLimitExceeded(16)));
+
+    interpreter.eval("'ab' 'cd' concat").unwrap();
+    assert_eq!(stack(&interpreter), ["'abcd'"]);
+    assert_uncatchable(&mut interpreter, "'abcdefghij' dup concat", LycorisError::LengthLimitExceeded(16));
+}
+
+// 要素数は作る前に確かめるので、巨大な range や iota もすぐに止まる
+#[test]
+fn huge_ranges_are_refused_before_allocating() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(interpreter.eval("18446744073709551615 iota"), Err(LycorisError::LengthLimitExceeded(1 << 20)));
+    assert_eq!(interpreter.eval("0 100000000000 range"), Err(LycorisError::LengthLimitExceeded(1 << 20)));
+
+    let mut interpreter = limited(Limits { max_length: None, ..Limits::default() });
+    let error = interpreter.eval("18446744073709551615 iota").unwrap_err();
+    assert_eq!(error.kind(), "invalid-argument");
+    interpreter.eval("3 iota 2 5 range concat").unwrap();
+    assert_eq!(stack(&interpreter), ["[0 1 2 2 3 4]"]);
+}
+
+// 数十トークンで文字列を倍々に伸ばしても、メモリを使い切る前に止まる
+#[test]
+fn doubling_a_string_stops_at_the_default_length() {
+    let mut interpreter = limited(Limits { max_steps: Some(10_000), max_stack: Some(1_000), ..Limits::default() });
+    let source = format!("'a'{}", " dup concat".repeat(40));
+    assert_eq!(interpreter.eval(&source), Err(LycorisError::LengthLimitExceeded(1 << 20)));
+    assert!(interpreter.stack().is_empty());
+
+    interpreter.eval(&format!("'a'{} length", " dup concat".repeat(20))).unwrap();
+    assert_eq!(stack(&interpreter), ["1048576"]);
+}
+
+// atomic でなければ制限に当たる前の結果が残り、そのまま続けられる
+#[test]
+fn partial_work_survives_a_limit_without_atomic() {
+    let mut interpreter = limited(Limits { max_stack: Some(3), ..Limits::default() });
+    interpreter.set_atomic(false);
+    assert_eq!(interpreter.eval("1 2 3 4"), Err(LycorisError::StackLimitExceeded(3)));
+    assert_eq!(stack(&interpreter), ["1", "2", "3"]);
+
+    interpreter.eval("add add").unwrap();
+    assert_eq!(stack(&interpreter), ["6"]);
+}
+
+// 括弧の入れ子は読み込みの時点で制限し、ネイティブのスタックを使い切らない
+#[test]
+fn bracket_nesting_is_limited_when_reading() {
+    let mut interpreter = Interpreter::new();
+    let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    interpreter.eval(&format!("{} length", nested(256))).unwrap();
+    assert_eq!(stack(&interpreter), ["1"]);
+
+    let error = LycorisError::Syntax("Nesting deeper than 256 at position 256".to_string());
+    assert_eq!(interpreter.eval(&nested(257)), Err(error.clone()));
+    assert_eq!(interpreter.eval(&"[".repeat(100_000)), Err(error.clone()));
+    assert_eq!(interpreter.eval(&"{".repeat(100_000)), Err(error));
+    assert_eq!(stack(&interpreter), ["1"]);
+}
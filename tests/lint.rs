@@ -0,0 +1,72 @@
+use lycoris::{Interpreter, Lint, Severity, Span};
+
+// ============================================================================
+// リンター
+// ============================================================================
+
+fn lint(source: &str) -> Vec<Lint> {
+    Interpreter::new().lint(source).unwrap()
+}
+
+fn codes(source: &str) -> Vec<&'static str> {
+    lint(source).into_iter().map(|lint| lint.code).collect()
+}
+
+#[test]
+fn runs_without_whitespace_that_split_are_reported() {
+    let lints = lint("1 addx");
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].code, "split-word");
+    assert_eq!(lints[0].severity, Severity::Warning);
+    assert_eq!(lints[0].message, "'addx' is read as 2 words: add x");
+    assert_eq!(lints[0].span, Span::new(2, 6));
+    assert_eq!((lints[0].line, lints[0].column), (1, 3));
+
+    let lints = lint("[1 2add3mul]");
+    assert_eq!(lints[0].code, "glued-number");
+    assert_eq!(lints[0].message, "'2add3mul' glues numbers and words together and is read as 2 add 3 mul");
+
+    // 文字列と括弧は空白がなくても区切りがはっきりしている
+    assert!(codes("[dup mul]'square'def [1 2]'x'").is_empty());
+    assert!(codes("[3 rot] 'rotate' def 1 2 3 rotate 1/2 -3").iter().all(|code| *code != "split-word"));
+}
+
+#[test]
+fn definitions_that_overlap_builtins_are_reported() {
+    let lints = lint("[3 rot] 'rotate' def");
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].code, "shadows-builtin");
+    assert_eq!(
+        lints[0].message,
+        "'rotate' starts with the builtin 'rot'; where 'rotate' is not defined it is read as rot ate"
+    );
+    assert_eq!(lints[0].span, Span::new(8, 16));
+
+    assert_eq!(codes("[1] 'concat' def"), vec!["redefines-builtin"]);
+    assert_eq!(lint("[1] 'concat' def")[0].severity, Severity::Error);
+    assert_eq!(codes("['x'] 'len' def"), vec!["shadows-builtin", "prefixes-builtin"]);
+    assert_eq!(codes("'m' module [1] 'rotate' def end"), vec!["shadows-builtin"]);
+    assert_eq!(codes("[[1] 'rotate' def] 't' test"), vec!["shadows-builtin"]);
+    assert!(codes("[1] 'square' def").is_empty());
+}
+
+#[test]
+fn global_scope_written_as_a_comment_is_reported() {
+    let lints = lint("1 2 3 #add\n[#sum is unknown, #mul is not\n]");
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].code, "global-scope");
+    assert_eq!(lints[0].message, "'#add' is a comment, not 'add' with the # scope; the rest of the line is ignored");
+    assert_eq!(lints[0].span, Span::new(6, 10));
+
+    assert_eq!(codes("[1] 'one' def #one"), vec!["global-scope"]);
+    assert!(codes("# add two numbers\n1 2 add").is_empty());
+}
+
+#[test]
+fn lints_serialize_with_code_and_span() {
+    let json = Interpreter::new().lint_json("addx").unwrap();
+    assert_eq!(
+        json,
+        r#"[{"code":"split-word","severity":"warning","message":"'addx' is read as 2 words: add x","span":{"start":0,"end":4},"line":1,"column":1}]"#
+    );
+}
@@ -0,0 +1,122 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde_json::Value as Json;
+
+// ============================================================================
+// 言語サーバーの記録済みセッション
+// ============================================================================
+//
+// tests/lsp/*.json は {"send": ...} と {"expect": ...} の列。
+// send をすべて lycoris-lsp の標準入力へ送り、標準出力のメッセージを
+// expect と順に突き合わせる。
+
+fn session_files() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("lsp");
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .expect("tests/lsp exists")
+        .map(|entry| entry.expect("readable entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    files
+}
+
+fn frame(message: &Json) -> Vec<u8> {
+    let body = message.to_string();
+    let mut framed = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    framed.extend_from_slice(body.as_bytes());
+    framed
+}
+
+// Content-Length で区切られたメッセージをすべて読む
+fn read_messages(output: &[u8]) -> Vec<Json> {
+    let mut reader = BufReader::new(output);
+    let mut messages = Vec::new();
+    loop {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).expect("readable header") == 0 {
+                return messages;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = Some(value.trim().parse::<usize>().expect("numeric Content-Length"));
+            }
+        }
+        let mut body = vec![0; length.expect("Content-Length header")];
+        reader.read_exact(&mut body).expect("complete body");
+        messages.push(serde_json::from_slice(&body).expect("JSON body"));
+    }
+}
+
+fn replay(path: &Path) -> Result<(), String> {
+    let session: Vec<Json> = serde_json::from_str(&std::fs::read_to_string(path).expect("readable session"))
+        .map_err(|err| format!("invalid session: {}", err))?;
+
+    let mut input = Vec::new();
+    let mut expected = Vec::new();
+    for entry in &session {
+        if let Some(message) = entry.get("send") {
+            input.extend(frame(message));
+        } else if let Some(message) = entry.get("expect") {
+            expected.push(message.clone());
+        } else {
+            return Err(format!("entry is neither send nor expect: {}", entry));
+        }
+    }
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lycoris-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("lycoris-lsp starts");
+    child.stdin.take().unwrap().write_all(&input).expect("writable stdin");
+    let output = child.wait_with_output().expect("lycoris-lsp exits");
+    if !output.status.success() {
+        return Err(format!("lycoris-lsp exited with {}", output.status));
+    }
+
+    let actual = read_messages(&output.stdout);
+    for (i, (expected, actual)) in expected.iter().zip(&actual).enumerate() {
+        if expected != actual {
+            return Err(format!("message {} differs\n  expected: {}\n  actual:   {}", i, expected, actual));
+        }
+    }
+    if expected.len() != actual.len() {
+        return Err(format!("expected {} messages but got {}", expected.len(), actual.len()));
+    }
+    Ok(())
+}
+
+#[test]
+fn recorded_sessions_replay() {
+    let files = session_files();
+    assert!(!files.is_empty(), "no session files found");
+
+    let failures: Vec<String> = files.iter()
+        .filter_map(|file| {
+            replay(file).err().map(|err| format!("{}: {}", file.file_name().unwrap().to_string_lossy(), err))
+        })
+        .collect();
+    assert!(failures.is_empty(), "session failures:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn malformed_messages_get_a_parse_error() {
+    let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+    input.extend(frame(&serde_json::json!({ "jsonrpc": "2.0", "method": "exit" })));
+
+    let mut output = Vec::new();
+    lycoris::lsp::run(&input[..], &mut output).unwrap();
+
+    let messages = read_messages(&output);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["error"]["code"], -32700);
+    assert_eq!(messages[0]["id"], Json::Null);
+}
@@ -0,0 +1,130 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use lycoris::{Interpreter, Profile, ProfileClock, WordProfile};
+
+// ============================================================================
+// プロファイラ
+// ============================================================================
+
+// 読むたびに 1 進む時計。確保量も読むたびに 2 バイト増える
+#[derive(Clone, Default)]
+struct TickClock(Rc<Cell<u64>>, Rc<Cell<u64>>);
+
+impl ProfileClock for TickClock {
+    fn now(&mut self) -> u64 {
+        self.0.set(self.0.get() + 1);
+        self.0.get()
+    }
+
+    fn allocated(&mut self) -> u64 {
+        self.1.set(self.1.get() + 2);
+        self.1.get()
+    }
+}
+
+fn profile(source: &str) -> Profile {
+    let mut interpreter = Interpreter::new();
+    interpreter.start_profiling(TickClock::default());
+    interpreter.eval(source).unwrap();
+    interpreter.profile().unwrap()
+}
+
+fn word<'a>(profile: &'a Profile, name: &str) -> &'a WordProfile {
+    profile.words.iter().find(|word| word.name == name).unwrap_or_else(|| panic!("{} was not profiled", name))
+}
+
+#[test]
+fn calls_are_counted_per_builtin_and_user_word() {
+    let profile = profile("[dup mul] 'sq' def\n3 sq 4 sq add\n[1 2 3] @sq");
+
+    assert_eq!(word(&profile, "sq").calls, 5);
+    assert!(!word(&profile, "sq").builtin);
+    assert_eq!(word(&profile, "mul").calls, 5);
+    assert!(word(&profile, "mul").builtin);
+    assert_eq!(word(&profile, "add").calls, 1);
+    assert_eq!(word(&profile, "@sq").calls, 1);
+}
+
+#[test]
+fn nested_user_words_have_inclusive_and_exclusive_time() {
+    let profile = profile("[dup mul] 'sq' def [sq sq 0 add] 'quad' def 2 quad");
+    let quad = word(&profile, "quad");
+    let sq = word(&profile, "sq");
+    let mul = word(&profile, "mul");
+
+    // 包括時間は呼び出したワードの分を含み、自己時間は含まない
+    assert!(quad.inclusive_ns > sq.inclusive_ns);
+    assert!(sq.inclusive_ns > mul.inclusive_ns);
+    assert!(quad.exclusive_ns < quad.inclusive_ns);
+    assert_eq!(mul.exclusive_ns, mul.inclusive_ns);
+    assert!(sq.inclusive_bytes >= sq.exclusive_bytes && sq.exclusive_bytes > 0);
+
+    // 自己時間を合わせると全体になる
+    let top = profile.stacks.get("(top)").copied().unwrap_or(0);
+    let exclusive: u64 = profile.words.iter().map(|word| word.exclusive_ns).sum();
+    assert_eq!(exclusive + top, profile.total_ns);
+    assert_eq!(profile.stacks.values().sum::<u64>(), profile.total_ns);
+}
+
+#[test]
+fn recursion_and_tail_calls_fold_into_stacks() {
+    let profile = profile(
+        "[[n] -> [n 1 le [1] [n n 1 sub fact mul] ifelse]] 'fact' def 3 fact\n\
+         [dup 0 eq [drop] [1 sub down] ifelse] 'down' def 3 down",
+    );
+
+    let stacks: Vec<&str> = profile.stacks.keys().map(String::as_str).collect();
+    assert!(stacks.contains(&"fact;fact;fact;le"), "{:?}", stacks);
+    assert!(stacks.contains(&"fact;fact;mul"), "{:?}", stacks);
+    // 末尾呼び出しは呼び出し元のフレームを置き換えるので積み重ならない
+    assert!(stacks.contains(&"down;sub"), "{:?}", stacks);
+    assert!(stacks.contains(&"down;down"), "{:?}", stacks);
+    assert!(!stacks.iter().any(|stack| stack.starts_with("down;down;")), "{:?}", stacks);
+
+    // 再帰しても包括時間は二重に数えない
+    assert!(word(&profile, "fact").inclusive_ns <= profile.total_ns);
+    assert_eq!(word(&profile, "fact").calls, 3);
+
+    let folded = profile.to_folded();
+    assert!(folded.lines().all(|line| line.rsplit_once(' ').is_some_and(|(_, ns)| ns.parse::<u64>().is_ok())));
+}
+
+#[test]
+fn reports_are_sorted_by_exclusive_time() {
+    let profile = profile("[dup mul] 'sq' def [1 2 3 4] @sq *add");
+    let exclusive: Vec<u64> = profile.words.iter().map(|word| word.exclusive_ns).collect();
+    let mut sorted = exclusive.clone();
+    sorted.sort_by(|a, b| b.cmp(a));
+    assert_eq!(exclusive, sorted);
+
+    let report = profile.to_report();
+    let mut lines = report.lines();
+    assert_eq!(
+        lines.next().unwrap().split_whitespace().collect::<Vec<_>>(),
+        ["word", "calls", "inclusive", "exclusive", "allocated"]
+    );
+    assert!(report.lines().last().unwrap().starts_with("total "));
+}
+
+#[test]
+fn profiling_is_optional_and_restartable() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval("1 2 add").unwrap();
+    assert!(interpreter.profile().is_none());
+    assert_eq!(interpreter.get_profile_json(), "null");
+
+    interpreter.start_profiling(TickClock::default());
+    interpreter.eval("1 2 add").unwrap();
+    interpreter.eval("3 add").unwrap();
+    assert_eq!(word(&interpreter.profile().unwrap(), "add").calls, 2);
+
+    let json: serde_json::Value = serde_json::from_str(&interpreter.get_profile_json()).unwrap();
+    assert_eq!(json["words"][0]["name"], "add");
+    assert_eq!(json["words"][0]["calls"], 2);
+
+    interpreter.start_profiling(TickClock::default());
+    assert!(interpreter.profile().unwrap().words.is_empty());
+    interpreter.stop_profiling();
+    assert!(interpreter.profile().is_none());
+}
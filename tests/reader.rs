@@ -0,0 +1,83 @@
+use lycoris::{Interpreter, Limits, LycorisError, Node, Scope, Value};
+
+// ============================================================================
+// ソースの読み込みと値の表示
+// ============================================================================
+//
+// fuzz/ の tokenize と execute ターゲットで見つかった入力を元にしている。
+
+fn read(source: &str) -> Result<Vec<Value>, LycorisError> {
+    Ok(Interpreter::new().read(source)?.into_iter().map(Node::into_value).collect())
+}
+
+fn printed(source: &str) -> String {
+    Value::Vector(read(source).unwrap()).to_display_string()
+}
+
+#[test]
+fn printed_programs_read_back_the_same() {
+    for source in [
+        "[a b] -> [a b add] [5]",
+        "[[a b] -> [a b add]] 'plus' def",
+        "[n] 1 -> [n 0 eq] [drop]",
+        "'[' [']'] '# not a comment' # a comment [",
+        "-0.5 1.25e-1 2/4",
+    ] {
+        let program = Value::Vector(read(source).unwrap());
+        let text = program.to_display_string();
+        assert_eq!(read(&text).unwrap(), [program], "{} printed as {}", source, text);
+    }
+}
+
+#[test]
+fn local_bindings_print_in_infix_form() {
+    assert_eq!(printed("[a b] -> [a b add] [5]"), "[[a b] -> [a b add] [5]]");
+    // 名前のベクタに続かない -> は並べ替えない
+    assert_eq!(printed("1 -> [x] [y]"), "[1 -> [x] [y]]");
+
+    let mut interpreter = Interpreter::new();
+    let output = interpreter.eval("[[a b] -> [a b sub]] 'minus' def 'minus' see").unwrap();
+    assert_eq!(output, "[[a b] -> [a b sub]] 'minus' def");
+}
+
+#[test]
+fn unclosed_brackets_are_syntax_errors() {
+    assert_eq!(read("1 [2 3"), Err(LycorisError::Syntax("Unclosed [ at position 2".to_string())));
+    assert_eq!(read("{1 '}'"), Err(LycorisError::Syntax("Unclosed { at position 0".to_string())));
+    assert!(read("[1 # ]\n").is_err());
+    assert!(read("[1 # [\n]").is_ok());
+}
+
+#[test]
+fn number_literals_respect_the_size_limit() {
+    assert_eq!(read("1/0"), Err(LycorisError::DivisionByZero));
+    assert_eq!(read("1e9999999999"), Err(LycorisError::NumberTooLarge(1 << 20)));
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_limits(Limits { max_number_bits: None, ..Limits::default() });
+    assert_eq!(
+        interpreter.read("1e9999999999").map(|_| ()),
+        Err(LycorisError::InvalidArgument("Exponent out of range".to_string()))
+    );
+}
+
+// 空白を含む名前は1つのトークンにならないので、同じ入力の def で先読みしない
+#[test]
+fn names_with_spaces_are_not_read_as_one_word() {
+    let values = read("[1] 'a b' def a b").unwrap();
+    let word = |name: &str| Value::Word(name.to_string(), Scope::Local);
+    assert_eq!(values[3..], [word("a"), word("b")]);
+}
+
+// 既定の制限のままでも、巨大な値を作る入力はパニックやメモリ不足ではなくエラーで止まる
+#[test]
+fn huge_values_stop_with_an_error_under_the_default_limits() {
+    let doubling = format!("'a'{}", " dup concat".repeat(40));
+    for source in ["18446744073709551615 iota", "0 100000000000 range", "1 1e999999 range", doubling.as_str()] {
+        let mut interpreter = Interpreter::new();
+        let error = interpreter.eval(source).unwrap_err();
+        assert!(error.is_limit(), "{}: {}", source, error);
+        assert!(interpreter.stack().is_empty());
+        interpreter.eval("3 iota").unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+use lycoris::{Interpreter, Scope, Span, Trace};
+
+// ============================================================================
+// 実行トレース
+// ============================================================================
+
+fn trace(source: &str) -> Trace {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_tracing(true);
+    let _ = interpreter.eval(source);
+    interpreter.trace().unwrap().clone()
+}
+
+// (深さ, トークン, 行:列) の列
+fn outline(trace: &Trace) -> Vec<(usize, String, String)> {
+    trace.steps.iter()
+        .map(|step| (step.depth, step.token(), format!("{}:{}", step.line.unwrap_or(0), step.column.unwrap_or(0))))
+        .collect()
+}
+
+fn row(depth: usize, token: &str, location: &str) -> (usize, String, String) {
+    (depth, token.to_string(), location.to_string())
+}
+
+#[test]
+fn every_token_records_the_stack_before_and_after() {
+    let trace = trace("1 2add");
+    assert_eq!(trace.steps.len(), 3);
+
+    let add = &trace.steps[2];
+    assert_eq!(add.word, "add");
+    assert_eq!(add.scope, Some(Scope::Local));
+    assert_eq!(add.before, vec!["1", "2"]);
+    assert_eq!(add.after, vec!["3"]);
+    assert_eq!(add.span, Some(Span::new(3, 6)));
+    assert_eq!((add.line, add.column), (Some(1), Some(4)));
+    assert_eq!(add.depth, 0);
+
+    let literal = &trace.steps[0];
+    assert_eq!((literal.word.as_str(), literal.scope), ("1", None));
+    assert!(literal.before.is_empty());
+}
+
+#[test]
+fn nested_code_is_traced_with_depth_and_definition_spans() {
+    let trace = trace("[dup mul] 'sq' def\n[1 2] @sq\n[3 sq] run");
+    assert_eq!(
+        outline(&trace)[3..],
+        [
+            row(0, "[1 2]", "2:1"),
+            row(0, "@sq", "2:7"),
+            // @ が呼び出すワード自体には位置がないので @sq の位置になる
+            row(1, "sq", "2:7"),
+            row(2, "dup", "1:2"),
+            row(2, "mul", "1:6"),
+            row(1, "sq", "2:7"),
+            row(2, "dup", "1:2"),
+            row(2, "mul", "1:6"),
+            row(0, "[3 sq]", "3:1"),
+            row(0, "run", "3:8"),
+            row(1, "3", "3:2"),
+            row(1, "sq", "3:4"),
+            row(1, "dup", "1:2"),
+            row(1, "mul", "1:6"),
+        ]
+    );
+    assert_eq!(trace.steps[4].after, vec!["[1 4]"]);
+}
+
+#[test]
+fn identical_quotations_resolve_to_the_nearest_one() {
+    let trace = trace("[1] run\n[1] run");
+    assert_eq!(
+        outline(&trace),
+        [row(0, "[1]", "1:1"), row(0, "run", "1:5"), row(1, "1", "1:2"), row(0, "[1]", "2:1"), row(0, "run", "2:5"), row(1, "1", "2:2")]
+    );
+}
+
+// フレームを積むトークンの実行後は、本体が終わったときのスタック
+#[test]
+fn calls_record_the_stack_after_their_body() {
+    let trace = trace("[dup mul] 'sq' def [sq 1 add] 'sq1' def [sq] 'tail' def\n3 sq 2 sq1 [4 tail] run true [5] [6] ifelse");
+    let steps: Vec<(String, String)> = trace.steps.iter()
+        .filter(|step| step.depth == 0 && step.line == Some(2))
+        .map(|step| (step.token(), format!("{} -> {}", step.before.join(" "), step.after.join(" "))))
+        .collect();
+    let expected = [
+        ("3", " -> 3"),
+        ("sq", "3 -> 9"),
+        ("2", "9 -> 9 2"),
+        ("sq1", "9 2 -> 9 5"),
+        ("[4 tail]", "9 5 -> 9 5 [4 tail]"),
+        // 末尾呼び出しで置き換えたフレームが終わるまで待つ
+        ("run", "9 5 [4 tail] -> 9 5 16"),
+        ("true", "9 5 16 -> 9 5 16 true"),
+        ("[5]", "9 5 16 true -> 9 5 16 true [5]"),
+        ("[6]", "9 5 16 true [5] -> 9 5 16 true [5] [6]"),
+        ("ifelse", "9 5 16 true [5] [6] -> 9 5 16 5"),
+    ];
+    assert_eq!(steps, expected.map(|(token, change)| (token.to_string(), change.to_string())));
+
+    // 本体の途中で失敗した呼び出しにもエラーが残る
+    let trace = self::trace("[1 'x' add] 'bad' def bad");
+    let bad = trace.steps.iter().find(|step| step.word == "bad").unwrap();
+    assert!(bad.error.is_some());
+}
+
+#[test]
+fn errors_are_recorded_on_the_failing_steps() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_tracing(true);
+    assert!(interpreter.eval("[1 'x'] *add").is_err());
+
+    let trace = interpreter.trace().unwrap();
+    let failed: Vec<String> = trace.steps.iter().filter(|step| step.error.is_some()).map(|step| step.token()).collect();
+    assert_eq!(failed, vec!["*add", "add"]);
+
+    // 次の実行でトレースは入れ替わる
+    interpreter.eval("1").unwrap();
+    assert_eq!(interpreter.trace().unwrap().steps.len(), 1);
+}
+
+#[test]
+fn traces_export_as_json_and_as_a_table() {
+    let mut interpreter = Interpreter::new();
+    assert!(interpreter.trace().is_none());
+    assert_eq!(interpreter.get_trace_json(), "null");
+
+    interpreter.set_tracing(true);
+    interpreter.eval("[dup mul] 'sq' def\n3 sq").unwrap();
+    assert_eq!(
+        interpreter.get_trace_table(),
+        "\
+1  1:1   [dup mul]   -> [dup mul]
+2  1:11  'sq'       [dup mul] -> [dup mul] 'sq'
+3  1:16  def        [dup mul] 'sq' ->
+4  2:1   3           -> 3
+5  2:3   sq         3 -> 9
+6  1:2     dup      3 -> 3 3
+7  1:6     mul      3 3 -> 9
+"
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&interpreter.get_trace_json()).unwrap();
+    assert_eq!(json["truncated"], false);
+    assert_eq!(
+        json["steps"][6],
+        serde_json::json!({
+            "depth": 1,
+            "word": "mul",
+            "scope": "Local",
+            "before": ["3", "3"],
+            "after": ["9"],
+            "span": { "start": 5, "end": 8 },
+            "line": 1,
+            "column": 6,
+            "error": null,
+        })
+    );
+}